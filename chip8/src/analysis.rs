@@ -0,0 +1,391 @@
+// Code/data separation
+//
+// A linear sweep decodes every word, so sprite bytes placed between routines come
+// out as nonsense instructions and knock the following code out of alignment. The
+// analyzer instead follows control flow from the entry point: jumps, calls and their
+// returns, both outcomes of every skip and the base of BNNN jump tables. Only words
+// reached that way are code.
+//
+// Along each path it also tracks the value of I while it is a known constant (set by
+// ANNN or F000 NNNN). A DXYN, or a register load/store through I, with a known I
+// marks the bytes it reads as data, which is how sprites are recovered.
+//
+// A symbol map improves on both: its data regions are never decoded, the source
+// lines it knows are extra entry points and its names replace generated labels.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::Write;
+
+use crate::decode::{decode, DecodedOp};
+use crate::disasm::{build_listing, fetch, LineKind, Listing, Syntax, Targets};
+use crate::symbols::SymbolMap;
+
+/// What the analyzer found out about one byte of a ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteKind {
+    /// Never reached, neither executed nor read.
+    Unreached,
+    /// First byte of an instruction.
+    Code,
+    /// Any later byte of an instruction.
+    Operand,
+    /// Read through I, usually a sprite.
+    Data,
+}
+
+/// Result of following the control flow of a ROM.
+pub struct Analysis {
+    origin: u16,
+    bytes: Vec<u8>,
+    map: Vec<ByteKind>,
+    targets: Targets,
+    symbols: SymbolMap,
+}
+
+/// Follow the control flow of `bytes` loaded at `origin`, starting at `origin`.
+pub fn analyze(bytes: &[u8], origin: u16) -> Analysis {
+    analyze_with_symbols(bytes, origin, &SymbolMap::new())
+}
+
+/// Like [`analyze`], guided by what a symbol map knows about the ROM.
+pub fn analyze_with_symbols(bytes: &[u8], origin: u16, symbols: &SymbolMap) -> Analysis {
+    let mut analysis = Analysis {
+        origin,
+        bytes: bytes.to_vec(),
+        map: vec![ByteKind::Unreached; bytes.len()],
+        targets: Targets::default(),
+        symbols: symbols.clone(),
+    };
+    analysis.run();
+    analysis
+}
+
+impl Analysis {
+    /// Kind of the byte at `address`, `Unreached` outside of the ROM.
+    pub fn kind_at(&self, address: u16) -> ByteKind {
+        match self.offset(address) {
+            Some(offset) => self.map[offset],
+            None => ByteKind::Unreached,
+        }
+    }
+
+    /// Addresses that are jumped to or called.
+    pub fn code_targets(&self) -> &BTreeSet<u16> {
+        &self.targets.code
+    }
+
+    /// Addresses loaded into I that are not also code.
+    pub fn data_targets(&self) -> &BTreeSet<u16> {
+        &self.targets.data
+    }
+
+    /// Number of bytes of each kind, in `ByteKind` order.
+    pub fn counts(&self) -> [usize; 4] {
+        let mut counts = [0; 4];
+        for kind in &self.map {
+            counts[*kind as usize] += 1;
+        }
+        counts
+    }
+
+    /// Labeled listing, code where the flow reached and data everywhere else.
+    ///
+    /// Unreached bytes are listed eight per line, read data one byte per line.
+    pub fn listing(&self, syntax: Syntax) -> Listing {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < self.bytes.len() {
+            let address = self.address(offset);
+            let op = fetch(&self.bytes, offset).map(decode);
+            let (len, kind) = match (self.map[offset], op) {
+                (ByteKind::Code, Some(op)) => (instruction_len(op), LineKind::Code(op)),
+                (ByteKind::Data, _) => (1, LineKind::Data),
+                // Unreached bytes, and operand bytes that a jump into the middle of an
+                // instruction left behind, up to the next byte of another kind or label
+                _ => {
+                    let run = (offset..self.bytes.len())
+                        .take(8)
+                        .take_while(|next| {
+                            *next == offset
+                                || (!matches!(self.map[*next], ByteKind::Code | ByteKind::Data)
+                                    && !self.is_target(self.address(*next)))
+                        })
+                        .count();
+                    (run, LineKind::Data)
+                }
+            };
+            let len = len.min(self.bytes.len() - offset);
+            lines.push((address, &self.bytes[offset..offset + len], kind));
+            offset += len;
+        }
+        build_listing(lines, &self.targets, &self.symbols, syntax)
+    }
+
+    /// One character per byte, 64 per row: `C` code, `D` data, `.` unreached.
+    pub fn reachability_map(&self) -> String {
+        let mut out = String::new();
+        for (row, chunk) in self.map.chunks(64).enumerate() {
+            let _ = write!(out, "{:04X}  ", self.address(row * 64));
+            for kind in chunk {
+                out.push(match kind {
+                    ByteKind::Unreached => '.',
+                    ByteKind::Code | ByteKind::Operand => 'C',
+                    ByteKind::Data => 'D',
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn address(&self, offset: usize) -> u16 {
+        self.origin.wrapping_add(offset as u16)
+    }
+
+    fn offset(&self, address: u16) -> Option<usize> {
+        let offset = address.wrapping_sub(self.origin) as usize;
+        if address >= self.origin && offset < self.bytes.len() {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    fn is_target(&self, address: u16) -> bool {
+        self.targets.code.contains(&address) || self.targets.data.contains(&address)
+    }
+
+    // Work list of (address, known value of I). A state is only explored once, the
+    // value of I is part of it so a routine drawing different sprites is seen with each.
+    fn run(&mut self) {
+        for (start, len) in self.symbols.data_regions().collect::<Vec<_>>() {
+            self.mark_data(Some(start), len);
+        }
+        let mut pending = vec![(self.origin, None)];
+        let lines = (0..self.bytes.len()).map(|offset| self.address(offset));
+        let lines = lines.filter(|address| self.symbols.line(*address).is_some());
+        pending.extend(lines.map(|address| (address, None)).collect::<Vec<_>>());
+
+        let mut seen = HashSet::new();
+        while let Some((address, i)) = pending.pop() {
+            if !seen.insert((address, i)) || self.symbols.is_data(address) {
+                continue;
+            }
+            let op = match self
+                .offset(address)
+                .and_then(|offset| fetch(&self.bytes, offset))
+            {
+                Some(word) => decode(word),
+                None => continue,
+            };
+            if let DecodedOp::Invalid(_) = op {
+                continue;
+            }
+            let len = instruction_len(op);
+            let long_address = self
+                .offset(address.wrapping_add(2))
+                .and_then(|offset| fetch(&self.bytes, offset));
+            if op == DecodedOp::LdILong && long_address.is_none() {
+                continue;
+            }
+            self.mark_code(address, len);
+            let next = address.wrapping_add(len as u16);
+
+            match op {
+                DecodedOp::Sys { .. } | DecodedOp::Ret | DecodedOp::Exit => {}
+                DecodedOp::Jp { nnn } => {
+                    self.targets.code.insert(nnn);
+                    pending.push((nnn, i));
+                }
+                DecodedOp::JpV0Nnn { nnn } => {
+                    // Only the base of the table is known
+                    self.targets.code.insert(nnn);
+                    pending.push((nnn, i));
+                }
+                DecodedOp::Call { nnn } => {
+                    self.targets.code.insert(nnn);
+                    self.targets.calls.insert(nnn);
+                    pending.push((nnn, i));
+                    // The subroutine may have changed I
+                    pending.push((next, None));
+                }
+                DecodedOp::SeVxByte { .. }
+                | DecodedOp::SneVxByte { .. }
+                | DecodedOp::SeVxVy { .. }
+                | DecodedOp::SneVxVy { .. }
+                | DecodedOp::SkpVx { .. }
+                | DecodedOp::SknpVx { .. } => {
+                    pending.push((next, i));
+                    let skipped = self
+                        .offset(next)
+                        .and_then(|offset| fetch(&self.bytes, offset))
+                        .map_or(2, |word| instruction_len(decode(word)));
+                    pending.push((next.wrapping_add(skipped as u16), i));
+                }
+                DecodedOp::LdINnn { nnn } => {
+                    self.targets.data.insert(nnn);
+                    pending.push((next, Some(nnn)));
+                }
+                DecodedOp::LdILong => {
+                    let target = long_address.unwrap_or(0);
+                    self.targets.data.insert(target);
+                    pending.push((next, Some(target)));
+                }
+                DecodedOp::DrwVxVyN { n, .. } => {
+                    // DXY0 is a 16x16 SUPER-CHIP sprite, two bytes per row
+                    self.mark_data(i, if n == 0 { 32 } else { n as usize });
+                    pending.push((next, i));
+                }
+                DecodedOp::LdIVx { x } | DecodedOp::LdVxI { x } => {
+                    self.mark_data(i, x as usize + 1);
+                    // I may move depending on the load/store quirk
+                    pending.push((next, None));
+                }
+                DecodedOp::SaveVxVy { x, y } | DecodedOp::LoadVxVy { x, y } => {
+                    self.mark_data(i, x.abs_diff(y) as usize + 1);
+                    pending.push((next, i));
+                }
+                DecodedOp::LdBVx { .. } => {
+                    self.mark_data(i, 3);
+                    pending.push((next, i));
+                }
+                DecodedOp::AddIVx { .. } | DecodedOp::LdFVx { .. } | DecodedOp::LdHfVx { .. } => {
+                    pending.push((next, None));
+                }
+                _ => pending.push((next, i)),
+            }
+        }
+        let code = &self.targets.code;
+        self.targets.data = self.targets.data.difference(code).copied().collect();
+    }
+
+    fn mark_code(&mut self, address: u16, len: usize) {
+        for n in 0..len {
+            if let Some(offset) = self.offset(address.wrapping_add(n as u16)) {
+                self.map[offset] = if n == 0 {
+                    ByteKind::Code
+                } else {
+                    ByteKind::Operand
+                };
+            }
+        }
+    }
+
+    // Code wins over data, e.g. for code that reads its own instructions
+    fn mark_data(&mut self, i: Option<u16>, len: usize) {
+        let start = match i {
+            Some(start) => start,
+            None => return,
+        };
+        for n in 0..len {
+            if let Some(offset) = self.offset(start.wrapping_add(n as u16)) {
+                if self.map[offset] == ByteKind::Unreached {
+                    self.map[offset] = ByteKind::Data;
+                }
+            }
+        }
+    }
+}
+
+fn instruction_len(op: DecodedOp) -> usize {
+    match op {
+        DecodedOp::LdILong => 4,
+        _ => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, analyze_with_symbols, ByteKind};
+    use crate::disasm::{LineKind, Syntax};
+    use crate::symbols::SymbolMap;
+    use crate::test_rom::TEST_PROGRAM;
+
+    #[test]
+    fn test_sprites_between_routines() {
+        let analysis = analyze(&TEST_PROGRAM, 0x200);
+        // JP 0x24E jumps over the sprites the linear sweep would decode as code
+        assert_eq!(analysis.kind_at(0x200), ByteKind::Code);
+        assert_eq!(analysis.kind_at(0x202), ByteKind::Data);
+        assert_eq!(analysis.kind_at(0x241), ByteKind::Data);
+        assert_eq!(analysis.kind_at(0x24E), ByteKind::Code);
+        assert_eq!(analysis.kind_at(0x24F), ByteKind::Operand);
+        assert!(analysis.code_targets().contains(&0x24E));
+        assert!(analysis.data_targets().contains(&0x202));
+
+        let listing = analysis.listing(Syntax::Cowgod);
+        let sprite = &listing.lines[listing.line_at(0x202).unwrap()];
+        assert_eq!(sprite.kind, LineKind::Data);
+        assert_eq!(sprite.label.as_deref(), Some("data_202"));
+        assert_eq!(listing.lines[0].text, "JP label_24E");
+        let entry = &listing.lines[listing.line_at(0x24E).unwrap()];
+        assert_eq!(entry.text, "LD V8, #01");
+
+        let map = analysis.reachability_map();
+        assert!(map.starts_with("0200  CCDDDD"));
+        assert_eq!(map.lines().count(), (TEST_PROGRAM.len() + 63) / 64);
+    }
+
+    #[test]
+    fn test_skips_calls_and_unreached_bytes() {
+        // SE V0, 0 ; CALL 0x208 ; JP 0x204 ; (unreached) ; LD I, 0x20E ; DRW ; RET ; sprite
+        let rom = [
+            0x30, 0x00, 0x22, 0x08, 0x12, 0x04, 0xFF, 0xFF, 0xA2, 0x0E, 0xD0, 0x11, 0x00, 0xEE,
+            0x80,
+        ];
+        let analysis = analyze(&rom, 0x200);
+        assert_eq!(analysis.kind_at(0x202), ByteKind::Code);
+        assert_eq!(analysis.kind_at(0x204), ByteKind::Code);
+        assert_eq!(analysis.kind_at(0x206), ByteKind::Unreached);
+        assert_eq!(analysis.kind_at(0x20E), ByteKind::Data);
+        assert_eq!(analysis.counts(), [2, 6, 6, 1]);
+
+        let listing = analysis.listing(Syntax::Octo);
+        let texts: Vec<&str> = listing
+            .lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "if v0 != 0x00 then",
+                ":call sub_208",
+                "jump label_204",
+                "0xFF 0xFF",
+                "i := data_20E",
+                "sprite v0 v1 1",
+                "return",
+                "0x80"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_symbols_guide_the_analysis() {
+        // JP 0x204 ; (reached only through a jump table) ; LD V0, 1 ; RET ; 0x00E0 as data
+        let rom = [0x12, 0x04, 0x60, 0x01, 0x00, 0xEE, 0x00, 0xE0];
+        let symbols =
+            SymbolMap::parse("0202 table\n0202 .line 5\n0204 done\n0206 .data 2").unwrap();
+        let analysis = analyze_with_symbols(&rom, 0x200, &symbols);
+        assert_eq!(analysis.kind_at(0x202), ByteKind::Code);
+        assert_eq!(analysis.kind_at(0x206), ByteKind::Data);
+
+        let texts: Vec<String> = analysis
+            .listing(Syntax::Cowgod)
+            .lines
+            .iter()
+            .map(|line| format!("{} {}", line.label.as_deref().unwrap_or("-"), line.text))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "- JP done",
+                "table LD V0, #01",
+                "done RET",
+                "- DB #00",
+                "- DB #E0"
+            ]
+        );
+    }
+}
@@ -1,35 +1,81 @@
 use serde::{Deserialize, Serialize};
-use serde_big_array::BigArray;
 
-use super::instruction::INSTRUCTION_SET;
-use super::{Bit, Byte, Ram, Stack, Word};
+use super::decode::decode_for;
+use super::error::{Chip8Error, StepOutcome};
+use super::keypad::{KeyWait, KeyWaitMode, Keypad};
+use super::quirks::Quirks;
+use super::ram_ops::{MemoryAccess, TOTAL_RAM_SIZE};
+use super::rng::Rng;
+use super::stack_ops::STACK_SIZE;
+use super::trace::Tracer;
+use super::{Byte, Ram, Stack, Word};
 
-use std::{collections::VecDeque, default::Default};
+use std::{cell::RefCell, collections::VecDeque, default::Default};
 
-const BITMAP_HEIGHT: usize = 32;
-const BITMAP_WIDTH: usize = 64;
+pub(crate) const BITMAP_HEIGHT: usize = 32;
+pub(crate) const BITMAP_WIDTH: usize = 64;
+pub(crate) const HIRES_BITMAP_HEIGHT: usize = 64;
+pub(crate) const HIRES_BITMAP_WIDTH: usize = 128;
+// FX3A pitch that plays the audio pattern at 4000 bits per second
+pub(crate) const DEFAULT_PITCH: u8 = 64;
 
-#[derive(Deserialize, Serialize, Copy, Clone)]
-pub(crate) enum Pixel {
+/// The colour index of one pixel: one bit per XO-CHIP bitplane.
+///
+/// Plain CHIP-8 and SUPER-CHIP only ever draw on the first plane, so their pixels
+/// are either `Black` or `White`.
+#[derive(Deserialize, Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pixel {
     Black = 0,
+    /// Lit on the first plane only.
     White = 1,
+    /// Lit on the second plane only.
+    Plane2 = 2,
+    /// Lit on both planes.
+    BothPlanes = 3,
 }
 
 #[derive(Deserialize, Serialize)]
 // #[serde(default)]
 pub struct Chip8 {
     pub(crate) registers: [Byte; 16],
-    delay_timer: Byte,
-    sound_timer: Byte,
+    pub(crate) delay_timer: Byte,
+    pub(crate) sound_timer: Byte,
     pub(crate) index_register: Word, // Only 12 bits are used for adressing
     pub(crate) program_counter: Word,
     pub(crate) stack_pointer: Word, // Points to the top of the stack
-    #[serde(with = "BigArray")]
     pub(crate) ram: Ram,
     pub(crate) stack: Stack,
     pub(crate) curr_op: Word,
-    #[serde(with = "BigArray")]
-    pub(crate) bit_map: [Pixel; BITMAP_WIDTH * BITMAP_HEIGHT],
+    pub(crate) bit_map: Vec<Pixel>,
+    // SUPER-CHIP 128x64 mode, see `set_hires`
+    pub(crate) hires: bool,
+    // SUPER-CHIP RPL user flags, saved and restored by FX75/FX85
+    pub(crate) rpl: [Byte; 16],
+    pub(crate) keypad: Keypad,
+    pub(crate) key_wait: Option<KeyWait>,
+    pub(crate) key_wait_mode: KeyWaitMode,
+    pub(crate) draw_flag: bool,
+    pub(crate) quirks: Quirks,
+    // Set by DXYN under the display wait quirk, cleared by the next timer tick
+    pub(crate) vblank_wait: bool,
+    pub(crate) fault: Option<Chip8Error>,
+    // Set by the SUPER-CHIP 00FD exit instruction
+    pub(crate) exited: bool,
+    // XO-CHIP mode: 64 KiB of RAM, see `set_xo_chip`
+    pub(crate) xo_chip: bool,
+    // Bitplanes that drawing, clearing and scrolling act on (FN01), bit 0 is the first plane
+    pub(crate) planes: u8,
+    // XO-CHIP 1-bit audio pattern (F002) and its playback pitch (FX3A)
+    pub(crate) audio_pattern: [Byte; 16],
+    pub(crate) pitch: Byte,
+    // Random source for CXKK, see `with_seed`
+    pub(crate) rng: Rng,
+    // RAM accesses of the last instruction, only kept while enabled, see `take_memory_log`
+    #[serde(skip)]
+    pub(crate) memory_log: Option<RefCell<Vec<MemoryAccess>>>,
+    // Sees every executed instruction, see `set_tracer`
+    #[serde(skip)]
+    pub(crate) tracer: Option<Tracer>,
 }
 
 impl Chip8 {
@@ -43,23 +89,89 @@ impl Chip8 {
     //     self.
     // }
 
-    pub fn emulate_cycle(&mut self) {
+    // Execute a single instruction
+    // An error halts the machine: every later call returns the same error.
+    pub fn emulate_cycle(&mut self) -> Result<StepOutcome, Chip8Error> {
+        if let Some(fault) = self.fault {
+            return Err(fault);
+        }
+        // FX0A blocks execution until press/release delivers a key
+        if self.waiting_for_key() {
+            return Ok(StepOutcome::WaitingForKey);
+        }
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+        if self.vblank_wait {
+            return Ok(StepOutcome::WaitingForVblank);
+        }
+        // The log only ever holds the accesses of the last instruction
+        if let Some(log) = &self.memory_log {
+            log.borrow_mut().clear();
+        }
+        let pc = self.program_counter;
+        let result = self.step();
+        match result {
+            Ok(()) if self.tracer.is_some() => self.trace(pc),
+            Ok(()) => {}
+            Err(fault) => self.fault = Some(fault),
+        }
+        result.map(|_| StepOutcome::Executed)
+    }
+
+    fn step(&mut self) -> Result<(), Chip8Error> {
         // Fetch Opcode from MEMORY[PC] ( |OpCode| = 1 WORD )
-        self.curr_op = self.read_word(self.program_counter as usize);
-        self.program_counter += 2;
-        let func = (self.curr_op & 0xF000) >> 12;
-        let _idx = 0;
+        self.curr_op = self.fetch_word(self.program_counter as usize)?;
+        self.program_counter = self.program_counter.wrapping_add(2);
         // Decode Opcode and Execute opcode
-        INSTRUCTION_SET[func as usize](self);
-        // Update Timers
+        let super_chip = self.quirks.super_chip_instructions;
+        self.execute(decode_for(self.curr_op, super_chip, self.xo_chip))
+        // Timers are updated separately at 60 Hz, see `tick_timers` and `run_frame`
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // Address of the next instruction to execute
+    pub fn program_counter(&self) -> Word {
+        self.program_counter
+    }
+
+    pub fn registers(&self) -> &[Byte; 16] {
+        &self.registers
+    }
+
+    pub fn index_register(&self) -> Word {
+        self.index_register
+    }
+
+    // The error that halted the machine, if any
+    pub fn fault(&self) -> Option<Chip8Error> {
+        self.fault
+    }
+
+    // True once the program has run 00FD, nothing executes after that
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    // Address of the instruction being executed
+    // Only valid until the handler moves the program counter.
+    pub(crate) fn op_address(&self) -> Word {
+        self.program_counter.wrapping_sub(2)
     }
 }
 
 impl Default for Chip8 {
     fn default() -> Self {
         Self {
-            ram: [0u8; 4096],
-            stack: VecDeque::with_capacity(4096),
+            ram: vec![0u8; TOTAL_RAM_SIZE],
+            stack: VecDeque::with_capacity(STACK_SIZE),
             registers: [0u8; 16],
             delay_timer: 0,
             sound_timer: 0,
@@ -67,7 +179,24 @@ impl Default for Chip8 {
             program_counter: 0,
             stack_pointer: 0,
             curr_op: 0x0000,
-            bit_map: [Pixel::Black; BITMAP_WIDTH * BITMAP_HEIGHT],
+            bit_map: vec![Pixel::Black; BITMAP_WIDTH * BITMAP_HEIGHT],
+            hires: false,
+            rpl: [0u8; 16],
+            keypad: Keypad::new(),
+            key_wait: None,
+            key_wait_mode: KeyWaitMode::default(),
+            draw_flag: false,
+            quirks: Quirks::default(),
+            vblank_wait: false,
+            fault: None,
+            exited: false,
+            xo_chip: false,
+            planes: 1,
+            audio_pattern: [0u8; 16],
+            pitch: DEFAULT_PITCH,
+            rng: Rng::default(),
+            memory_log: None,
+            tracer: None,
         }
     }
 }
@@ -79,6 +208,7 @@ pub fn new_annn(i: &mut u16, nib: u16) {
 #[cfg(test)]
 mod tests {
     use super::Chip8;
+    use crate::{Chip8Error, StepOutcome};
     #[test]
     fn test_placeholder() {
         let result = 2 + 2;
@@ -89,8 +219,8 @@ mod tests {
     fn test_jp_opcode() {
         let mut chip: Chip8 = Chip8::new();
         let program = &[0x12, 0xF0];
-        chip.load_program(program);
-        chip.emulate_cycle();
+        chip.load_program(program).unwrap();
+        chip.emulate_cycle().unwrap();
         assert_eq!(chip.program_counter, 0x2F0)
     }
 
@@ -98,8 +228,8 @@ mod tests {
     fn test_annn_opcode() {
         let mut chip: Chip8 = Chip8::new();
         let program = &[0xA2, 0xF0];
-        chip.load_program(program);
-        chip.emulate_cycle();
+        chip.load_program(program).unwrap();
+        chip.emulate_cycle().unwrap();
         assert_eq!(chip.program_counter, (program.len() + 0x0200) as u16);
         assert_eq!(chip.index_register, 0x02F0);
     }
@@ -108,8 +238,8 @@ mod tests {
     fn test_bnnn_opcode() {
         let mut chip: Chip8 = Chip8::new();
         let program = &[0xB2, 0xF0];
-        chip.load_program(program);
-        chip.emulate_cycle();
+        chip.load_program(program).unwrap();
+        chip.emulate_cycle().unwrap();
 
         assert_eq!(chip.program_counter, 0x02F0);
     }
@@ -121,8 +251,8 @@ mod tests {
         // Load a byte into Vx and then JP to PC + Vx
         let program = &[0x60, 0xF0, 0xB2, 0xF0];
         chip.initialize_ram();
-        chip.load_program(program);
-        chip.emulate_cycle();
+        chip.load_program(program).unwrap();
+        chip.emulate_cycle().unwrap();
         //chip.dump_to_file("two_opcode_test.txt", 8);
         assert_eq!(chip.registers[0], 0xF0);
 
@@ -130,4 +260,24 @@ mod tests {
         // chip.emulate_cycle();
         // assert_eq!(chip.program_counter, 0xF0 + 0x2F0);
     }
+
+    #[test]
+    fn test_fault_halts_machine() {
+        let mut chip = Chip8::new();
+        // RET ; LD V0, 0x01
+        chip.load_program(&[0x00, 0xEE, 0x60, 0x01]).unwrap();
+        let fault = Chip8Error::StackUnderflow { pc: 0x200 };
+        assert_eq!(chip.emulate_cycle(), Err(fault));
+        assert_eq!(chip.emulate_cycle(), Err(fault));
+        assert_eq!(chip.fault(), Some(fault));
+        assert_eq!(chip.registers[0], 0x00);
+    }
+
+    #[test]
+    fn test_waiting_outcome() {
+        let mut chip = Chip8::new();
+        chip.load_program(&[0xF0, 0x0A]).unwrap();
+        assert_eq!(chip.emulate_cycle(), Ok(StepOutcome::Executed));
+        assert_eq!(chip.emulate_cycle(), Ok(StepOutcome::WaitingForKey));
+    }
 }
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Rate at which the delay and sound timers count down, and the length of one frame.
+pub const TIMER_HZ: u32 = 60;
+
+/// How many instructions the CPU executes per second of emulated time.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstructionRate {
+    PerSecond(u32),
+    /// As many instructions as the frontend can fit in a frame.
+    Unlimited,
+}
+
+impl InstructionRate {
+    /// Instructions to run between two timer ticks, `None` when unlimited.
+    pub fn cycles_per_frame(&self) -> Option<usize> {
+        match self {
+            InstructionRate::PerSecond(ips) => Some((*ips / TIMER_HZ).max(1) as usize),
+            InstructionRate::Unlimited => None,
+        }
+    }
+}
+
+impl Default for InstructionRate {
+    fn default() -> Self {
+        InstructionRate::PerSecond(700)
+    }
+}
+
+/// Turns elapsed wall-clock time into a whole number of 60 Hz frames.
+///
+/// The remainder is carried over between calls so the timers tick at exactly
+/// 60 Hz no matter how often or how irregularly the frontend polls.
+#[derive(Default, Clone, Debug)]
+pub struct Clock {
+    pending: Duration,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn frame_duration() -> Duration {
+        Duration::from_secs(1) / TIMER_HZ
+    }
+
+    /// Add `elapsed` to the clock and return how many frames are now due.
+    pub fn frames_due(&mut self, elapsed: Duration) -> u32 {
+        self.pending += elapsed;
+        let frame = Self::frame_duration();
+        let mut frames = 0;
+        while self.pending >= frame {
+            self.pending -= frame;
+            frames += 1;
+        }
+        frames
+    }
+
+    pub fn reset(&mut self) {
+        self.pending = Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, InstructionRate};
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_cycles_per_frame() {
+        assert_eq!(InstructionRate::PerSecond(600).cycles_per_frame(), Some(10));
+        assert_eq!(InstructionRate::PerSecond(30).cycles_per_frame(), Some(1));
+        assert_eq!(InstructionRate::Unlimited.cycles_per_frame(), None);
+    }
+
+    #[test]
+    pub fn test_clock_carries_remainder() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.frames_due(Duration::from_millis(10)), 0);
+        assert_eq!(clock.frames_due(Duration::from_millis(10)), 1);
+        assert_eq!(clock.frames_due(Duration::from_secs(1)), 60);
+    }
+}
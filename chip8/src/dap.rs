@@ -0,0 +1,719 @@
+// Debug Adapter Protocol
+//
+// Lets editors debug CHIP-8 programs: `launch` with `program` (the ROM), and optionally
+// `symbols` (defaults to the `.sym` next to the ROM), `source` (the `.8o` the symbol line
+// map refers to, defaults to the one next to the ROM), `platform` (`vip`, `chip48`, `schip`
+// or `xochip` as for `Quirks::by_name`, `vip` by default) and `stopOnEntry`.
+//
+// Breakpoints can be set on source lines, through the `.line` entries of the symbol map,
+// or on addresses with `setInstructionBreakpoints`. Both accept conditions in the
+// expression language of `Expr`, which `evaluate` also uses. There is a single thread,
+// and the variables view has the registers, the timers and the call stack.
+//
+// Messages are read on a separate thread so `pause` arrives while the program runs.
+
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Instant;
+
+use crate::chip8::Chip8;
+use crate::clock::{Clock, InstructionRate};
+use crate::debugger::{Debugger, Register, StopReason};
+use crate::expr::Expr;
+use crate::quirks::Quirks;
+use crate::symbols::SymbolMap;
+
+const THREAD_ID: u64 = 1;
+// Largest request body accepted, requests are small JSON objects
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+// `variablesReference` of each scope
+const REGISTERS: u64 = 1;
+const TIMERS: u64 = 2;
+const STACK: u64 = 3;
+
+/// A debug adapter for one client, see [`DapServer::serve`].
+pub struct DapServer {
+    chip: Chip8,
+    debugger: Debugger,
+    symbols: SymbolMap,
+    // The source file whose lines the symbol map refers to
+    source: Option<String>,
+    cycles_per_frame: usize,
+    stop_on_entry: bool,
+    // Set by each kind of `set*Breakpoints`, which replaces its own breakpoints only
+    line_breakpoints: Vec<u16>,
+    instruction_breakpoints: Vec<u16>,
+    running: bool,
+    seq: u64,
+}
+
+impl Default for DapServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DapServer {
+    pub fn new() -> Self {
+        Self {
+            chip: Chip8::new(),
+            debugger: Debugger::new(),
+            symbols: SymbolMap::new(),
+            source: None,
+            cycles_per_frame: InstructionRate::default()
+                .cycles_per_frame()
+                .expect("the default rate is fixed"),
+            stop_on_entry: false,
+            line_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            running: false,
+            seq: 0,
+        }
+    }
+
+    /// Listen on `port` of the loopback interface only, 0 picks a free port.
+    pub fn listen(port: u16) -> io::Result<TcpListener> {
+        TcpListener::bind(("127.0.0.1", port))
+    }
+
+    /// Answer requests from `input` on `output` until the client disconnects.
+    ///
+    /// Use standard input and output, or both halves of a `TcpStream`.
+    pub fn serve<R, W>(&mut self, input: R, mut output: W) -> io::Result<()>
+    where
+        R: Read + Send + 'static,
+        W: Write,
+    {
+        let requests = spawn_reader(input);
+        loop {
+            // Block while stopped, only look for requests between frames while running
+            let request = if self.running {
+                match requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return Ok(()),
+                }
+            };
+            match request {
+                Some(request) => {
+                    if !self.handle(&request, &mut output)? {
+                        return Ok(());
+                    }
+                }
+                None => {
+                    let start = Instant::now();
+                    self.run_frame(&mut output)?;
+                    if let Some(rest) = Clock::frame_duration().checked_sub(start.elapsed()) {
+                        thread::sleep(rest);
+                    }
+                }
+            }
+        }
+    }
+
+    // Returns false once the client asked to disconnect
+    fn handle(&mut self, request: &Value, output: &mut impl Write) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or("");
+        let args = &request["arguments"];
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsSetVariable": true,
+                "supportsEvaluateForHovers": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => Ok(self.set_line_breakpoints(args)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => Ok(json!({})),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK, "expensive": false },
+            ] })),
+            "variables" => Ok(self.variables(args["variablesReference"].as_u64().unwrap_or(0))),
+            "setVariable" => self.set_variable(args),
+            "readMemory" => self.read_memory(args),
+            "evaluate" => self.evaluate(args),
+            "continue" => {
+                self.debugger.cancel_step();
+                self.running = true;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" | "stepIn" | "stepOut" if self.chip.fault().is_some() => {
+                Err("the machine has halted".to_string())
+            }
+            "next" => {
+                self.debugger.step_over(&self.chip);
+                self.running = true;
+                Ok(json!({}))
+            }
+            "stepIn" => self
+                .debugger
+                .step_into(&mut self.chip)
+                .map(|_| json!({}))
+                .map_err(|err| err.to_string()),
+            "stepOut" => {
+                self.debugger.step_out(&self.chip);
+                self.running = true;
+                Ok(json!({}))
+            }
+            "pause" => {
+                self.debugger.cancel_step();
+                self.running = false;
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                self.respond(output, request, Ok(json!({})))?;
+                return Ok(false);
+            }
+            _ => Err(format!("unsupported request {}", command)),
+        };
+        let success = result.is_ok();
+        self.respond(output, request, result)?;
+        if !success {
+            return Ok(true);
+        }
+
+        // Events that follow the response
+        match command {
+            "initialize" => self.event(output, "initialized", json!({}))?,
+            "configurationDone" if self.stop_on_entry => self.stopped(output, "entry", None)?,
+            "configurationDone" => self.running = true,
+            "stepIn" => self.stopped(output, "step", None)?,
+            "pause" => self.stopped(output, "pause", None)?,
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    // One 60 Hz frame, with the events for whatever stopped it
+    fn run_frame(&mut self, output: &mut impl Write) -> io::Result<()> {
+        match self
+            .debugger
+            .run_frame(&mut self.chip, self.cycles_per_frame)
+        {
+            Ok(Some(stop)) => {
+                self.running = false;
+                let reason = match stop {
+                    StopReason::Breakpoint(_) => "breakpoint",
+                    StopReason::Memory(_) | StopReason::Register { .. } => "data breakpoint",
+                    StopReason::Step => "step",
+                };
+                self.stopped(output, reason, Some(stop.to_string()))?;
+            }
+            Ok(None) if self.chip.exited() => {
+                self.running = false;
+                self.event(output, "exited", json!({ "exitCode": 0 }))?;
+                self.event(output, "terminated", json!({}))?;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                self.running = false;
+                self.stopped(output, "exception", Some(err.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"]
+            .as_str()
+            .ok_or("launch needs the ROM as `program`")?;
+        let platform = args["platform"].as_str().unwrap_or("vip");
+        let quirks =
+            Quirks::by_name(platform).ok_or_else(|| format!("unknown platform {}", platform))?;
+        let rom = std::fs::read(program).map_err(|err| format!("{}: {}", program, err))?;
+        let mut chip = Chip8::new();
+        chip.set_quirks(quirks);
+        chip.set_xo_chip(platform == "xochip");
+        chip.initialize_ram();
+        chip.load_program(&rom)
+            .map_err(|err| format!("{}: {}", program, err))?;
+        self.chip = chip;
+
+        let sibling = |extension: &str| {
+            let path = Path::new(program).with_extension(extension);
+            match path.is_file() {
+                true => Some(path.to_string_lossy().into_owned()),
+                false => None,
+            }
+        };
+        let symbols = args["symbols"]
+            .as_str()
+            .map(String::from)
+            .or_else(|| sibling("sym"));
+        self.symbols = match symbols {
+            Some(path) => {
+                let text =
+                    std::fs::read_to_string(&path).map_err(|err| format!("{}: {}", path, err))?;
+                SymbolMap::parse(&text).map_err(|err| format!("{}: {}", path, err))?
+            }
+            None => SymbolMap::new(),
+        };
+        self.source = args["source"]
+            .as_str()
+            .map(String::from)
+            .or_else(|| sibling("8o"));
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(json!({}))
+    }
+
+    fn set_line_breakpoints(&mut self, args: &Value) -> Value {
+        for address in self.line_breakpoints.drain(..) {
+            self.debugger.remove_breakpoint(address);
+        }
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let breakpoints: Vec<Value> = requested
+            .iter()
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or(0) as usize;
+                match self.symbols.line_address(line) {
+                    Some(address) => {
+                        let result = self.add_breakpoint(address, &breakpoint["condition"]);
+                        if result["verified"] == true {
+                            self.line_breakpoints.push(address);
+                        }
+                        result
+                    }
+                    None => json!({
+                        "verified": false,
+                        "line": line,
+                        "message": "no code on this line",
+                    }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Value {
+        for address in self.instruction_breakpoints.drain(..) {
+            self.debugger.remove_breakpoint(address);
+        }
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let breakpoints: Vec<Value> = requested
+            .iter()
+            .map(|breakpoint| {
+                let reference = breakpoint["instructionReference"].as_str().unwrap_or("");
+                let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+                let address = self.symbols.resolve(reference).map(|address| {
+                    (address as i64)
+                        .checked_add(offset)
+                        .and_then(|address| u16::try_from(address).ok())
+                });
+                match address {
+                    Some(Some(address)) => {
+                        let result = self.add_breakpoint(address, &breakpoint["condition"]);
+                        if result["verified"] == true {
+                            self.instruction_breakpoints.push(address);
+                        }
+                        result
+                    }
+                    Some(None) => json!({ "verified": false, "message": "address out of range" }),
+                    None => json!({ "verified": false, "message": "unknown address" }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    // Conditions are checked now, a bad one leaves the breakpoint unverified
+    fn add_breakpoint(&mut self, address: u16, condition: &Value) -> Value {
+        let mut result = json!({
+            "verified": true,
+            "instructionReference": format!("{:#06X}", address),
+        });
+        if let Some(line) = self.symbols.line(address) {
+            result["line"] = json!(line);
+        }
+        match condition.as_str().filter(|text| !text.trim().is_empty()) {
+            Some(text) => match Expr::parse_with_symbols(text, &self.symbols) {
+                Ok(condition) => self.debugger.add_conditional_breakpoint(address, condition),
+                Err(err) => {
+                    result["verified"] = json!(false);
+                    result["message"] = json!(format!("condition: {}", err));
+                }
+            },
+            None => self.debugger.add_breakpoint(address),
+        }
+        result
+    }
+
+    fn stack_trace(&self) -> Value {
+        // Innermost first: the program counter, then every CALL still waiting for its RET
+        let pc = self.chip.program_counter();
+        let callers = self.chip.call_stack().rev().map(|ret| ret.wrapping_sub(2));
+        let frames: Vec<Value> = std::iter::once(pc)
+            .chain(callers)
+            .enumerate()
+            .map(|(id, address)| {
+                let mut frame = json!({
+                    "id": id,
+                    "name": self.symbols.describe(address),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("{:#06X}", address),
+                });
+                if let (Some(line), Some(source)) = (self.symbols.line(address), &self.source) {
+                    frame["line"] = json!(line);
+                    frame["column"] = json!(1);
+                    frame["source"] = json!({ "path": source });
+                }
+                frame
+            })
+            .collect();
+        let total = frames.len();
+        json!({ "stackFrames": frames, "totalFrames": total })
+    }
+
+    fn variables(&self, reference: u64) -> Value {
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+        let registers = |list: &[Register]| -> Vec<Value> {
+            list.iter()
+                .map(|register| {
+                    let value = register.read(&self.chip);
+                    variable(register.to_string(), format!("{:#04X}", value))
+                })
+                .collect()
+        };
+        let variables = match reference {
+            REGISTERS => {
+                let mut list: Vec<Register> = (0..16).map(Register::V).collect();
+                list.extend([Register::I, Register::Pc, Register::Sp]);
+                registers(&list)
+            }
+            TIMERS => registers(&[Register::Dt, Register::St]),
+            STACK => self
+                .chip
+                .call_stack()
+                .enumerate()
+                .map(|(depth, ret)| variable(format!("[{}]", depth), self.symbols.describe(ret)))
+                .collect(),
+            _ => Vec::new(),
+        };
+        json!({ "variables": variables })
+    }
+
+    fn set_variable(&mut self, args: &Value) -> Result<Value, String> {
+        let name = args["name"].as_str().unwrap_or("");
+        let register = Register::parse(name)
+            .filter(|register| *register != Register::Sp)
+            .ok_or_else(|| format!("{} cannot be changed", name))?;
+        let text = args["value"].as_str().unwrap_or("");
+        let value = Expr::parse_with_symbols(text, &self.symbols)
+            .map_err(|err| err.to_string())?
+            .evaluate(&self.chip, 0);
+        register.write(&mut self.chip, value as u16);
+        Ok(json!({ "value": format!("{:#04X}", register.read(&self.chip)) }))
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let reference = args["memoryReference"].as_str().unwrap_or("");
+        let start = self
+            .symbols
+            .resolve(reference)
+            .ok_or_else(|| format!("unknown address {}", reference))? as i64;
+        let start = start.saturating_add(args["offset"].as_i64().unwrap_or(0));
+        let count = args["count"].as_u64().unwrap_or(0) as usize;
+        let start = start.clamp(0, self.chip.total_ram() as i64) as usize;
+        let end = start.saturating_add(count).min(self.chip.total_ram());
+        Ok(json!({
+            "address": format!("{:#06X}", start),
+            "data": base64(&self.chip.ram[start..end]),
+            "unreadableBytes": count - (end - start),
+        }))
+    }
+
+    fn evaluate(&self, args: &Value) -> Result<Value, String> {
+        let text = args["expression"].as_str().unwrap_or("");
+        let expr = Expr::parse_with_symbols(text, &self.symbols).map_err(|err| err.to_string())?;
+        let value = expr.evaluate(&self.chip, 0);
+        Ok(json!({
+            "result": format!("{} ({:#X})", value, value),
+            "variablesReference": 0,
+        }))
+    }
+
+    fn stopped(
+        &mut self,
+        output: &mut impl Write,
+        reason: &str,
+        text: Option<String>,
+    ) -> io::Result<()> {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.event(output, "stopped", body)
+    }
+
+    fn respond(
+        &mut self,
+        output: &mut impl Write,
+        request: &Value,
+        result: Result<Value, String>,
+    ) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(output, response)
+    }
+
+    fn event(&mut self, output: &mut impl Write, event: &str, body: Value) -> io::Result<()> {
+        self.send(
+            output,
+            json!({ "type": "event", "event": event, "body": body }),
+        )
+    }
+
+    fn send(&mut self, output: &mut impl Write, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        output.flush()
+    }
+}
+
+// Parse `Content-Length` framed messages until the input ends or is malformed
+fn spawn_reader<R: Read + Send + 'static>(input: R) -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        while let Some(message) = read_message(&mut input) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    // A larger length is refused before anything is allocated for it
+    let mut body = vec![0u8; length.filter(|length| *length <= MAX_MESSAGE_SIZE)?];
+    input.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (index, byte)| {
+            value | ((*byte as u32) << (16 - 8 * index))
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                out.push(ALPHABET[((value >> (18 - 6 * index)) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, read_message, DapServer};
+    use crate::SymbolMap;
+    use serde_json::{json, Value};
+
+    // 200: CALL 206
+    // 202: JP 202
+    // 206: ADD V0, 01
+    // 208: RET
+    const PROGRAM: [u8; 10] = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x70, 0x01, 0x00, 0xEE];
+
+    fn server() -> DapServer {
+        let mut server = DapServer::new();
+        server.chip.load_program(&PROGRAM).unwrap();
+        server.symbols = SymbolMap::parse("0206 add\n0206 .line 7\n").unwrap();
+        server.source = Some("game.8o".to_string());
+        server
+    }
+
+    // Send a request, returns every message written in response
+    fn request(server: &mut DapServer, command: &str, arguments: Value) -> Vec<Value> {
+        let mut output = Vec::new();
+        let request =
+            json!({ "seq": 1, "type": "request", "command": command, "arguments": arguments });
+        server.handle(&request, &mut output).unwrap();
+        messages(&output)
+    }
+
+    fn messages(mut output: &[u8]) -> Vec<Value> {
+        std::iter::from_fn(|| read_message(&mut output)).collect()
+    }
+
+    #[test]
+    fn test_line_breakpoint_and_stack() {
+        let mut server = server();
+        let set = request(
+            &mut server,
+            "setBreakpoints",
+            json!({ "source": { "path": "game.8o" }, "breakpoints": [{ "line": 7 }, { "line": 3 }] }),
+        );
+        let breakpoints = &set[0]["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[1]["verified"], false);
+
+        request(&mut server, "configurationDone", json!({}));
+        let mut output = Vec::new();
+        server.run_frame(&mut output).unwrap();
+        let stopped = messages(&output);
+        assert_eq!(stopped[0]["event"], "stopped");
+        assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+
+        let trace = request(&mut server, "stackTrace", json!({ "threadId": 1 }));
+        let frames = &trace[0]["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "add");
+        assert_eq!(frames[0]["line"], 7);
+        assert_eq!(frames[0]["source"]["path"], "game.8o");
+        assert_eq!(frames[1]["instructionPointerReference"], "0x0200");
+
+        let stack = request(&mut server, "variables", json!({ "variablesReference": 3 }));
+        assert_eq!(stack[0]["body"]["variables"][0]["value"], "0x202");
+    }
+
+    #[test]
+    fn test_stepping_and_memory() {
+        let mut server = server();
+        let step = request(&mut server, "stepIn", json!({ "threadId": 1 }));
+        assert_eq!(step[0]["success"], true);
+        assert_eq!(step[1]["body"]["reason"], "step");
+        assert_eq!(server.chip.program_counter(), 0x206);
+
+        request(&mut server, "stepOut", json!({ "threadId": 1 }));
+        let mut output = Vec::new();
+        server.run_frame(&mut output).unwrap();
+        assert_eq!(messages(&output)[0]["body"]["reason"], "step");
+        assert_eq!(server.chip.program_counter(), 0x202);
+
+        let registers = request(&mut server, "variables", json!({ "variablesReference": 1 }));
+        assert_eq!(registers[0]["body"]["variables"][0]["value"], "0x01");
+
+        let memory = request(
+            &mut server,
+            "readMemory",
+            json!({ "memoryReference": "0x200", "count": 4 }),
+        );
+        assert_eq!(memory[0]["body"]["data"], base64(&PROGRAM[..4]));
+        let huge = request(
+            &mut server,
+            "readMemory",
+            json!({ "memoryReference": "0x200", "offset": i64::MAX, "count": u64::MAX }),
+        );
+        assert_eq!(huge[0]["body"]["data"], "");
+        let bad = request(&mut server, "evaluate", json!({ "expression": "V0 ==" }));
+        assert_eq!(bad[0]["success"], false);
+        let good = request(
+            &mut server,
+            "evaluate",
+            json!({ "expression": "[add] + V0" }),
+        );
+        assert_eq!(good[0]["body"]["result"], "113 (0x71)");
+    }
+
+    #[test]
+    fn test_instruction_breakpoint_offsets() {
+        let mut server = server();
+        let set = request(
+            &mut server,
+            "setInstructionBreakpoints",
+            json!({ "breakpoints": [
+                { "instructionReference": "0x200", "offset": 4 },
+                { "instructionReference": "0x200", "offset": -0x201 },
+                { "instructionReference": "0x200", "offset": 0xFE00 },
+                { "instructionReference": "0x200", "offset": i64::MAX },
+            ] }),
+        );
+        let breakpoints = &set[0]["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["instructionReference"], "0x0204");
+        for breakpoint in breakpoints.as_array().unwrap()[1..].iter() {
+            assert_eq!(breakpoint["verified"], false);
+            assert_eq!(breakpoint["message"], "address out of range");
+        }
+    }
+
+    #[test]
+    fn test_launch_platform() {
+        // HIGH ; JP 202
+        let path = std::env::temp_dir().join("chip8_dap_platform.ch8");
+        std::fs::write(&path, [0x00, 0xFF, 0x12, 0x02]).unwrap();
+        let program = path.to_string_lossy();
+        let mut server = DapServer::new();
+        let launch = request(
+            &mut server,
+            "launch",
+            json!({ "program": program, "platform": "schip" }),
+        );
+        assert_eq!(launch[0]["success"], true);
+        server.chip.emulate_cycle().unwrap();
+        assert!(server.chip.is_hires());
+
+        let xo_chip = json!({ "program": program, "platform": "xochip" });
+        request(&mut server, "launch", xo_chip);
+        assert!(server.chip.is_xo_chip());
+        let unknown = json!({ "program": program, "platform": "pdp-8" });
+        assert_eq!(request(&mut server, "launch", unknown)[0]["success"], false);
+    }
+
+    #[test]
+    fn test_message_size_limit() {
+        let message = |length: usize| format!("Content-Length: {}\r\n\r\n{{}}", length);
+        assert_eq!(read_message(&mut message(2).as_bytes()), Some(json!({})));
+        let oversized = message(usize::MAX);
+        assert_eq!(read_message(&mut oversized.as_bytes()), None);
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+}
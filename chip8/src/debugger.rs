@@ -0,0 +1,565 @@
+// Debugger
+//
+// Breakpoints, watchpoints and stepping on top of `Chip8`. Like `MovieRecorder` the
+// debugger does not own the machine: drive it through `Debugger::run_frame` instead of
+// `Chip8::run_frame` and stop calling it once it reports a `StopReason`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::chip8::Chip8;
+use crate::error::{Chip8Error, StepOutcome};
+use crate::expr::Expr;
+use crate::ram_ops::MemoryAccess;
+
+/// A register a watchpoint can follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Register {
+    V(u8),
+    I,
+    /// Delay timer.
+    Dt,
+    /// Sound timer.
+    St,
+    Pc,
+    /// Call stack depth.
+    Sp,
+}
+
+impl Register {
+    /// `V0`..`VF`, `I`, `DT`, `ST`, `PC` or `SP`, in any case.
+    pub fn parse(text: &str) -> Option<Self> {
+        let upper = text.trim().to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => Some(Register::I),
+            "DT" => Some(Register::Dt),
+            "ST" => Some(Register::St),
+            "PC" => Some(Register::Pc),
+            "SP" => Some(Register::Sp),
+            _ => {
+                let digit = upper.strip_prefix('V')?;
+                match u8::from_str_radix(digit, 16) {
+                    Ok(x) if digit.len() == 1 => Some(Register::V(x)),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    pub fn read(self, chip: &Chip8) -> u16 {
+        match self {
+            Register::V(x) => chip.registers[(x & 0xF) as usize] as u16,
+            Register::I => chip.index_register,
+            Register::Dt => chip.delay_timer as u16,
+            Register::St => chip.sound_timer as u16,
+            Register::Pc => chip.program_counter,
+            Register::Sp => chip.stack.len() as u16,
+        }
+    }
+
+    /// Set the register, registers narrower than 16 bits keep the low bits.
+    ///
+    /// `SP` follows the call stack and cannot be written.
+    pub fn write(self, chip: &mut Chip8, value: u16) {
+        match self {
+            Register::V(x) => chip.registers[(x & 0xF) as usize] = value as u8,
+            Register::I => chip.index_register = value,
+            Register::Dt => chip.delay_timer = value as u8,
+            Register::St => chip.sound_timer = value as u8,
+            Register::Pc => chip.program_counter = value,
+            Register::Sp => {}
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Dt => write!(f, "DT"),
+            Register::St => write!(f, "ST"),
+            Register::Pc => write!(f, "PC"),
+            Register::Sp => write!(f, "SP"),
+        }
+    }
+}
+
+/// Which accesses to a RAM byte stop the machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(self, access: &MemoryAccess) -> bool {
+        match self {
+            WatchKind::Read => !access.write,
+            WatchKind::Write => access.write,
+            WatchKind::ReadWrite => true,
+        }
+    }
+}
+
+/// Why [`Debugger::run_frame`] or [`Debugger::step_into`] stopped the machine.
+///
+/// The program counter is left on the next instruction to execute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The next instruction has a breakpoint.
+    Breakpoint(u16),
+    /// The last instruction touched a watched RAM byte.
+    Memory(MemoryAccess),
+    /// The last instruction changed a watched register.
+    Register {
+        register: Register,
+        old: u16,
+        new: u16,
+    },
+    /// A step or run to cursor finished.
+    Step,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Breakpoint(address) => write!(f, "breakpoint at {:#05X}", address),
+            StopReason::Memory(access) => write!(
+                f,
+                "{} {:#04X} at {:#05X}",
+                if access.write { "wrote" } else { "read" },
+                access.value,
+                access.address
+            ),
+            StopReason::Register { register, old, new } => {
+                write!(f, "{} changed from {:#X} to {:#X}", register, old, new)
+            }
+            StopReason::Step => write!(f, "step"),
+        }
+    }
+}
+
+// Where a step over, step out or run to cursor ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    // Once the call stack is at most this deep
+    Depth(usize),
+    // Once the program counter gets there
+    Address(u16),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Breakpoint {
+    // Only stop when this holds, `hitcount` counts every arrival at the address
+    condition: Option<Expr>,
+    hits: u64,
+}
+
+/// PC breakpoints, RAM and register watchpoints, watch expressions and stepping.
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    breakpoints: BTreeMap<u16, Breakpoint>,
+    memory: BTreeMap<u16, WatchKind>,
+    // Watched registers and their value after the last instruction
+    registers: BTreeMap<Register, u16>,
+    watches: Vec<Expr>,
+    target: Option<Target>,
+    // Program counter of the last stop, its breakpoint does not stop the machine again
+    stopped_at: Option<u16>,
+    // Cycles left of a frame a stop interrupted
+    remaining: Option<usize>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address, Breakpoint::default());
+    }
+
+    /// A breakpoint that only stops when `condition` is true, see [`Expr`].
+    pub fn add_conditional_breakpoint(&mut self, address: u16, condition: Expr) {
+        self.breakpoints.insert(
+            address,
+            Breakpoint {
+                condition: Some(condition),
+                hits: 0,
+            },
+        );
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    // Returns true when the breakpoint is now set
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        if self.breakpoints.remove(&address).is_none() {
+            self.add_breakpoint(address);
+        }
+        self.breakpoints.contains_key(&address)
+    }
+
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains_key(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    pub fn condition(&self, address: u16) -> Option<&Expr> {
+        self.breakpoints.get(&address)?.condition.as_ref()
+    }
+
+    /// Times the program reached the breakpoint at `address`, whether it stopped or not.
+    pub fn hit_count(&self, address: u16) -> u64 {
+        self.breakpoints
+            .get(&address)
+            .map_or(0, |breakpoint| breakpoint.hits)
+    }
+
+    pub fn watch_memory(&mut self, address: u16, kind: WatchKind) {
+        self.memory.insert(address, kind);
+    }
+
+    pub fn unwatch_memory(&mut self, address: u16) {
+        self.memory.remove(&address);
+    }
+
+    pub fn memory_watchpoints(&self) -> impl Iterator<Item = (u16, WatchKind)> + '_ {
+        self.memory.iter().map(|(address, kind)| (*address, *kind))
+    }
+
+    pub fn watch_register(&mut self, register: Register, chip: &Chip8) {
+        self.registers.insert(register, register.read(chip));
+    }
+
+    pub fn unwatch_register(&mut self, register: Register) {
+        self.registers.remove(&register);
+    }
+
+    pub fn register_watchpoints(&self) -> impl Iterator<Item = Register> + '_ {
+        self.registers.keys().copied()
+    }
+
+    pub fn add_watch(&mut self, expr: Expr) {
+        self.watches.push(expr);
+    }
+
+    pub fn remove_watch(&mut self, index: usize) {
+        if index < self.watches.len() {
+            self.watches.remove(index);
+        }
+    }
+
+    /// Watch expressions in the order they were added.
+    pub fn watches(&self) -> &[Expr] {
+        &self.watches
+    }
+
+    /// Execute one instruction.
+    ///
+    /// Watchpoints still report what the instruction did, breakpoints are ignored.
+    pub fn step_into(&mut self, chip: &mut Chip8) -> Result<StopReason, Chip8Error> {
+        self.target = None;
+        let (_, stop) = self.cycle(chip)?;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(1);
+        }
+        self.stopped_at = Some(chip.program_counter);
+        Ok(stop.unwrap_or(StopReason::Step))
+    }
+
+    /// Run the next instruction, a `CALL` runs until its subroutine returns.
+    pub fn step_over(&mut self, chip: &Chip8) {
+        self.target = Some(Target::Depth(chip.stack.len()));
+    }
+
+    /// Run until the current subroutine returns.
+    ///
+    /// Outside of any subroutine this stops after the next instruction.
+    pub fn step_out(&mut self, chip: &Chip8) {
+        self.target = Some(Target::Depth(chip.stack.len().saturating_sub(1)));
+    }
+
+    /// Run until the program counter reaches `address`, breakpoints on the way still stop.
+    pub fn run_to(&mut self, address: u16) {
+        self.target = Some(Target::Address(address));
+    }
+
+    /// Forget an unfinished step over, step out or run to cursor.
+    pub fn cancel_step(&mut self) {
+        self.target = None;
+    }
+
+    /// [`Chip8::run_frame`] that stops at breakpoints, watchpoints and the end of steps.
+    ///
+    /// A breakpoint under the program counter stops before the first instruction, unless
+    /// the machine was just stopped there: resuming runs the instruction under it.
+    /// A stopped frame returns before ticking the timers, the next call finishes that frame
+    /// with the cycles it had left.
+    pub fn run_frame(
+        &mut self,
+        chip: &mut Chip8,
+        cycles_per_frame: usize,
+    ) -> Result<Option<StopReason>, Chip8Error> {
+        let cycles = self.remaining.take().unwrap_or(cycles_per_frame);
+        let resumed = self.stopped_at.take() == Some(chip.program_counter);
+        if !resumed && self.hit(chip) {
+            let pc = chip.program_counter;
+            return Ok(Some(self.stop(chip, StopReason::Breakpoint(pc), cycles)));
+        }
+        for done in 1..=cycles {
+            let (outcome, stop) = self.cycle(chip)?;
+            if let Some(stop) = stop {
+                return Ok(Some(self.stop(chip, stop, cycles - done)));
+            }
+            match outcome {
+                StepOutcome::WaitingForVblank | StepOutcome::Exited => break,
+                StepOutcome::WaitingForKey => continue,
+                StepOutcome::Executed => {}
+            }
+            let pc = chip.program_counter;
+            let stepped = match self.target {
+                Some(Target::Depth(depth)) => chip.stack.len() <= depth,
+                Some(Target::Address(address)) => pc == address,
+                None => false,
+            };
+            let stop = if stepped {
+                Some(StopReason::Step)
+            } else if self.hit(chip) {
+                Some(StopReason::Breakpoint(pc))
+            } else {
+                None
+            };
+            if let Some(stop) = stop {
+                return Ok(Some(self.stop(chip, stop, cycles - done)));
+            }
+        }
+        chip.tick_timers();
+        Ok(None)
+    }
+
+    // End any step and remember where the machine stopped and what is left of the frame
+    fn stop(&mut self, chip: &Chip8, reason: StopReason, remaining: usize) -> StopReason {
+        self.target = None;
+        self.stopped_at = Some(chip.program_counter);
+        self.remaining = Some(remaining);
+        reason
+    }
+
+    /// Run up to `max_frames` frames, headless, until the debugger stops the machine.
+    ///
+    /// Returns `None` when the frames ran out or the program exited.
+    pub fn run(
+        &mut self,
+        chip: &mut Chip8,
+        cycles_per_frame: usize,
+        max_frames: usize,
+    ) -> Result<Option<StopReason>, Chip8Error> {
+        for _ in 0..max_frames {
+            if let Some(stop) = self.run_frame(chip, cycles_per_frame)? {
+                return Ok(Some(stop));
+            }
+            if chip.exited() {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    // Count an arrival at a breakpoint under the program counter, true when it stops
+    fn hit(&mut self, chip: &Chip8) -> bool {
+        match self.breakpoints.get_mut(&chip.program_counter) {
+            Some(breakpoint) => {
+                breakpoint.hits += 1;
+                match &breakpoint.condition {
+                    Some(condition) => condition.is_true(chip, breakpoint.hits),
+                    None => true,
+                }
+            }
+            None => false,
+        }
+    }
+
+    // Execute one instruction and check the watchpoints against it
+    fn cycle(&mut self, chip: &mut Chip8) -> Result<(StepOutcome, Option<StopReason>), Chip8Error> {
+        // The log is also kept for a tracer, so it is never switched off here
+        if !self.memory.is_empty() {
+            chip.set_memory_log(true);
+        }
+        let outcome = chip.emulate_cycle()?;
+
+        let hit = chip.take_memory_log().into_iter().find(|access| {
+            match self.memory.get(&access.address) {
+                Some(kind) => kind.matches(access),
+                None => false,
+            }
+        });
+        let mut stop = hit.map(StopReason::Memory);
+        for (register, old) in self.registers.iter_mut() {
+            let new = register.read(chip);
+            if new != *old && stop.is_none() {
+                stop = Some(StopReason::Register {
+                    register: *register,
+                    old: *old,
+                    new,
+                });
+            }
+            *old = new;
+        }
+        Ok((outcome, stop))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Debugger, Register, StopReason, WatchKind};
+    use crate::{Chip8, Expr, MemoryAccess};
+
+    // 200: CALL 206
+    // 202: LD V1, 02
+    // 204: JP 204
+    // 206: LD I, 300
+    // 208: LD [I], V0
+    // 20A: ADD V0, 01
+    // 20C: RET
+    const PROGRAM: [u8; 14] = [
+        0x22, 0x06, 0x61, 0x02, 0x12, 0x04, 0xA3, 0x00, 0xF0, 0x55, 0x70, 0x01, 0x00, 0xEE,
+    ];
+
+    fn chip() -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.initialize_ram();
+        chip.load_program(&PROGRAM).unwrap();
+        chip
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut chip = chip();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x20A);
+        assert_eq!(
+            debugger.run_frame(&mut chip, 100),
+            Ok(Some(StopReason::Breakpoint(0x20A)))
+        );
+        assert_eq!(chip.program_counter(), 0x20A);
+        // Resuming runs the instruction under the breakpoint
+        assert_eq!(debugger.run_frame(&mut chip, 100), Ok(None));
+        assert_eq!(chip.program_counter(), 0x204);
+        assert!(debugger.toggle_breakpoint(0x204));
+        assert_eq!(
+            debugger.run_frame(&mut chip, 100),
+            Ok(Some(StopReason::Breakpoint(0x204)))
+        );
+    }
+
+    #[test]
+    fn test_breakpoint_on_entry_and_frame_budget() {
+        let mut chip = chip();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x200);
+        debugger.add_breakpoint(0x20A);
+        Register::Dt.write(&mut chip, 5);
+        assert_eq!(
+            debugger.run_frame(&mut chip, 4),
+            Ok(Some(StopReason::Breakpoint(0x200)))
+        );
+        assert_eq!(
+            debugger.run_frame(&mut chip, 4),
+            Ok(Some(StopReason::Breakpoint(0x20A)))
+        );
+        assert_eq!(chip.delay_timer, 5);
+        // The rest of the interrupted frame is one instruction, then the timers tick
+        assert_eq!(debugger.run_frame(&mut chip, 4), Ok(None));
+        assert_eq!(chip.program_counter(), 0x20C);
+        assert_eq!(chip.delay_timer, 4);
+        assert_eq!(debugger.run_frame(&mut chip, 4), Ok(None));
+        assert_eq!(chip.program_counter(), 0x204);
+    }
+
+    #[test]
+    fn test_conditional_breakpoints() {
+        // 200: ADD V0, 01
+        // 202: JP 200
+        let mut chip = Chip8::new();
+        chip.load_program(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut debugger = Debugger::new();
+        let condition = Expr::parse("hitcount >= 3 && V0 > 3").unwrap();
+        debugger.add_conditional_breakpoint(0x202, condition);
+        assert_eq!(
+            debugger.run_frame(&mut chip, 100),
+            Ok(Some(StopReason::Breakpoint(0x202)))
+        );
+        assert_eq!(chip.registers()[0], 4);
+        assert_eq!(debugger.hit_count(0x202), 4);
+
+        debugger.add_watch(Expr::parse("V0 * 2").unwrap());
+        let watch = &debugger.watches()[0];
+        assert_eq!(watch.evaluate(&chip, 0), 8);
+    }
+
+    #[test]
+    fn test_stepping() {
+        let mut chip = chip();
+        let mut debugger = Debugger::new();
+        debugger.step_over(&chip);
+        assert_eq!(
+            debugger.run_frame(&mut chip, 100),
+            Ok(Some(StopReason::Step))
+        );
+        assert_eq!(chip.program_counter(), 0x202);
+        assert_eq!(chip.registers()[0], 1);
+
+        let mut chip = self::chip();
+        assert_eq!(debugger.step_into(&mut chip), Ok(StopReason::Step));
+        assert_eq!(chip.program_counter(), 0x206);
+        debugger.step_out(&chip);
+        assert_eq!(
+            debugger.run_frame(&mut chip, 100),
+            Ok(Some(StopReason::Step))
+        );
+        assert_eq!(chip.program_counter(), 0x202);
+
+        let mut chip = self::chip();
+        debugger.run_to(0x20C);
+        assert_eq!(debugger.run(&mut chip, 10, 10), Ok(Some(StopReason::Step)));
+        assert_eq!(chip.program_counter(), 0x20C);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut chip = chip();
+        let mut debugger = Debugger::new();
+        debugger.watch_memory(0x300, WatchKind::Write);
+        assert_eq!(
+            debugger.run_frame(&mut chip, 100),
+            Ok(Some(StopReason::Memory(MemoryAccess {
+                address: 0x300,
+                value: 0,
+                write: true
+            })))
+        );
+        assert_eq!(chip.program_counter(), 0x20A);
+
+        debugger.unwatch_memory(0x300);
+        debugger.watch_register(Register::V(1), &chip);
+        assert_eq!(
+            debugger.run_frame(&mut chip, 100),
+            Ok(Some(StopReason::Register {
+                register: Register::V(1),
+                old: 0,
+                new: 2
+            }))
+        );
+        assert_eq!(chip.program_counter(), 0x204);
+        assert_eq!(Register::parse("vf"), Some(Register::V(15)));
+        assert_eq!(Register::parse("V10"), None);
+    }
+}
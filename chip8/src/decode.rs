@@ -0,0 +1,312 @@
+// Opcode decoding
+//
+// Every CHIP-8 instruction is one big-endian word. The operand fields share the
+// same positions across the whole instruction set:
+//   NNN  - lowest 12 bits, an address
+//   KK   - lowest 8 bits, a byte
+//   N    - lowest 4 bits, a nibble
+//   X    - lower 4 bits of the high byte, a register index
+//   Y    - upper 4 bits of the low byte, a register index
+// so they are extracted once here and handed to the handlers already split out.
+
+/// A single decoded CHIP-8 instruction.
+///
+/// Variants are named after the Cowgod mnemonics, register indices (`x`, `y`)
+/// are always in `0x0..=0xF`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodedOp {
+    /// `0NNN` - SYS addr
+    Sys { nnn: u16 },
+    /// `00E0` - CLS
+    Cls,
+    /// `00EE` - RET
+    Ret,
+    /// `00CN` - SCD nibble (SUPER-CHIP)
+    ScrollDown { n: u8 },
+    /// `00DN` - SCU nibble (XO-CHIP)
+    ScrollUp { n: u8 },
+    /// `00FB` - SCR (SUPER-CHIP)
+    ScrollRight,
+    /// `00FC` - SCL (SUPER-CHIP)
+    ScrollLeft,
+    /// `00FD` - EXIT (SUPER-CHIP)
+    Exit,
+    /// `00FE` - LOW (SUPER-CHIP)
+    Lores,
+    /// `00FF` - HIGH (SUPER-CHIP)
+    Hires,
+    /// `1NNN` - JP addr
+    Jp { nnn: u16 },
+    /// `2NNN` - CALL addr
+    Call { nnn: u16 },
+    /// `3XKK` - SE Vx, byte
+    SeVxByte { x: u8, kk: u8 },
+    /// `4XKK` - SNE Vx, byte
+    SneVxByte { x: u8, kk: u8 },
+    /// `5XY0` - SE Vx, Vy
+    SeVxVy { x: u8, y: u8 },
+    /// `5XY2` - SAVE Vx - Vy (XO-CHIP)
+    SaveVxVy { x: u8, y: u8 },
+    /// `5XY3` - LOAD Vx - Vy (XO-CHIP)
+    LoadVxVy { x: u8, y: u8 },
+    /// `6XKK` - LD Vx, byte
+    LdVxByte { x: u8, kk: u8 },
+    /// `7XKK` - ADD Vx, byte
+    AddVxByte { x: u8, kk: u8 },
+    /// `8XY0` - LD Vx, Vy
+    LdVxVy { x: u8, y: u8 },
+    /// `8XY1` - OR Vx, Vy
+    OrVxVy { x: u8, y: u8 },
+    /// `8XY2` - AND Vx, Vy
+    AndVxVy { x: u8, y: u8 },
+    /// `8XY3` - XOR Vx, Vy
+    XorVxVy { x: u8, y: u8 },
+    /// `8XY4` - ADD Vx, Vy
+    AddVxVy { x: u8, y: u8 },
+    /// `8XY5` - SUB Vx, Vy
+    SubVxVy { x: u8, y: u8 },
+    /// `8XY6` - SHR Vx {, Vy}
+    ShrVxVy { x: u8, y: u8 },
+    /// `8XY7` - SUBN Vx, Vy
+    SubnVxVy { x: u8, y: u8 },
+    /// `8XYE` - SHL Vx {, Vy}
+    ShlVxVy { x: u8, y: u8 },
+    /// `9XY0` - SNE Vx, Vy
+    SneVxVy { x: u8, y: u8 },
+    /// `ANNN` - LD I, addr
+    LdINnn { nnn: u16 },
+    /// `BNNN` - JP V0, addr
+    JpV0Nnn { nnn: u16 },
+    /// `CXKK` - RND Vx, byte
+    RndVxByte { x: u8, kk: u8 },
+    /// `DXYN` - DRW Vx, Vy, nibble, `DXY0` draws a 16x16 sprite in SUPER-CHIP
+    DrwVxVyN { x: u8, y: u8, n: u8 },
+    /// `EX9E` - SKP Vx
+    SkpVx { x: u8 },
+    /// `EXA1` - SKNP Vx
+    SknpVx { x: u8 },
+    /// `F000 NNNN` - LD I, long addr (XO-CHIP)
+    ///
+    /// The address is the word following the opcode, this is the only four byte instruction.
+    LdILong,
+    /// `FN01` - PLANE n (XO-CHIP)
+    Plane { n: u8 },
+    /// `F002` - AUDIO (XO-CHIP)
+    Audio,
+    /// `FX07` - LD Vx, DT
+    LdVxDt { x: u8 },
+    /// `FX0A` - LD Vx, K
+    LdVxK { x: u8 },
+    /// `FX15` - LD DT, Vx
+    LdDtVx { x: u8 },
+    /// `FX18` - LD ST, Vx
+    LdStVx { x: u8 },
+    /// `FX1E` - ADD I, Vx
+    AddIVx { x: u8 },
+    /// `FX29` - LD F, Vx
+    LdFVx { x: u8 },
+    /// `FX30` - LD HF, Vx (SUPER-CHIP)
+    LdHfVx { x: u8 },
+    /// `FX33` - LD B, Vx
+    LdBVx { x: u8 },
+    /// `FX3A` - PITCH Vx (XO-CHIP)
+    PitchVx { x: u8 },
+    /// `FX55` - LD [I], Vx
+    LdIVx { x: u8 },
+    /// `FX65` - LD Vx, [I]
+    LdVxI { x: u8 },
+    /// `FX75` - LD R, Vx (SUPER-CHIP)
+    LdRVx { x: u8 },
+    /// `FX85` - LD Vx, R (SUPER-CHIP)
+    LdVxR { x: u8 },
+    /// Any word that is not a CHIP-8 instruction.
+    Invalid(u16),
+}
+
+/// Decode a raw opcode word into a [`DecodedOp`].
+pub fn decode(op: u16) -> DecodedOp {
+    let nnn = op & 0x0FFF;
+    let kk = (op & 0x00FF) as u8;
+    let n = (op & 0x000F) as u8;
+    let x = ((op >> 8) & 0x0F) as u8;
+    let y = ((op >> 4) & 0x0F) as u8;
+
+    match op >> 12 {
+        0x0 => match op {
+            0x00E0 => DecodedOp::Cls,
+            0x00EE => DecodedOp::Ret,
+            0x00C0..=0x00CF => DecodedOp::ScrollDown { n },
+            0x00D0..=0x00DF => DecodedOp::ScrollUp { n },
+            0x00FB => DecodedOp::ScrollRight,
+            0x00FC => DecodedOp::ScrollLeft,
+            0x00FD => DecodedOp::Exit,
+            0x00FE => DecodedOp::Lores,
+            0x00FF => DecodedOp::Hires,
+            _ => DecodedOp::Sys { nnn },
+        },
+        0x1 => DecodedOp::Jp { nnn },
+        0x2 => DecodedOp::Call { nnn },
+        0x3 => DecodedOp::SeVxByte { x, kk },
+        0x4 => DecodedOp::SneVxByte { x, kk },
+        0x5 => match n {
+            0x0 => DecodedOp::SeVxVy { x, y },
+            0x2 => DecodedOp::SaveVxVy { x, y },
+            0x3 => DecodedOp::LoadVxVy { x, y },
+            _ => DecodedOp::Invalid(op),
+        },
+        0x6 => DecodedOp::LdVxByte { x, kk },
+        0x7 => DecodedOp::AddVxByte { x, kk },
+        0x8 => match n {
+            0x0 => DecodedOp::LdVxVy { x, y },
+            0x1 => DecodedOp::OrVxVy { x, y },
+            0x2 => DecodedOp::AndVxVy { x, y },
+            0x3 => DecodedOp::XorVxVy { x, y },
+            0x4 => DecodedOp::AddVxVy { x, y },
+            0x5 => DecodedOp::SubVxVy { x, y },
+            0x6 => DecodedOp::ShrVxVy { x, y },
+            0x7 => DecodedOp::SubnVxVy { x, y },
+            0xE => DecodedOp::ShlVxVy { x, y },
+            _ => DecodedOp::Invalid(op),
+        },
+        0x9 if n == 0x0 => DecodedOp::SneVxVy { x, y },
+        0xA => DecodedOp::LdINnn { nnn },
+        0xB => DecodedOp::JpV0Nnn { nnn },
+        0xC => DecodedOp::RndVxByte { x, kk },
+        0xD => DecodedOp::DrwVxVyN { x, y, n },
+        0xE => match kk {
+            0x9E => DecodedOp::SkpVx { x },
+            0xA1 => DecodedOp::SknpVx { x },
+            _ => DecodedOp::Invalid(op),
+        },
+        0xF => match kk {
+            0x00 if x == 0x0 => DecodedOp::LdILong,
+            0x01 => DecodedOp::Plane { n: x },
+            0x02 if x == 0x0 => DecodedOp::Audio,
+            0x07 => DecodedOp::LdVxDt { x },
+            0x0A => DecodedOp::LdVxK { x },
+            0x15 => DecodedOp::LdDtVx { x },
+            0x18 => DecodedOp::LdStVx { x },
+            0x1E => DecodedOp::AddIVx { x },
+            0x29 => DecodedOp::LdFVx { x },
+            0x30 => DecodedOp::LdHfVx { x },
+            0x33 => DecodedOp::LdBVx { x },
+            0x3A => DecodedOp::PitchVx { x },
+            0x55 => DecodedOp::LdIVx { x },
+            0x65 => DecodedOp::LdVxI { x },
+            0x75 => DecodedOp::LdRVx { x },
+            0x85 => DecodedOp::LdVxR { x },
+            _ => DecodedOp::Invalid(op),
+        },
+        _ => DecodedOp::Invalid(op),
+    }
+}
+
+/// Decode `op` for a machine that only knows some of the extensions.
+///
+/// SUPER-CHIP instructions need `super_chip`, XO-CHIP ones `xo_chip`, which also implies
+/// SUPER-CHIP. Anything else decodes as the original interpreter saw it, a machine code
+/// call in the `0NNN` range or an invalid opcode.
+pub fn decode_for(op: u16, super_chip: bool, xo_chip: bool) -> DecodedOp {
+    let decoded = decode(op);
+    let supported = match decoded {
+        DecodedOp::ScrollDown { .. }
+        | DecodedOp::ScrollRight
+        | DecodedOp::ScrollLeft
+        | DecodedOp::Exit
+        | DecodedOp::Lores
+        | DecodedOp::Hires
+        | DecodedOp::LdHfVx { .. }
+        | DecodedOp::LdRVx { .. }
+        | DecodedOp::LdVxR { .. } => super_chip || xo_chip,
+        DecodedOp::ScrollUp { .. }
+        | DecodedOp::SaveVxVy { .. }
+        | DecodedOp::LoadVxVy { .. }
+        | DecodedOp::LdILong
+        | DecodedOp::Plane { .. }
+        | DecodedOp::Audio
+        | DecodedOp::PitchVx { .. } => xo_chip,
+        _ => true,
+    };
+    match decoded {
+        _ if supported => decoded,
+        _ if op >> 12 == 0x0 => DecodedOp::Sys { nnn: op & 0x0FFF },
+        _ => DecodedOp::Invalid(op),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, decode_for, DecodedOp};
+
+    #[test]
+    fn test_decode_operand_fields() {
+        assert_eq!(decode(0x3A42), DecodedOp::SeVxByte { x: 0xA, kk: 0x42 });
+        assert_eq!(decode(0x8AB4), DecodedOp::AddVxVy { x: 0xA, y: 0xB });
+        assert_eq!(decode(0xD12F), DecodedOp::DrwVxVyN { x: 1, y: 2, n: 0xF });
+        assert_eq!(decode(0xB2F0), DecodedOp::JpV0Nnn { nnn: 0x2F0 });
+    }
+
+    #[test]
+    fn test_decode_system_group() {
+        assert_eq!(decode(0x00E0), DecodedOp::Cls);
+        assert_eq!(decode(0x00EE), DecodedOp::Ret);
+        assert_eq!(decode(0x0123), DecodedOp::Sys { nnn: 0x123 });
+    }
+
+    #[test]
+    fn test_decode_super_chip() {
+        assert_eq!(decode(0x00C4), DecodedOp::ScrollDown { n: 4 });
+        assert_eq!(decode(0x00FB), DecodedOp::ScrollRight);
+        assert_eq!(decode(0x00FC), DecodedOp::ScrollLeft);
+        assert_eq!(decode(0x00FD), DecodedOp::Exit);
+        assert_eq!(decode(0x00FE), DecodedOp::Lores);
+        assert_eq!(decode(0x00FF), DecodedOp::Hires);
+        assert_eq!(decode(0xD120), DecodedOp::DrwVxVyN { x: 1, y: 2, n: 0 });
+        assert_eq!(decode(0xF330), DecodedOp::LdHfVx { x: 3 });
+        assert_eq!(decode(0xF775), DecodedOp::LdRVx { x: 7 });
+        assert_eq!(decode(0xF785), DecodedOp::LdVxR { x: 7 });
+    }
+
+    #[test]
+    fn test_decode_xo_chip() {
+        assert_eq!(decode(0x00D3), DecodedOp::ScrollUp { n: 3 });
+        assert_eq!(decode(0x5122), DecodedOp::SaveVxVy { x: 1, y: 2 });
+        assert_eq!(decode(0x5213), DecodedOp::LoadVxVy { x: 2, y: 1 });
+        assert_eq!(decode(0xF000), DecodedOp::LdILong);
+        assert_eq!(decode(0xF201), DecodedOp::Plane { n: 2 });
+        assert_eq!(decode(0xF002), DecodedOp::Audio);
+        assert_eq!(decode(0xF53A), DecodedOp::PitchVx { x: 5 });
+        assert_eq!(decode(0xF100), DecodedOp::Invalid(0xF100));
+    }
+
+    #[test]
+    fn test_decode_for_platform() {
+        assert_eq!(
+            decode_for(0x00FF, false, false),
+            DecodedOp::Sys { nnn: 0x0FF }
+        );
+        assert_eq!(decode_for(0x00FF, true, false), DecodedOp::Hires);
+        assert_eq!(decode_for(0xF375, false, false), DecodedOp::Invalid(0xF375));
+        assert_eq!(decode_for(0x5122, true, false), DecodedOp::Invalid(0x5122));
+        assert_eq!(
+            decode_for(0x00D3, true, false),
+            DecodedOp::Sys { nnn: 0x0D3 }
+        );
+        assert_eq!(
+            decode_for(0x5122, false, true),
+            DecodedOp::SaveVxVy { x: 1, y: 2 }
+        );
+        assert_eq!(decode_for(0x00FB, false, true), DecodedOp::ScrollRight);
+        assert_eq!(
+            decode_for(0x6142, false, false),
+            DecodedOp::LdVxByte { x: 1, kk: 0x42 }
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        for op in [0x5121, 0x800F, 0x9AB1, 0xE0FF, 0xF0FF] {
+            assert_eq!(decode(op), DecodedOp::Invalid(op));
+        }
+    }
+}
@@ -0,0 +1,518 @@
+// Disassembler
+//
+// Listings are built in two passes. The first pass sweeps the bytes linearly and
+// collects every address that an instruction refers to: jump and call targets are
+// code, ANNN / F000 NNNN targets are (usually sprite) data. The second pass sweeps
+// again, switching to data as soon as it reaches a data target and back to code at
+// the next code target. Words that do not decode are emitted as data as well.
+// Targets that land on the start of a listed line get a label, every operand that
+// refers to them prints the label instead of the address.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chip8::Chip8;
+use crate::decode::{decode, DecodedOp};
+use crate::symbols::SymbolMap;
+
+/// Assembly dialect of a [`Listing`].
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// Cowgod's technical reference mnemonics, `LD VA, #02`.
+    Cowgod,
+    /// Octo, `va := 0x02`.
+    Octo,
+}
+
+impl Default for Syntax {
+    fn default() -> Self {
+        Syntax::Cowgod
+    }
+}
+
+/// What a listing line holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Code(DecodedOp),
+    Data,
+}
+
+/// One instruction or run of data bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub kind: LineKind,
+    /// Label of `address`, when something jumps to or loads it.
+    pub label: Option<String>,
+    /// Mnemonic and operands, without address and bytes.
+    pub text: String,
+}
+
+/// A disassembled byte range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    pub syntax: Syntax,
+    pub lines: Vec<Line>,
+}
+
+impl Listing {
+    /// Index of the line starting at `address`.
+    pub fn line_at(&self, address: u16) -> Option<usize> {
+        self.lines
+            .binary_search_by_key(&address, |line| line.address)
+            .ok()
+    }
+
+    /// Full listing with addresses and raw bytes.
+    ///
+    /// Octo listings keep addresses and bytes in comments so they assemble again.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        if self.syntax == Syntax::Octo {
+            match self.lines.first() {
+                Some(line) if line.address != 0x200 => {
+                    let _ = writeln!(out, ":org {:#05X}", line.address);
+                }
+                _ => {}
+            }
+        }
+        for line in &self.lines {
+            if let Some(label) = &line.label {
+                let _ = match self.syntax {
+                    Syntax::Cowgod => writeln!(out, "{}:", label),
+                    Syntax::Octo => writeln!(out, ": {}", label),
+                };
+            }
+            let _ = match self.syntax {
+                Syntax::Cowgod => writeln!(
+                    out,
+                    "{:04X}  {:<11} {}",
+                    line.address,
+                    hex_bytes(&line.bytes),
+                    line.text
+                ),
+                Syntax::Octo => writeln!(
+                    out,
+                    "\t{:<24} # {:04X}  {}",
+                    line.text,
+                    line.address,
+                    hex_bytes(&line.bytes)
+                ),
+            };
+        }
+        out
+    }
+}
+
+/// Disassemble `bytes` as if they were loaded at `origin`.
+pub fn disassemble(bytes: &[u8], origin: u16, syntax: Syntax) -> Listing {
+    let targets = Targets::collect(bytes);
+
+    // Line boundaries and contents, labels are attached once all lines are known
+    let mut lines = Vec::new();
+    let mut in_data = false;
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let address = origin.wrapping_add(offset as u16);
+        if targets.code.contains(&address) {
+            in_data = false;
+        } else if targets.data.contains(&address) {
+            in_data = true;
+        }
+        let op = match fetch(bytes, offset) {
+            Some(word) if !in_data => decode(word),
+            _ => DecodedOp::Invalid(0),
+        };
+        // Data lines are one byte long, or two for a word that does not decode
+        let (len, kind) = match op {
+            DecodedOp::LdILong if offset + 4 <= bytes.len() => (4, LineKind::Code(op)),
+            DecodedOp::LdILong | DecodedOp::Invalid(_) if !in_data && offset + 2 <= bytes.len() => {
+                (2, LineKind::Data)
+            }
+            DecodedOp::LdILong | DecodedOp::Invalid(_) => (1, LineKind::Data),
+            _ => (2, LineKind::Code(op)),
+        };
+        lines.push((address, &bytes[offset..offset + len], kind));
+        offset += len;
+    }
+    build_listing(lines, &targets, &SymbolMap::new(), syntax)
+}
+
+// Attach labels to split up lines and format them, names from `symbols` win over
+// generated ones and are used for operands even where no line starts
+pub(crate) fn build_listing(
+    lines: Vec<(u16, &[u8], LineKind)>,
+    targets: &Targets,
+    symbols: &SymbolMap,
+    syntax: Syntax,
+) -> Listing {
+    let starts: BTreeSet<u16> = lines.iter().map(|(address, _, _)| *address).collect();
+    let mut labels: BTreeMap<u16, String> = targets
+        .code
+        .iter()
+        .chain(&targets.data)
+        .filter(|target| starts.contains(target))
+        .map(|target| (*target, targets.label(*target)))
+        .collect();
+    for (address, name) in symbols.names() {
+        labels.insert(address, name.to_string());
+    }
+
+    let lines = lines
+        .into_iter()
+        .map(|(address, bytes, kind)| Line {
+            address,
+            bytes: bytes.to_vec(),
+            kind,
+            label: labels.get(&address).cloned(),
+            text: match kind {
+                LineKind::Code(op) => format_op(op, bytes, syntax, &labels),
+                LineKind::Data => format_data(bytes, syntax),
+            },
+        })
+        .collect();
+    Listing { syntax, lines }
+}
+
+impl Chip8 {
+    // Disassemble `len` bytes of RAM starting at `start`, clipped to the end of RAM
+    pub fn disassemble(&self, start: u16, len: usize, syntax: Syntax) -> Listing {
+        let start_index = (start as usize).min(self.ram.len());
+        let end = start_index.saturating_add(len).min(self.ram.len());
+        disassemble(&self.ram[start_index..end], start, syntax)
+    }
+}
+
+pub(crate) fn fetch(bytes: &[u8], offset: usize) -> Option<u16> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|word| u16::from_be_bytes([word[0], word[1]]))
+}
+
+// Addresses referred to by instructions
+#[derive(Default)]
+pub(crate) struct Targets {
+    // Jump and call targets
+    pub(crate) code: BTreeSet<u16>,
+    pub(crate) calls: BTreeSet<u16>,
+    // Addresses loaded into I that are not also code
+    pub(crate) data: BTreeSet<u16>,
+}
+
+impl Targets {
+    // Linear sweep over every word
+    fn collect(bytes: &[u8]) -> Self {
+        let mut targets = Targets::default();
+        let mut offset = 0;
+        while let Some(word) = fetch(bytes, offset) {
+            offset += 2;
+            match decode(word) {
+                DecodedOp::Call { nnn } => {
+                    targets.code.insert(nnn);
+                    targets.calls.insert(nnn);
+                }
+                DecodedOp::Jp { nnn } | DecodedOp::JpV0Nnn { nnn } => {
+                    targets.code.insert(nnn);
+                }
+                DecodedOp::LdINnn { nnn } => {
+                    targets.data.insert(nnn);
+                }
+                DecodedOp::LdILong => {
+                    if let Some(address) = fetch(bytes, offset) {
+                        targets.data.insert(address);
+                        offset += 2;
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Code wins when an address is both, e.g. code that reads its own bytes
+        targets.data = targets.data.difference(&targets.code).copied().collect();
+        targets
+    }
+
+    fn label(&self, address: u16) -> String {
+        let prefix = if self.calls.contains(&address) {
+            "sub"
+        } else if self.code.contains(&address) {
+            "label"
+        } else {
+            "data"
+        };
+        format!("{}_{:03X}", prefix, address)
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn format_data(bytes: &[u8], syntax: Syntax) -> String {
+    let values: Vec<String> = bytes
+        .iter()
+        .map(|byte| match syntax {
+            Syntax::Cowgod => format!("#{:02X}", byte),
+            Syntax::Octo => format!("0x{:02X}", byte),
+        })
+        .collect();
+    match syntax {
+        Syntax::Cowgod => format!("DB {}", values.join(", ")),
+        Syntax::Octo => values.join(" "),
+    }
+}
+
+pub(crate) fn format_op(
+    op: DecodedOp,
+    bytes: &[u8],
+    syntax: Syntax,
+    labels: &BTreeMap<u16, String>,
+) -> String {
+    match syntax {
+        Syntax::Cowgod => cowgod(op, bytes, labels),
+        Syntax::Octo => octo(op, bytes, labels),
+    }
+}
+
+fn cowgod(op: DecodedOp, bytes: &[u8], labels: &BTreeMap<u16, String>) -> String {
+    let addr = |nnn: u16| match labels.get(&nnn) {
+        Some(label) => label.clone(),
+        None => format!("#{:03X}", nnn),
+    };
+    match op {
+        DecodedOp::Sys { nnn } => format!("SYS {}", addr(nnn)),
+        DecodedOp::Cls => "CLS".to_string(),
+        DecodedOp::Ret => "RET".to_string(),
+        DecodedOp::ScrollDown { n } => format!("SCD {}", n),
+        DecodedOp::ScrollUp { n } => format!("SCU {}", n),
+        DecodedOp::ScrollRight => "SCR".to_string(),
+        DecodedOp::ScrollLeft => "SCL".to_string(),
+        DecodedOp::Exit => "EXIT".to_string(),
+        DecodedOp::Lores => "LOW".to_string(),
+        DecodedOp::Hires => "HIGH".to_string(),
+        DecodedOp::Jp { nnn } => format!("JP {}", addr(nnn)),
+        DecodedOp::Call { nnn } => format!("CALL {}", addr(nnn)),
+        DecodedOp::SeVxByte { x, kk } => format!("SE V{:X}, #{:02X}", x, kk),
+        DecodedOp::SneVxByte { x, kk } => format!("SNE V{:X}, #{:02X}", x, kk),
+        DecodedOp::SeVxVy { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        DecodedOp::SaveVxVy { x, y } => format!("SAVE V{:X} - V{:X}", x, y),
+        DecodedOp::LoadVxVy { x, y } => format!("LOAD V{:X} - V{:X}", x, y),
+        DecodedOp::LdVxByte { x, kk } => format!("LD V{:X}, #{:02X}", x, kk),
+        DecodedOp::AddVxByte { x, kk } => format!("ADD V{:X}, #{:02X}", x, kk),
+        DecodedOp::LdVxVy { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        DecodedOp::OrVxVy { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        DecodedOp::AndVxVy { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        DecodedOp::XorVxVy { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        DecodedOp::AddVxVy { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        DecodedOp::SubVxVy { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        DecodedOp::ShrVxVy { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        DecodedOp::SubnVxVy { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        DecodedOp::ShlVxVy { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        DecodedOp::SneVxVy { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        DecodedOp::LdINnn { nnn } => format!("LD I, {}", addr(nnn)),
+        DecodedOp::JpV0Nnn { nnn } => format!("JP V0, {}", addr(nnn)),
+        DecodedOp::RndVxByte { x, kk } => format!("RND V{:X}, #{:02X}", x, kk),
+        DecodedOp::DrwVxVyN { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        DecodedOp::SkpVx { x } => format!("SKP V{:X}", x),
+        DecodedOp::SknpVx { x } => format!("SKNP V{:X}", x),
+        DecodedOp::LdILong => {
+            let target = long_address(bytes);
+            match labels.get(&target) {
+                Some(label) => format!("LD I, {}", label),
+                None => format!("LD I, #{:04X}", target),
+            }
+        }
+        DecodedOp::Plane { n } => format!("PLANE {}", n),
+        DecodedOp::Audio => "AUDIO".to_string(),
+        DecodedOp::LdVxDt { x } => format!("LD V{:X}, DT", x),
+        DecodedOp::LdVxK { x } => format!("LD V{:X}, K", x),
+        DecodedOp::LdDtVx { x } => format!("LD DT, V{:X}", x),
+        DecodedOp::LdStVx { x } => format!("LD ST, V{:X}", x),
+        DecodedOp::AddIVx { x } => format!("ADD I, V{:X}", x),
+        DecodedOp::LdFVx { x } => format!("LD F, V{:X}", x),
+        DecodedOp::LdHfVx { x } => format!("LD HF, V{:X}", x),
+        DecodedOp::LdBVx { x } => format!("LD B, V{:X}", x),
+        DecodedOp::PitchVx { x } => format!("PITCH V{:X}", x),
+        DecodedOp::LdIVx { x } => format!("LD [I], V{:X}", x),
+        DecodedOp::LdVxI { x } => format!("LD V{:X}, [I]", x),
+        DecodedOp::LdRVx { x } => format!("LD R, V{:X}", x),
+        DecodedOp::LdVxR { x } => format!("LD V{:X}, R", x),
+        DecodedOp::Invalid(_) => format_data(bytes, Syntax::Cowgod),
+    }
+}
+
+// Octo has no skip instructions, a skip is the condition under which the next
+// instruction runs: `SE Vx, kk` skips when equal, so the next one runs `if vx != kk`.
+fn octo(op: DecodedOp, bytes: &[u8], labels: &BTreeMap<u16, String>) -> String {
+    let addr = |nnn: u16| match labels.get(&nnn) {
+        Some(label) => label.clone(),
+        None => format!("{:#05X}", nnn),
+    };
+    match op {
+        // Native calls have no Octo statement, emit the raw word
+        DecodedOp::Sys { .. } => format_data(bytes, Syntax::Octo),
+        DecodedOp::Cls => "clear".to_string(),
+        DecodedOp::Ret => "return".to_string(),
+        DecodedOp::ScrollDown { n } => format!("scroll-down {}", n),
+        DecodedOp::ScrollUp { n } => format!("scroll-up {}", n),
+        DecodedOp::ScrollRight => "scroll-right".to_string(),
+        DecodedOp::ScrollLeft => "scroll-left".to_string(),
+        DecodedOp::Exit => "exit".to_string(),
+        DecodedOp::Lores => "lores".to_string(),
+        DecodedOp::Hires => "hires".to_string(),
+        DecodedOp::Jp { nnn } => format!("jump {}", addr(nnn)),
+        DecodedOp::Call { nnn } => format!(":call {}", addr(nnn)),
+        DecodedOp::SeVxByte { x, kk } => format!("if v{:x} != 0x{:02X} then", x, kk),
+        DecodedOp::SneVxByte { x, kk } => format!("if v{:x} == 0x{:02X} then", x, kk),
+        DecodedOp::SeVxVy { x, y } => format!("if v{:x} != v{:x} then", x, y),
+        DecodedOp::SaveVxVy { x, y } => format!("save v{:x} - v{:x}", x, y),
+        DecodedOp::LoadVxVy { x, y } => format!("load v{:x} - v{:x}", x, y),
+        DecodedOp::LdVxByte { x, kk } => format!("v{:x} := 0x{:02X}", x, kk),
+        DecodedOp::AddVxByte { x, kk } => format!("v{:x} += 0x{:02X}", x, kk),
+        DecodedOp::LdVxVy { x, y } => format!("v{:x} := v{:x}", x, y),
+        DecodedOp::OrVxVy { x, y } => format!("v{:x} |= v{:x}", x, y),
+        DecodedOp::AndVxVy { x, y } => format!("v{:x} &= v{:x}", x, y),
+        DecodedOp::XorVxVy { x, y } => format!("v{:x} ^= v{:x}", x, y),
+        DecodedOp::AddVxVy { x, y } => format!("v{:x} += v{:x}", x, y),
+        DecodedOp::SubVxVy { x, y } => format!("v{:x} -= v{:x}", x, y),
+        DecodedOp::ShrVxVy { x, y } => format!("v{:x} >>= v{:x}", x, y),
+        DecodedOp::SubnVxVy { x, y } => format!("v{:x} =- v{:x}", x, y),
+        DecodedOp::ShlVxVy { x, y } => format!("v{:x} <<= v{:x}", x, y),
+        DecodedOp::SneVxVy { x, y } => format!("if v{:x} == v{:x} then", x, y),
+        DecodedOp::LdINnn { nnn } => format!("i := {}", addr(nnn)),
+        DecodedOp::JpV0Nnn { nnn } => format!("jump0 {}", addr(nnn)),
+        DecodedOp::RndVxByte { x, kk } => format!("v{:x} := random 0x{:02X}", x, kk),
+        DecodedOp::DrwVxVyN { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+        DecodedOp::SkpVx { x } => format!("if v{:x} -key then", x),
+        DecodedOp::SknpVx { x } => format!("if v{:x} key then", x),
+        DecodedOp::LdILong => {
+            let target = long_address(bytes);
+            match labels.get(&target) {
+                Some(label) => format!("i := long {}", label),
+                None => format!("i := long {:#06X}", target),
+            }
+        }
+        DecodedOp::Plane { n } => format!("plane {}", n),
+        DecodedOp::Audio => "audio".to_string(),
+        DecodedOp::LdVxDt { x } => format!("v{:x} := delay", x),
+        DecodedOp::LdVxK { x } => format!("v{:x} := key", x),
+        DecodedOp::LdDtVx { x } => format!("delay := v{:x}", x),
+        DecodedOp::LdStVx { x } => format!("buzzer := v{:x}", x),
+        DecodedOp::AddIVx { x } => format!("i += v{:x}", x),
+        DecodedOp::LdFVx { x } => format!("i := hex v{:x}", x),
+        DecodedOp::LdHfVx { x } => format!("i := bighex v{:x}", x),
+        DecodedOp::LdBVx { x } => format!("bcd v{:x}", x),
+        DecodedOp::PitchVx { x } => format!("pitch := v{:x}", x),
+        DecodedOp::LdIVx { x } => format!("save v{:x}", x),
+        DecodedOp::LdVxI { x } => format!("load v{:x}", x),
+        DecodedOp::LdRVx { x } => format!("saveflags v{:x}", x),
+        DecodedOp::LdVxR { x } => format!("loadflags v{:x}", x),
+        DecodedOp::Invalid(_) => format_data(bytes, Syntax::Octo),
+    }
+}
+
+// Operand of `F000 NNNN`, the word after the opcode
+fn long_address(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, LineKind, Syntax};
+    use crate::{Chip8, DecodedOp};
+
+    // CALL sub ; JP self ; sub: LD I, sprite ; DRW V0, V1, 2 ; RET ; sprite: F0 90
+    const PROGRAM: [u8; 12] = [
+        0x22, 0x04, 0x12, 0x02, 0xA2, 0x0A, 0xD0, 0x12, 0x00, 0xEE, 0xF0, 0x90,
+    ];
+
+    #[test]
+    fn test_cowgod_listing() {
+        let listing = disassemble(&PROGRAM, 0x200, Syntax::Cowgod);
+        let texts: Vec<&str> = listing
+            .lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "CALL sub_204",
+                "JP label_202",
+                "LD I, data_20A",
+                "DRW V0, V1, 2",
+                "RET",
+                "DB #F0",
+                "DB #90"
+            ]
+        );
+        assert_eq!(listing.lines[2].label.as_deref(), Some("sub_204"));
+        assert_eq!(listing.lines[5].kind, LineKind::Data);
+        assert_eq!(listing.line_at(0x206), Some(3));
+        assert!(listing
+            .to_text()
+            .contains("sub_204:\n0204  A2 0A       LD I, data_20A\n"));
+    }
+
+    #[test]
+    fn test_octo_listing() {
+        let listing = disassemble(&PROGRAM, 0x200, Syntax::Octo);
+        let texts: Vec<&str> = listing
+            .lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                ":call sub_204",
+                "jump label_202",
+                "i := data_20A",
+                "sprite v0 v1 2",
+                "return",
+                "0xF0",
+                "0x90"
+            ]
+        );
+        let text = listing.to_text();
+        assert!(text.starts_with("\t:call sub_204"));
+        assert!(text.contains(": data_20A\n"));
+
+        let skips = disassemble(&[0x3A, 0x02, 0xE1, 0xA1, 0x8A, 0xB7], 0x300, Syntax::Octo);
+        assert!(skips.to_text().starts_with(":org 0x300\n"));
+        assert_eq!(skips.lines[0].text, "if va != 0x02 then");
+        assert_eq!(skips.lines[1].text, "if v1 key then");
+        assert_eq!(skips.lines[2].text, "va =- vb");
+    }
+
+    #[test]
+    fn test_long_load_and_invalid_words() {
+        // LD I, long 0x1234 ; invalid word ; trailing odd byte
+        let listing = disassemble(
+            &[0xF0, 0x00, 0x12, 0x34, 0xFF, 0xFF, 0x12],
+            0x200,
+            Syntax::Cowgod,
+        );
+        assert_eq!(listing.lines[0].kind, LineKind::Code(DecodedOp::LdILong));
+        assert_eq!(listing.lines[0].bytes.len(), 4);
+        assert_eq!(listing.lines[0].text, "LD I, #1234");
+        assert_eq!(listing.lines[1].text, "DB #FF, #FF");
+        assert_eq!(listing.lines[2].text, "DB #12");
+    }
+
+    #[test]
+    fn test_disassemble_ram() {
+        let mut chip = Chip8::new();
+        chip.load_program(&PROGRAM).unwrap();
+        let listing = chip.disassemble(0x200, 6, Syntax::Cowgod);
+        assert_eq!(listing.lines.len(), 3);
+        // Clipped at the end of RAM
+        assert_eq!(chip.disassemble(0xFFE, 100, Syntax::Cowgod).lines.len(), 1);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::chip8::{Pixel, BITMAP_HEIGHT, BITMAP_WIDTH, HIRES_BITMAP_HEIGHT};
+
+/// Black background, white foreground, plus the XO-CHIP second plane and plane overlap colours.
+pub const DEFAULT_PALETTE: [[u8; 4]; 4] = [
+    [0x00, 0x00, 0x00, 0xFF],
+    [0xFF, 0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA, 0xFF],
+    [0x55, 0x55, 0x55, 0xFF],
+];
+
+/// Read-only view of the CHIP-8 frame buffer, see [`crate::Chip8::display`].
+///
+/// The size follows the current resolution: 64x32, or 128x64 in SUPER-CHIP hires mode.
+#[derive(Clone, Copy)]
+pub struct Display<'a> {
+    bit_map: &'a [Pixel],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Display<'a> {
+    pub(crate) fn new(bit_map: &'a [Pixel], width: usize, height: usize) -> Self {
+        Self {
+            bit_map,
+            width,
+            height,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Pixel at (x, y), with (0, 0) the top left corner. Panics when out of range.
+    pub fn pixel(&self, x: usize, y: usize) -> Pixel {
+        assert!(
+            x < self.width() && y < self.height(),
+            "pixel ({}, {}) is off screen",
+            x,
+            y
+        );
+        self.bit_map[y * self.width() + x]
+    }
+
+    /// True when the pixel is lit on any plane.
+    pub fn is_lit(&self, x: usize, y: usize) -> bool {
+        self.pixel(x, y) != Pixel::Black
+    }
+
+    /// Iterate over the rows of the screen, top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &'a [Pixel]> {
+        self.bit_map.chunks(self.width)
+    }
+
+    /// One `u64` per row, the most significant bit is the leftmost pixel. A bit is set when the pixel is lit on any plane.
+    ///
+    /// In hires mode only the top left 64x32 pixels are returned, see [`Display::to_packed_hires`].
+    pub fn to_packed(&self) -> [u64; BITMAP_HEIGHT] {
+        let mut packed = [0u64; BITMAP_HEIGHT];
+        for (word, row) in packed.iter_mut().zip(self.rows()) {
+            *word = row[..BITMAP_WIDTH]
+                .iter()
+                .fold(0, |acc, pixel| (acc << 1) | (*pixel != Pixel::Black) as u64);
+        }
+        packed
+    }
+
+    /// One `u128` per row of the 128x64 hires screen, the most significant bit is the leftmost pixel.
+    ///
+    /// In lores mode every pixel is doubled in both directions.
+    pub fn to_packed_hires(&self) -> [u128; HIRES_BITMAP_HEIGHT] {
+        let scale = HIRES_BITMAP_HEIGHT / self.height;
+        let mut packed = [0u128; HIRES_BITMAP_HEIGHT];
+        for (y, word) in packed.iter_mut().enumerate() {
+            let row = &self.bit_map[y / scale * self.width..][..self.width];
+            *word = row.iter().fold(0, |acc, pixel| {
+                let lit = (*pixel != Pixel::Black) as u128;
+                (0..scale).fold(acc, |acc, _| (acc << 1) | lit)
+            });
+        }
+        packed
+    }
+
+    /// Convert to a row-major RGBA8 buffer of `width * height * 4` bytes.
+    ///
+    /// Each pixel value indexes into `palette`, `Pixel::Black` is 0 up to `Pixel::BothPlanes` at 3.
+    /// Indices past the end use the last colour, so a two colour palette draws every lit pixel
+    /// in its foreground. Panics if `palette` is empty.
+    pub fn to_rgba8(&self, palette: &[[u8; 4]]) -> Vec<u8> {
+        let last = palette.len() - 1;
+        self.bit_map
+            .iter()
+            .flat_map(|pixel| palette[(*pixel as usize).min(last)])
+            .collect()
+    }
+
+    /// One byte per pixel, 0x00 for unlit and 0xFF for lit pixels.
+    ///
+    /// XO-CHIP pixels lit only on the second plane are 0xAA, on both planes 0x55.
+    pub fn to_grayscale(&self) -> Vec<u8> {
+        self.bit_map
+            .iter()
+            .map(|pixel| DEFAULT_PALETTE[*pixel as usize][0])
+            .collect()
+    }
+
+    /// One line of characters per row: `.` unlit, `#` lit, and for XO-CHIP `+` on the
+    /// second plane only and `@` on both.
+    pub fn to_text(&self) -> String {
+        let mut text = String::with_capacity((self.width + 1) * self.height);
+        for row in self.rows() {
+            text.extend(row.iter().map(|pixel| match pixel {
+                Pixel::Black => '.',
+                Pixel::White => '#',
+                Pixel::Plane2 => '+',
+                Pixel::BothPlanes => '@',
+            }));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Display;
+    use crate::{Chip8, Pixel, DEFAULT_PALETTE};
+
+    fn chip_with_sprite() -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.write_byte(0x300, 0b1000_0001).unwrap();
+        chip.index_register = 0x300;
+        chip.draw_sprite(0, 1, 1).unwrap();
+        chip
+    }
+
+    #[test]
+    pub fn test_display_queries() {
+        let chip = chip_with_sprite();
+        let display = chip.display();
+        assert_eq!((display.width(), display.height()), (64, 32));
+        assert!(display.is_lit(0, 1) && display.is_lit(7, 1));
+        assert!(matches!(display.pixel(1, 1), Pixel::Black));
+        assert_eq!(display.rows().count(), 32);
+
+        let packed = display.to_packed();
+        assert_eq!(packed[0], 0);
+        assert_eq!(packed[1], 0x81 << 56);
+
+        let hires = display.to_packed_hires();
+        assert_eq!(hires[1], 0);
+        assert_eq!((hires[2], hires[3]), (0xC003 << 112, 0xC003 << 112));
+    }
+
+    #[test]
+    pub fn test_display_conversions() {
+        let chip = chip_with_sprite();
+        let display = chip.display();
+        let rgba = display.to_rgba8(&DEFAULT_PALETTE);
+        assert_eq!(rgba.len(), 64 * 32 * 4);
+        assert_eq!(rgba[..4], [0x00, 0x00, 0x00, 0xFF]);
+        assert_eq!(rgba[64 * 4..64 * 4 + 4], [0xFF, 0xFF, 0xFF, 0xFF]);
+
+        let gray = display.to_grayscale();
+        assert_eq!((gray[64], gray[65]), (0xFF, 0x00));
+
+        let text = display.to_text();
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows.len(), 32);
+        assert_eq!(rows[0], ".".repeat(64));
+        assert_eq!(&rows[1][..9], "#......#.");
+    }
+
+    #[test]
+    pub fn test_rgba8_palette_sizes() {
+        let pixels = [Pixel::Black, Pixel::White, Pixel::Plane2, Pixel::BothPlanes];
+        let display = Display {
+            bit_map: &pixels,
+            width: 4,
+            height: 1,
+        };
+        assert_eq!(display.to_rgba8(&DEFAULT_PALETTE), DEFAULT_PALETTE.concat());
+
+        let mono = [[0x10, 0x20, 0x30, 0xFF], [0xE0, 0xD0, 0xC0, 0xFF]];
+        let rgba = display.to_rgba8(&mono);
+        assert_eq!(rgba, [mono[0], mono[1], mono[1], mono[1]].concat());
+    }
+}
@@ -0,0 +1,278 @@
+use crate::chip8::{
+    Chip8, Pixel, BITMAP_HEIGHT, BITMAP_WIDTH, HIRES_BITMAP_HEIGHT, HIRES_BITMAP_WIDTH,
+};
+use crate::display::Display;
+use crate::error::Chip8Error;
+
+impl Pixel {
+    pub(crate) fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => Pixel::Black,
+            1 => Pixel::White,
+            2 => Pixel::Plane2,
+            _ => Pixel::BothPlanes,
+        }
+    }
+}
+
+impl Chip8 {
+    // Read-only view of the screen for frontends
+    pub fn display(&self) -> Display<'_> {
+        Display::new(&self.bit_map, self.screen_width(), self.screen_height())
+    }
+
+    // Clear the selected bitplanes, which is the whole screen outside of XO-CHIP
+    pub fn clear_display(&mut self) {
+        let planes = self.planes;
+        for pixel in self.bit_map.iter_mut() {
+            *pixel = Pixel::from_bits(*pixel as u8 & !planes);
+        }
+        self.draw_flag = true;
+    }
+
+    pub(crate) fn screen_width(&self) -> usize {
+        if self.hires {
+            HIRES_BITMAP_WIDTH
+        } else {
+            BITMAP_WIDTH
+        }
+    }
+
+    pub(crate) fn screen_height(&self) -> usize {
+        if self.hires {
+            HIRES_BITMAP_HEIGHT
+        } else {
+            BITMAP_HEIGHT
+        }
+    }
+
+    // Switch between 64x32 and the SUPER-CHIP 128x64 mode, the screen is cleared either way
+    pub(crate) fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.bit_map = vec![Pixel::Black; self.screen_width() * self.screen_height()];
+        self.draw_flag = true;
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // XOR an n-byte sprite read from RAM at I onto the bit map at (x, y)
+    // n = 0 draws a SUPER-CHIP 16x16 sprite made of 16 two-byte rows instead, or nothing
+    // without the SUPER-CHIP instructions.
+    // With both XO-CHIP planes selected the sprite data for the second plane follows the first.
+    // The starting coordinate always wraps around the screen, the parts of the sprite that
+    // fall off the right or bottom edge are either clipped or wrapped depending on the clipping quirk.
+    // Returns true if any pixel was switched off (collision).
+    pub(crate) fn draw_sprite(&mut self, x: u8, y: u8, n: u8) -> Result<bool, Chip8Error> {
+        let big = n == 0 && (self.quirks.super_chip_instructions || self.xo_chip);
+        let (cols, rows) = if big { (16, 16) } else { (8, n as usize) };
+        let mut address = self.index_register as usize;
+        let mut collision = false;
+        for plane in [0b01, 0b10] {
+            if self.planes & plane == 0 {
+                continue;
+            }
+            collision |= self.draw_plane(plane, address, x, y, cols, rows)?;
+            address += rows * cols / 8;
+        }
+        self.draw_flag = true;
+        Ok(collision)
+    }
+
+    fn draw_plane(
+        &mut self,
+        plane: u8,
+        address: usize,
+        x: u8,
+        y: u8,
+        cols: usize,
+        rows: usize,
+    ) -> Result<bool, Chip8Error> {
+        let (width, height) = (self.screen_width(), self.screen_height());
+        let x0 = x as usize % width;
+        let y0 = y as usize % height;
+        let mut collision = false;
+
+        for row in 0..rows {
+            let mut py = y0 + row;
+            if py >= height {
+                if self.quirks.clipping {
+                    break;
+                }
+                py %= height;
+            }
+            // Left align the row in 16 bits so both sprite widths share the loop below
+            let row_address = address + row * cols / 8;
+            let sprite_row = if cols == 16 {
+                self.read_word(row_address)?
+            } else {
+                (self.read_byte(row_address)? as u16) << 8
+            };
+            for col in 0..cols {
+                if sprite_row & (0x8000 >> col) == 0 {
+                    continue;
+                }
+                let mut px = x0 + col;
+                if px >= width {
+                    if self.quirks.clipping {
+                        break;
+                    }
+                    px %= width;
+                }
+                let pixel = &mut self.bit_map[py * width + px];
+                if *pixel as u8 & plane != 0 {
+                    collision = true;
+                }
+                *pixel = Pixel::from_bits(*pixel as u8 ^ plane);
+            }
+        }
+        Ok(collision)
+    }
+
+    // 00CN/00DN/00FB/00FC - move the selected planes by (dx, dy) pixels
+    // Pixels scrolled off the screen are lost, blank ones come in from the other side.
+    pub(crate) fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.screen_width() as isize, self.screen_height() as isize);
+        let planes = self.planes;
+        let source = self.bit_map.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let scrolled = if (0..width).contains(&sx) && (0..height).contains(&sy) {
+                    source[(sy * width + sx) as usize] as u8
+                } else {
+                    0
+                };
+                let pixel = &mut self.bit_map[(y * width + x) as usize];
+                *pixel = Pixel::from_bits((*pixel as u8 & !planes) | (scrolled & planes));
+            }
+        }
+        self.draw_flag = true;
+    }
+
+    // Set whenever the bit map changes, frontends should redraw and then clear it
+    pub fn draw_flag(&self) -> bool {
+        self.draw_flag
+    }
+
+    pub fn clear_draw_flag(&mut self) {
+        self.draw_flag = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::{Chip8, Pixel};
+    use crate::quirks::Quirks;
+
+    fn lit(chip: &Chip8, x: usize, y: usize) -> bool {
+        chip.display().is_lit(x, y)
+    }
+
+    #[test]
+    pub fn test_draw_and_collision() {
+        let mut chip = Chip8::new();
+        chip.initialize_ram();
+        // LD I, font(0) ; DRW V0, V0, 5 ; DRW V0, V0, 5
+        chip.load_program(&[0xA0, 0x00, 0xD0, 0x05, 0xD0, 0x05])
+            .unwrap();
+        chip.emulate_cycle().unwrap();
+        chip.emulate_cycle().unwrap();
+        chip.tick_timers();
+        assert!(chip.draw_flag());
+        assert!(lit(&chip, 0, 0) && lit(&chip, 3, 4) && !lit(&chip, 1, 1));
+        assert_eq!(chip.registers[0xF], 0);
+
+        chip.emulate_cycle().unwrap();
+        assert!(chip.bit_map.iter().all(|p| matches!(p, Pixel::Black)));
+        assert_eq!(chip.registers[0xF], 1);
+    }
+
+    #[test]
+    pub fn test_clip_and_wrap() {
+        let mut chip = Chip8::new();
+        chip.write_byte(0x300, 0xFF).unwrap();
+        chip.index_register = 0x300;
+
+        // Starting coordinate wraps: x = 64 + 60
+        assert_eq!(chip.draw_sprite(124, 0, 1), Ok(false));
+        assert!(lit(&chip, 60, 0) && lit(&chip, 63, 0) && !lit(&chip, 0, 0));
+
+        chip.clear_display();
+        chip.quirks.clipping = false;
+        chip.draw_sprite(60, 0, 1).unwrap();
+        assert!(lit(&chip, 63, 0) && lit(&chip, 3, 0));
+    }
+
+    #[test]
+    pub fn test_hires_16x16_sprite() {
+        let mut chip = Chip8::new();
+        chip.set_quirks(Quirks::super_chip());
+        chip.set_hires(true);
+        for i in 0..32 {
+            chip.write_byte(0x300 + i, 0xFF).unwrap();
+        }
+        chip.index_register = 0x300;
+        assert_eq!(chip.draw_sprite(120, 60, 0), Ok(false));
+        assert_eq!(chip.display().width(), 128);
+        assert!(lit(&chip, 120, 60) && lit(&chip, 127, 63));
+        assert_eq!(
+            chip.bit_map.iter().filter(|p| **p == Pixel::White).count(),
+            8 * 4
+        );
+    }
+
+    #[test]
+    pub fn test_scrolling() {
+        let mut chip = Chip8::new();
+        chip.write_byte(0x300, 0x80).unwrap();
+        chip.index_register = 0x300;
+        chip.draw_sprite(4, 0, 1).unwrap();
+
+        chip.scroll(0, 2);
+        assert!(lit(&chip, 4, 2) && !lit(&chip, 4, 0));
+        chip.scroll(4, 0);
+        assert!(lit(&chip, 8, 2));
+        chip.scroll(-4, 0);
+        chip.scroll(-4, 0);
+        assert!(lit(&chip, 0, 2));
+        chip.scroll(-4, 0);
+        assert!(chip.bit_map.iter().all(|p| *p == Pixel::Black));
+    }
+
+    #[test]
+    pub fn test_bitplanes() {
+        let mut chip = Chip8::new();
+        // Plane 1 sprite 0xF0, plane 2 sprite 0x30
+        chip.write_byte(0x300, 0xF0).unwrap();
+        chip.write_byte(0x301, 0x30).unwrap();
+        chip.index_register = 0x300;
+        chip.planes = 0b11;
+        assert_eq!(chip.draw_sprite(0, 0, 1), Ok(false));
+        let row: Vec<Pixel> = chip.display().rows().next().unwrap()[..5].to_vec();
+        assert_eq!(
+            row,
+            [
+                Pixel::White,
+                Pixel::White,
+                Pixel::BothPlanes,
+                Pixel::BothPlanes,
+                Pixel::Black
+            ]
+        );
+
+        // Only the second plane collides, is cleared and scrolls
+        chip.planes = 0b10;
+        chip.index_register = 0x301;
+        assert_eq!(chip.draw_sprite(0, 0, 1), Ok(true));
+        assert_eq!(chip.display().pixel(2, 0), Pixel::White);
+        chip.draw_sprite(0, 0, 1).unwrap();
+        chip.scroll(0, 1);
+        assert_eq!(chip.display().pixel(2, 0), Pixel::White);
+        assert_eq!(chip.display().pixel(2, 1), Pixel::Plane2);
+        chip.clear_display();
+        assert_eq!(chip.display().pixel(2, 1), Pixel::Black);
+        assert_eq!(chip.display().pixel(0, 0), Pixel::White);
+    }
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Why the machine stopped. Once `emulate_cycle` returns an error the machine is
+/// halted and keeps returning the same error, see [`crate::Chip8::fault`].
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// `RET` with an empty call stack.
+    StackUnderflow { pc: u16 },
+    /// `CALL` nested deeper than the call stack allows.
+    StackOverflow { pc: u16 },
+    /// The word at `pc` is not an instruction.
+    InvalidOpcode { pc: u16, op: u16 },
+    /// `0NNN` asked to run native machine code at `addr`, which cannot be emulated.
+    MachineCodeCall { pc: u16, addr: u16 },
+    /// A read or write outside of RAM.
+    MemoryOutOfBounds { addr: usize },
+}
+
+/// Why [`crate::Chip8::load_state`] rejected a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// Not a snapshot at all.
+    BadMagic,
+    /// Written by a newer version of the emulator.
+    UnsupportedVersion { version: u16 },
+    /// The snapshot was damaged.
+    ChecksumMismatch,
+    /// The snapshot ends early.
+    Truncated,
+    /// The checksum matches but a field holds an impossible value.
+    InvalidData,
+}
+
+/// Why a movie could not be parsed or played back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieError {
+    /// Line `line` (1-based) of the movie file is malformed.
+    Parse { line: usize },
+    /// Written by a newer version of the emulator.
+    UnsupportedVersion { version: u32 },
+    /// The movie was recorded with a different ROM.
+    RomMismatch { expected: u32, actual: u32 },
+    /// The ROM does not fit into memory.
+    Load(Chip8Error),
+}
+
+/// Why a symbol file could not be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolError {
+    /// Line `line` (1-based) of the symbol file is malformed.
+    Parse { line: usize },
+}
+
+/// Why a trace file could not be read, see `parse_trace`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceError {
+    /// Line `line` (1-based) is neither a text nor a JSON trace record.
+    Parse { line: usize },
+}
+
+/// Why an expression for a breakpoint condition or watch was rejected.
+///
+/// Columns are 1-based character positions in the expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExprError {
+    /// The expression stopped before it was complete.
+    UnexpectedEnd,
+    /// Something that does not belong at `column`.
+    Unexpected { column: usize },
+    /// A name at `column` that is neither a register, `hitcount` nor a symbol.
+    UnknownName { column: usize },
+    /// A malformed number at `column`.
+    BadNumber { column: usize },
+}
+
+/// What a successful call to `emulate_cycle` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// One instruction was executed.
+    Executed,
+    /// Nothing was executed, an `FX0A` is waiting for a key.
+    WaitingForKey,
+    /// Nothing was executed, a `DXYN` is waiting for the next frame (display wait quirk).
+    WaitingForVblank,
+    /// Nothing was executed, the program ran the SUPER-CHIP `00FD` exit instruction.
+    Exited,
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::StackUnderflow { pc } => {
+                write!(f, "stack underflow: RET with empty stack at {:#05X}", pc)
+            }
+            Chip8Error::StackOverflow { pc } => {
+                write!(f, "stack overflow: CALL nested too deep at {:#05X}", pc)
+            }
+            Chip8Error::InvalidOpcode { pc, op } => {
+                write!(f, "invalid opcode {:#06X} at {:#05X}", op, pc)
+            }
+            Chip8Error::MachineCodeCall { pc, addr } => write!(
+                f,
+                "unsupported machine code call SYS {:#05X} at {:#05X}",
+                addr, pc
+            ),
+            Chip8Error::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {:#X}", addr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::BadMagic => write!(f, "not a CHIP-8 save state"),
+            SnapshotError::UnsupportedVersion { version } => {
+                write!(f, "save state version {} is not supported", version)
+            }
+            SnapshotError::ChecksumMismatch => write!(f, "save state is corrupted"),
+            SnapshotError::Truncated => write!(f, "save state is truncated"),
+            SnapshotError::InvalidData => write!(f, "save state contains invalid data"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Parse { line } => write!(f, "malformed movie file at line {}", line),
+            MovieError::UnsupportedVersion { version } => {
+                write!(f, "movie version {} is not supported", version)
+            }
+            MovieError::RomMismatch { expected, actual } => write!(
+                f,
+                "movie was recorded with ROM {:08X}, this ROM is {:08X}",
+                expected, actual
+            ),
+            MovieError::Load(err) => write!(f, "cannot load ROM: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolError::Parse { line } => write!(f, "malformed symbol file at line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::Parse { line } => write!(f, "malformed trace record at line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprError::UnexpectedEnd => write!(f, "expression is incomplete"),
+            ExprError::Unexpected { column } => write!(f, "unexpected input at column {}", column),
+            ExprError::UnknownName { column } => write!(f, "unknown name at column {}", column),
+            ExprError::BadNumber { column } => write!(f, "malformed number at column {}", column),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
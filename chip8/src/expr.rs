@@ -0,0 +1,373 @@
+// Expressions for breakpoint conditions and watches
+//
+//   V3 == 0x10 && I > 0x300     registers V0..VF, I, PC, SP, DT and ST
+//   [I+2] != 0                  the RAM byte at an address
+//   hitcount >= 5               times the breakpoint has been reached, this time included
+//   [sprites] == 0xF0           symbols stand for their address
+//
+// Operators and their precedence follow C, comparisons and `!` give 0 or 1. Values are
+// 64-bit signed integers, division by zero gives 0 and RAM outside the address space reads 0,
+// so an expression that parsed never fails when evaluated.
+
+use std::fmt;
+
+use crate::chip8::Chip8;
+use crate::debugger::Register;
+use crate::error::ExprError;
+use crate::symbols::SymbolMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+// Binary operators from the loosest binding to the tightest
+const LEVELS: [&[(&str, Op)]; 8] = [
+    &[("||", Op::Or)],
+    &[("&&", Op::And)],
+    &[("|", Op::BitOr)],
+    &[("^", Op::BitXor)],
+    &[("&", Op::BitAnd)],
+    &[("==", Op::Eq), ("!=", Op::Ne)],
+    &[("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt)],
+    &[("<<", Op::Shl), (">>", Op::Shr)],
+];
+const SUM: [(&str, Op); 2] = [("+", Op::Add), ("-", Op::Sub)];
+const PRODUCT: [(&str, Op); 3] = [("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)];
+
+// Longest first, so `<=` is not read as `<` and `=`
+const SYMBOLS: [&str; 23] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")", "[",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Number(i64),
+    Register(Register),
+    HitCount,
+    Memory(Box<Node>),
+    Not(Box<Node>),
+    Negate(Box<Node>),
+    Complement(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+/// A parsed breakpoint condition or watch expression, see [`Expr::evaluate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    text: String,
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Self, ExprError> {
+        Self::parse_with_symbols(text, &SymbolMap::new())
+    }
+
+    /// Parse `text`, names that are not registers are looked up in `symbols`.
+    pub fn parse_with_symbols(text: &str, symbols: &SymbolMap) -> Result<Self, ExprError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+            symbols,
+        };
+        let root = parser.expression(0)?;
+        match parser.tokens.get(parser.position) {
+            Some((_, column)) => Err(ExprError::Unexpected { column: *column }),
+            None => Ok(Self {
+                text: text.trim().to_string(),
+                root,
+            }),
+        }
+    }
+
+    /// The value of the expression for the machine as it is now.
+    ///
+    /// `hitcount` is what the name `hitcount` stands for, 0 outside of breakpoints.
+    pub fn evaluate(&self, chip: &Chip8, hitcount: u64) -> i64 {
+        evaluate(&self.root, chip, hitcount)
+    }
+
+    /// True when the expression evaluates to anything but 0.
+    pub fn is_true(&self, chip: &Chip8, hitcount: u64) -> bool {
+        self.evaluate(chip, hitcount) != 0
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+// Tokens and the column they start at
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, ExprError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        let column = index + 1;
+        if c.is_whitespace() {
+            index += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '#' || c == '$' {
+            let start = index;
+            index += 1;
+            while index < chars.len()
+                && (chars[index].is_ascii_alphanumeric() || chars[index] == '_')
+            {
+                index += 1;
+            }
+            let word: String = chars[start..index].iter().collect();
+            let token = if c.is_ascii_digit() || c == '#' || c == '$' {
+                Token::Number(parse_number(&word).ok_or(ExprError::BadNumber { column })?)
+            } else {
+                Token::Name(word)
+            };
+            tokens.push((token, column));
+        } else if c == ']' {
+            tokens.push((Token::Symbol("]"), column));
+            index += 1;
+        } else {
+            let rest: String = chars[index..].iter().take(2).collect();
+            let symbol = SYMBOLS
+                .iter()
+                .copied()
+                .find(|symbol| rest.starts_with(symbol))
+                .ok_or(ExprError::Unexpected { column })?;
+            tokens.push((Token::Symbol(symbol), column));
+            index += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+// Decimal, or hexadecimal after `0x`, `#` or `$`, or binary after `0b`
+fn parse_number(word: &str) -> Option<i64> {
+    let lower = word.to_ascii_lowercase();
+    if let Some(hex) = lower
+        .strip_prefix("0x")
+        .or_else(|| lower.strip_prefix('#'))
+        .or_else(|| lower.strip_prefix('$'))
+    {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    symbols: &'a SymbolMap,
+}
+
+impl<'a> Parser<'a> {
+    // Binary operators of `LEVELS[level]` and tighter
+    fn expression(&mut self, level: usize) -> Result<Node, ExprError> {
+        let operators: &[(&str, Op)] = match level {
+            _ if level < LEVELS.len() => LEVELS[level],
+            8 => &SUM,
+            9 => &PRODUCT,
+            _ => return self.unary(),
+        };
+        let mut left = self.expression(level + 1)?;
+        while let Some(op) = self.operator(operators) {
+            let right = self.expression(level + 1)?;
+            left = Node::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn operator(&mut self, operators: &[(&str, Op)]) -> Option<Op> {
+        if let Some((Token::Symbol(symbol), _)) = self.tokens.get(self.position) {
+            let op = operators.iter().find(|(text, _)| text == symbol)?.1;
+            self.position += 1;
+            return Some(op);
+        }
+        None
+    }
+
+    fn unary(&mut self) -> Result<Node, ExprError> {
+        let (token, column) = match self.tokens.get(self.position) {
+            Some(next) => next.clone(),
+            None => return Err(ExprError::UnexpectedEnd),
+        };
+        self.position += 1;
+        match token {
+            Token::Number(value) => Ok(Node::Number(value)),
+            Token::Name(name) => self.name(&name, column),
+            Token::Symbol("!") => Ok(Node::Not(Box::new(self.unary()?))),
+            Token::Symbol("-") => Ok(Node::Negate(Box::new(self.unary()?))),
+            Token::Symbol("~") => Ok(Node::Complement(Box::new(self.unary()?))),
+            Token::Symbol("(") => {
+                let inner = self.expression(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Token::Symbol("[") => {
+                let address = self.expression(0)?;
+                self.expect("]")?;
+                Ok(Node::Memory(Box::new(address)))
+            }
+            Token::Symbol(_) => Err(ExprError::Unexpected { column }),
+        }
+    }
+
+    fn name(&self, name: &str, column: usize) -> Result<Node, ExprError> {
+        if name.eq_ignore_ascii_case("hitcount") {
+            Ok(Node::HitCount)
+        } else if let Some(register) = Register::parse(name) {
+            Ok(Node::Register(register))
+        } else if let Some(address) = self.symbols.address(name) {
+            Ok(Node::Number(address as i64))
+        } else {
+            Err(ExprError::UnknownName { column })
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ExprError> {
+        match self.tokens.get(self.position) {
+            Some((Token::Symbol(found), _)) if *found == symbol => {
+                self.position += 1;
+                Ok(())
+            }
+            Some((_, column)) => Err(ExprError::Unexpected { column: *column }),
+            None => Err(ExprError::UnexpectedEnd),
+        }
+    }
+}
+
+fn evaluate(node: &Node, chip: &Chip8, hitcount: u64) -> i64 {
+    let eval = |node: &Node| evaluate(node, chip, hitcount);
+    match node {
+        Node::Number(value) => *value,
+        Node::Register(register) => register.read(chip) as i64,
+        Node::HitCount => hitcount as i64,
+        Node::Memory(address) => match usize::try_from(eval(address)) {
+            Ok(address) => chip.ram.get(address).copied().unwrap_or(0) as i64,
+            Err(_) => 0,
+        },
+        Node::Not(value) => (eval(value) == 0) as i64,
+        Node::Negate(value) => eval(value).wrapping_neg(),
+        Node::Complement(value) => !eval(value),
+        // Short-circuit like C, so `[I] && ...` does not look further than it has to
+        Node::Binary(Op::And, left, right) => (eval(left) != 0 && eval(right) != 0) as i64,
+        Node::Binary(Op::Or, left, right) => (eval(left) != 0 || eval(right) != 0) as i64,
+        Node::Binary(op, left, right) => {
+            let (a, b) = (eval(left), eval(right));
+            match op {
+                Op::BitOr => a | b,
+                Op::BitXor => a ^ b,
+                Op::BitAnd => a & b,
+                Op::Eq => (a == b) as i64,
+                Op::Ne => (a != b) as i64,
+                Op::Lt => (a < b) as i64,
+                Op::Le => (a <= b) as i64,
+                Op::Gt => (a > b) as i64,
+                Op::Ge => (a >= b) as i64,
+                Op::Shl => a.wrapping_shl(b as u32),
+                Op::Shr => a.wrapping_shr(b as u32),
+                Op::Add => a.wrapping_add(b),
+                Op::Sub => a.wrapping_sub(b),
+                Op::Mul => a.wrapping_mul(b),
+                Op::Div => a.checked_div(b).unwrap_or(0),
+                Op::Rem => a.checked_rem(b).unwrap_or(0),
+                Op::And | Op::Or => unreachable!("short-circuited above"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Expr;
+    use crate::{Chip8, ExprError, SymbolMap};
+
+    fn chip() -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.registers[3] = 0x10;
+        chip.index_register = 0x310;
+        chip.write_byte(0x312, 7).unwrap();
+        chip.delay_timer = 0;
+        chip
+    }
+
+    fn eval(text: &str) -> i64 {
+        Expr::parse(text).unwrap().evaluate(&chip(), 5)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(eval("V3 == 0x10 && I > 0x300"), 1);
+        assert_eq!(eval("[I+2] != 0"), 1);
+        assert_eq!(eval("[I + 2]"), 7);
+        assert_eq!(eval("hitcount >= 5"), 1);
+        assert_eq!(eval("DT == 0"), 1);
+        assert_eq!(eval("1 + 2 * 3"), 7);
+        assert_eq!(eval("(1 + 2) * 3"), 9);
+        assert_eq!(eval("1 << 4 | 1"), 17);
+        assert_eq!(eval("!v3 || -1 < 0"), 1);
+        assert_eq!(eval("#FF / 0"), 0);
+        assert_eq!(eval("[0xFFFFFF]"), 0);
+        assert_eq!(eval("pc"), 0x200);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let symbols = SymbolMap::parse("0312 counter").unwrap();
+        let expr = Expr::parse_with_symbols("[counter] == 7", &symbols).unwrap();
+        assert!(expr.is_true(&chip(), 0));
+        assert_eq!(expr.to_string(), "[counter] == 7");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Expr::parse("V3 =="), Err(ExprError::UnexpectedEnd));
+        assert_eq!(Expr::parse("[I + 2"), Err(ExprError::UnexpectedEnd));
+        assert_eq!(
+            Expr::parse("V3 = 1"),
+            Err(ExprError::Unexpected { column: 4 })
+        );
+        assert_eq!(
+            Expr::parse("V3 1"),
+            Err(ExprError::Unexpected { column: 4 })
+        );
+        assert_eq!(
+            Expr::parse("VG > 1"),
+            Err(ExprError::UnknownName { column: 1 })
+        );
+        assert_eq!(Expr::parse("0x1G"), Err(ExprError::BadNumber { column: 1 }));
+    }
+}
@@ -0,0 +1,589 @@
+// GDB remote serial protocol
+//
+// A stub that lets `gdb`, `lldb` and scripts speaking the remote protocol drive a `Chip8`
+// over a localhost TCP connection. The register file, in `g` packet order, is
+//   V0..VF    1 byte each
+//   I, PC     2 bytes each, little endian
+//   SP        1 byte, the call stack depth, read-only
+//   DT, ST    1 byte each
+// and the target memory is the machine's RAM. The layout is also served as `target.xml`.
+//
+// Supported: `?`, `g`, `G`, `p`, `P`, `m`, `M`, `c`, `s`, `Z0`..`Z4`, `z0`..`z4`, `D`, `k`,
+// Ctrl-C while running, `QStartNoAckMode` and `qXfer:features:read`. Anything else gets
+// the empty reply that means unsupported.
+
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Instant;
+
+use crate::chip8::Chip8;
+use crate::clock::Clock;
+use crate::debugger::{Debugger, Register, StopReason, WatchKind};
+use crate::error::Chip8Error;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// Registers in `g` packet order
+const REGISTER_COUNT: usize = 21;
+
+// Stop signals
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// Ctrl-C from the client while the target runs
+const INTERRUPT: u8 = 0x03;
+
+fn register(number: usize) -> Option<Register> {
+    match number {
+        0..=15 => Some(Register::V(number as u8)),
+        16 => Some(Register::I),
+        17 => Some(Register::Pc),
+        18 => Some(Register::Sp),
+        19 => Some(Register::Dt),
+        20 => Some(Register::St),
+        _ => None,
+    }
+}
+
+fn register_size(register: Register) -> usize {
+    match register {
+        Register::I | Register::Pc => 2,
+        _ => 1,
+    }
+}
+
+// What the stub does after a packet
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Reply(String),
+    Continue,
+    Step,
+    Detach,
+    Kill,
+}
+
+/// Serves one GDB remote protocol client at a time, see [`GdbStub::serve`].
+pub struct GdbStub {
+    debugger: Debugger,
+    cycles_per_frame: usize,
+    // Reply to `?`
+    last_stop: String,
+}
+
+impl GdbStub {
+    /// A stub that runs `cycles_per_frame` instructions per 60 Hz frame while continuing.
+    pub fn new(cycles_per_frame: usize) -> Self {
+        Self {
+            debugger: Debugger::new(),
+            cycles_per_frame,
+            last_stop: format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    /// Breakpoints and watchpoints set by the client, they stay between connections.
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Listen on `port` of the loopback interface only, 0 picks a free port.
+    pub fn listen(port: u16) -> io::Result<TcpListener> {
+        TcpListener::bind(("127.0.0.1", port))
+    }
+
+    /// Answer the client on `stream` until it detaches, kills the target or disconnects.
+    pub fn serve(&mut self, chip: &mut Chip8, stream: TcpStream) -> io::Result<()> {
+        let mut connection = Connection {
+            stream,
+            no_ack: false,
+        };
+        while let Some(packet) = connection.read_packet()? {
+            if packet == "QStartNoAckMode" {
+                connection.send("OK")?;
+                connection.no_ack = true;
+                continue;
+            }
+            match self.handle(chip, &packet) {
+                Action::Reply(reply) => connection.send(&reply)?,
+                Action::Continue => {
+                    let reply = self.resume(chip, &mut connection)?;
+                    connection.send(&reply)?;
+                }
+                Action::Step => {
+                    let reply = match self.debugger.step_into(chip) {
+                        Ok(stop) => self.stop_reply(stop),
+                        Err(err) => fault_reply(err),
+                    };
+                    self.last_stop = reply.clone();
+                    connection.send(&reply)?;
+                }
+                Action::Detach => {
+                    connection.send("OK")?;
+                    break;
+                }
+                Action::Kill => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, chip: &mut Chip8, packet: &str) -> Action {
+        let reply = |text: &str| Action::Reply(text.to_string());
+        let command = packet.get(..1).unwrap_or("");
+        let args = packet.get(1..).unwrap_or("");
+        match command {
+            "?" => Action::Reply(self.last_stop.clone()),
+            "g" => Action::Reply(read_registers(chip)),
+            "G" => match from_hex(args) {
+                Some(bytes) if write_registers(chip, &bytes) => reply("OK"),
+                _ => reply("E01"),
+            },
+            "p" => match usize::from_str_radix(args, 16).ok().and_then(register) {
+                Some(register) => Action::Reply(register_hex(chip, register)),
+                None => reply("E01"),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(number, value)| {
+                    let register = register(usize::from_str_radix(number, 16).ok()?)?;
+                    Some((register, from_hex(value)?))
+                });
+                match parsed {
+                    Some((register, bytes)) => {
+                        register.write(chip, little_endian(&bytes));
+                        reply("OK")
+                    }
+                    None => reply("E01"),
+                }
+            }
+            "m" => {
+                match parse_range(args).and_then(|(address, len)| read_memory(chip, address, len)) {
+                    Some(hex) => Action::Reply(hex),
+                    None => reply("E01"),
+                }
+            }
+            "M" => {
+                let written = args.split_once(':').and_then(|(range, data)| {
+                    let (address, len) = parse_range(range)?;
+                    let bytes = from_hex(data).filter(|bytes| bytes.len() == len)?;
+                    let cells = chip.ram.get_mut(address..address.checked_add(len)?)?;
+                    cells.copy_from_slice(&bytes);
+                    Some(())
+                });
+                reply(if written.is_some() { "OK" } else { "E01" })
+            }
+            "c" | "s" => {
+                // An address argument resumes from there
+                if let Ok(address) = u16::from_str_radix(args, 16) {
+                    chip.program_counter = address;
+                }
+                if command == "c" {
+                    Action::Continue
+                } else {
+                    Action::Step
+                }
+            }
+            "Z" | "z" => match self.set_point(command == "Z", args, chip.ram.len()) {
+                Some(text) => reply(text),
+                None => reply(""),
+            },
+            "D" => Action::Detach,
+            "k" => Action::Kill,
+            "H" => reply("OK"),
+            _ => self.query(packet),
+        }
+    }
+
+    fn query(&self, packet: &str) -> Action {
+        let reply = |text: &str| Action::Reply(text.to_string());
+        if packet.starts_with("qSupported") {
+            reply("PacketSize=1000;QStartNoAckMode+;qXfer:features:read+")
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_range(range)
+                .and_then(|(offset, len)| Some((offset, offset.checked_add(len)?)))
+            {
+                Some((offset, end)) => {
+                    let chunk: String =
+                        TARGET_XML.chars().skip(offset).take(end - offset).collect();
+                    let more = end < TARGET_XML.len();
+                    Action::Reply(format!("{}{}", if more { "m" } else { "l" }, chunk))
+                }
+                None => reply("E01"),
+            }
+        } else {
+            match packet {
+                "qAttached" => reply("1"),
+                "qC" => reply("QC1"),
+                "qfThreadInfo" => reply("m1"),
+                "qsThreadInfo" => reply("l"),
+                "qSymbol::" => reply("OK"),
+                _ => reply(""),
+            }
+        }
+    }
+
+    // `Z type,address,kind` and `z type,address,kind`, kind is the length for watchpoints.
+    // None for packets the stub does not support, otherwise the reply.
+    fn set_point(&mut self, insert: bool, args: &str, ram_size: usize) -> Option<&'static str> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?;
+        let address = u16::from_str_radix(fields.next()?, 16).ok()?;
+        let len = usize::from_str_radix(fields.next()?, 16).ok()?;
+        let watch = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(address);
+                } else {
+                    self.debugger.remove_breakpoint(address);
+                }
+                return Some("OK");
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::ReadWrite,
+            _ => return None,
+        };
+        if len > ram_size {
+            return Some("E01");
+        }
+        for offset in 0..len.max(1) as u16 {
+            let address = address.wrapping_add(offset);
+            if insert {
+                self.debugger.watch_memory(address, watch);
+            } else {
+                self.debugger.unwatch_memory(address);
+            }
+        }
+        Some("OK")
+    }
+
+    // Run at 60 frames per second until something stops the machine or the client interrupts
+    fn resume(&mut self, chip: &mut Chip8, connection: &mut Connection) -> io::Result<String> {
+        let reply = loop {
+            if connection.interrupted()? {
+                break format!("S{:02x}", SIGINT);
+            }
+            let start = Instant::now();
+            match self.debugger.run_frame(chip, self.cycles_per_frame) {
+                Ok(Some(stop)) => break self.stop_reply(stop),
+                Ok(None) if chip.exited() => break "W00".to_string(),
+                Ok(None) => {}
+                Err(err) => break fault_reply(err),
+            }
+            if let Some(rest) = Clock::frame_duration().checked_sub(start.elapsed()) {
+                thread::sleep(rest);
+            }
+        };
+        self.last_stop = reply.clone();
+        Ok(reply)
+    }
+
+    fn stop_reply(&self, stop: StopReason) -> String {
+        match stop {
+            StopReason::Memory(access) => {
+                let kind = self
+                    .debugger
+                    .memory_watchpoints()
+                    .find(|(address, _)| *address == access.address)
+                    .map_or(WatchKind::ReadWrite, |(_, kind)| kind);
+                let name = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::ReadWrite => "awatch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, name, access.address)
+            }
+            _ => format!("S{:02x}", SIGTRAP),
+        }
+    }
+}
+
+fn fault_reply(err: Chip8Error) -> String {
+    let signal = match err {
+        Chip8Error::InvalidOpcode { .. } | Chip8Error::MachineCodeCall { .. } => SIGILL,
+        _ => SIGSEGV,
+    };
+    format!("S{:02x}", signal)
+}
+
+fn read_registers(chip: &Chip8) -> String {
+    (0..REGISTER_COUNT)
+        .filter_map(register)
+        .map(|register| register_hex(chip, register))
+        .collect()
+}
+
+// Returns false when the data is too short for the register file
+fn write_registers(chip: &mut Chip8, bytes: &[u8]) -> bool {
+    let mut offset = 0;
+    for register in (0..REGISTER_COUNT).filter_map(register) {
+        let size = register_size(register);
+        match bytes.get(offset..offset + size) {
+            Some(value) => register.write(chip, little_endian(value)),
+            None => return false,
+        }
+        offset += size;
+    }
+    true
+}
+
+fn register_hex(chip: &Chip8, register: Register) -> String {
+    let value = register.read(chip).to_le_bytes();
+    to_hex(&value[..register_size(register)])
+}
+
+fn read_memory(chip: &Chip8, address: usize, len: usize) -> Option<String> {
+    // Reads that run off the end return what there is
+    let end = address.checked_add(len)?.min(chip.ram.len());
+    match chip.ram.get(address..end) {
+        Some(bytes) if !bytes.is_empty() || len == 0 => Some(to_hex(bytes)),
+        _ => None,
+    }
+}
+
+// `address,length` in hexadecimal
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, len) = text.split_once(',')?;
+    Some((
+        usize::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+fn little_endian(bytes: &[u8]) -> u16 {
+    bytes
+        .iter()
+        .take(2)
+        .rev()
+        .fold(0, |value, byte| (value << 8) | *byte as u16)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+// `$data#checksum` framing and acknowledgements
+struct Connection {
+    stream: TcpStream,
+    no_ack: bool,
+}
+
+impl Connection {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0u8];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // The next packet, `None` once the client has disconnected
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Skip acknowledgements and stray interrupts until a packet starts
+            match self.read_byte()? {
+                Some(b'$') => {}
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let mut sum = [0u8; 2];
+            self.stream.read_exact(&mut sum)?;
+            let data = String::from_utf8_lossy(&data).into_owned();
+            let valid = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+                == Some(checksum(&data));
+            if !self.no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(data));
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data));
+        self.stream.write_all(packet.as_bytes())?;
+        self.stream.flush()
+    }
+
+    // Poll for Ctrl-C without blocking
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let result = self.read_byte();
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(Some(byte)) => Ok(byte == INTERRUPT),
+            Ok(None) => Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "debugger disconnected",
+            )),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum, Action, GdbStub};
+    use crate::Chip8;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    // 200: LD V0, 05
+    // 202: LD I, 300
+    // 204: LD [I], V0
+    // 206: JP 206
+    const PROGRAM: [u8; 8] = [0x60, 0x05, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06];
+
+    fn chip() -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.load_program(&PROGRAM).unwrap();
+        chip
+    }
+
+    fn reply(text: &str) -> Action {
+        Action::Reply(text.to_string())
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut chip = chip();
+        let mut stub = GdbStub::new(10);
+        chip.registers[1] = 0xAB;
+        let registers = format!("00ab{}00000002000000", "00".repeat(14));
+        assert_eq!(stub.handle(&mut chip, "g"), Action::Reply(registers));
+        assert_eq!(stub.handle(&mut chip, "p11"), reply("0002"));
+        assert_eq!(stub.handle(&mut chip, "P10=3412"), reply("OK"));
+        assert_eq!(chip.index_register(), 0x1234);
+        assert_eq!(stub.handle(&mut chip, "m200,4"), reply("6005a300"));
+        assert_eq!(stub.handle(&mut chip, "M300,2:beef"), reply("OK"));
+        assert_eq!(stub.handle(&mut chip, "m300,2"), reply("beef"));
+        assert_eq!(stub.handle(&mut chip, "m1000,2"), reply("E01"));
+        // Ranges past the end of the address space are refused, not wrapped
+        assert_eq!(stub.handle(&mut chip, "mffffffffffffffff,2"), reply("E01"));
+        assert_eq!(
+            stub.handle(&mut chip, "Mffffffffffffffff,1:00"),
+            reply("E01")
+        );
+        assert_eq!(
+            stub.handle(
+                &mut chip,
+                "qXfer:features:read:target.xml:1,ffffffffffffffff"
+            ),
+            reply("E01")
+        );
+        assert_eq!(stub.handle(&mut chip, "vCont?"), reply(""));
+    }
+
+    #[test]
+    fn test_watchpoint_lengths() {
+        let mut chip = chip();
+        let mut stub = GdbStub::new(10);
+        assert_eq!(stub.handle(&mut chip, "Z2,300,1000"), reply("OK"));
+        assert_eq!(stub.handle(&mut chip, "z2,300,1000"), reply("OK"));
+        // Longer than RAM, a huge length must not wrap around into a short one
+        assert_eq!(stub.handle(&mut chip, "Z3,300,1001"), reply("E01"));
+        assert_eq!(stub.handle(&mut chip, "Z4,0,10010"), reply("E01"));
+        assert_eq!(stub.handle(&mut chip, "Z9,300,1"), reply(""));
+    }
+
+    #[test]
+    fn test_session() {
+        let listener = GdbStub::listen(0).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut chip = chip();
+            GdbStub::new(10).serve(&mut chip, stream).unwrap();
+            chip
+        });
+
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut exchange = |packet: &str| -> String {
+            let framed = format!("${}#{:02x}", packet, checksum(packet));
+            client.write_all(framed.as_bytes()).unwrap();
+            let mut received = Vec::new();
+            let mut byte = [0u8];
+            // Acknowledgement, then the reply up to its checksum
+            while !received.ends_with(b"#") {
+                client.read_exact(&mut byte).unwrap();
+                received.push(byte[0]);
+            }
+            let mut sum = [0u8; 2];
+            client.read_exact(&mut sum).unwrap();
+            let text = String::from_utf8(received).unwrap();
+            let start = text.find('$').unwrap();
+            text[start + 1..text.len() - 1].to_string()
+        };
+        assert_eq!(exchange("?"), "S05");
+        assert_eq!(exchange("Z0,204,2"), "OK");
+        assert_eq!(exchange("c"), "S05");
+        assert_eq!(exchange("p11"), "0402");
+        assert_eq!(exchange("z0,204,2"), "OK");
+        assert_eq!(exchange("Z2,300,1"), "OK");
+        assert_eq!(exchange("c"), "T05watch:300;");
+        assert_eq!(exchange("s"), "S05");
+        assert_eq!(exchange("D"), "OK");
+        let chip = server.join().unwrap();
+        assert_eq!(chip.program_counter(), 0x206);
+    }
+}
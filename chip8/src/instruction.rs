@@ -1,217 +1,290 @@
-use crate::chip8::{self, Chip8};
-use lazy_static::*;
-//use rand::prelude::*;
-
-use std::default;
-type OpCode = u16;
-type Instr = fn(&mut Chip8) -> ();
-pub(crate) type InstructionSet = Vec<Instruction>;
-
-#[derive(serde::Deserialize, serde::Serialize, default::Default)]
-#[serde(default)]
-pub struct Instruction {
-    dissassembly: String,
-    operandlength: usize,
-    #[serde(skip)]
-    execute: Option<Instr>,
-}
-impl Instruction {
-    fn new(disassembly: &str, operandlength: usize, execute: Instr) -> Self {
-        Self {
-            dissassembly: disassembly.to_string(),
-            operandlength,
-            execute: Some(execute),
+use crate::chip8::Chip8;
+use crate::decode::DecodedOp;
+use crate::error::Chip8Error;
+
+impl Chip8 {
+    // Execute a decoded opcode against the machine state
+    pub(crate) fn execute(&mut self, op: DecodedOp) -> Result<(), Chip8Error> {
+        match op {
+            DecodedOp::Sys { nnn } => sys(self, nnn),
+            DecodedOp::Cls => cls(self),
+            DecodedOp::Ret => ret(self),
+            DecodedOp::ScrollDown { n } => scd(self, n),
+            DecodedOp::ScrollUp { n } => scu(self, n),
+            DecodedOp::ScrollRight => scr(self),
+            DecodedOp::ScrollLeft => scl(self),
+            DecodedOp::Exit => exit(self),
+            DecodedOp::Lores => low(self),
+            DecodedOp::Hires => high(self),
+            DecodedOp::Jp { nnn } => jp(self, nnn),
+            DecodedOp::Call { nnn } => call(self, nnn),
+            DecodedOp::SeVxByte { x, kk } => se_vx_byte(self, x, kk),
+            DecodedOp::SneVxByte { x, kk } => sne_vx_byte(self, x, kk),
+            DecodedOp::SeVxVy { x, y } => se_vx_vy(self, x, y),
+            DecodedOp::SaveVxVy { x, y } => save_vx_vy(self, x, y),
+            DecodedOp::LoadVxVy { x, y } => load_vx_vy(self, x, y),
+            DecodedOp::LdVxByte { x, kk } => ld_vx_byte(self, x, kk),
+            DecodedOp::AddVxByte { x, kk } => add_vx_byte(self, x, kk),
+            DecodedOp::LdVxVy { x, y } => ld_vx_vy(self, x, y),
+            DecodedOp::OrVxVy { x, y } => or_vx_vy(self, x, y),
+            DecodedOp::AndVxVy { x, y } => and_vx_vy(self, x, y),
+            DecodedOp::XorVxVy { x, y } => xor_vx_vy(self, x, y),
+            DecodedOp::AddVxVy { x, y } => add_vx_vy(self, x, y),
+            DecodedOp::SubVxVy { x, y } => sub_vx_vy(self, x, y),
+            DecodedOp::ShrVxVy { x, y } => shr_vx_vy(self, x, y),
+            DecodedOp::SubnVxVy { x, y } => subn_vx_vy(self, x, y),
+            DecodedOp::ShlVxVy { x, y } => shl_vx_vy(self, x, y),
+            DecodedOp::SneVxVy { x, y } => sne_vx_vy(self, x, y),
+            DecodedOp::LdINnn { nnn } => ld_i_nnn(self, nnn),
+            DecodedOp::JpV0Nnn { nnn } => jp_nnn(self, nnn),
+            DecodedOp::RndVxByte { x, kk } => rnd_vx_kk(self, x, kk),
+            DecodedOp::DrwVxVyN { x, y, n } => drw_vx_vy_n(self, x, y, n),
+            DecodedOp::SkpVx { x } => skp_vx(self, x),
+            DecodedOp::SknpVx { x } => sknp_vx(self, x),
+            DecodedOp::LdILong => ld_i_long(self),
+            DecodedOp::Plane { n } => plane(self, n),
+            DecodedOp::Audio => audio(self),
+            DecodedOp::LdVxDt { x } => ld_vx_dt(self, x),
+            DecodedOp::LdVxK { x } => ld_vx_k(self, x),
+            DecodedOp::LdDtVx { x } => ld_dt_vx(self, x),
+            DecodedOp::LdStVx { x } => ld_st_vx(self, x),
+            DecodedOp::AddIVx { x } => add_i_vx(self, x),
+            DecodedOp::LdFVx { x } => ld_f_vx(self, x),
+            DecodedOp::LdHfVx { x } => ld_hf_vx(self, x),
+            DecodedOp::LdBVx { x } => ld_b_vx(self, x),
+            DecodedOp::PitchVx { x } => pitch_vx(self, x),
+            DecodedOp::LdIVx { x } => ld_i_vx(self, x),
+            DecodedOp::LdVxI { x } => ld_vx_mem_val(self, x),
+            DecodedOp::LdRVx { x } => ld_r_vx(self, x),
+            DecodedOp::LdVxR { x } => ld_vx_r(self, x),
+            DecodedOp::Invalid(op) => Err(Chip8Error::InvalidOpcode {
+                pc: self.op_address(),
+                op,
+            }),
         }
     }
-
-    pub fn execute(&self) -> Instr {
-        self.execute.unwrap()
-    }
 }
 
-lazy_static! {
-    pub static ref INSTRUCTION_SET: Vec<fn(&mut Chip8)> = {
-        vec![cls_or_ret,
-        jp,
-        call,
-        se_vx_byte,
-        sne_vx_byte,
-        se_vx_vy,
-        ld_vx_byte,
-        add_vx_byte,
-        op_vx_vy,
-        sne_vx_vy,
-        ld_i_nnn,
-        jp_nnn,
-        rnd_vx_kk,
-        drw_vx_vy_n,
-        e_ops,
-        f_ops]
-        /*
-          Seperately calls:
-            ld_vx_dt, ld_vx_k, ld_dt_vx, ld_st_vx, add_i_vx, ld_f_vx, ld_b_vx, ld_i_vx. ld_vx_i
-
-         */
-
-    };
-}
-
-pub fn cls_or_ret(chip: &mut Chip8) {
-    if chip.curr_op & 0xF == 1 {
-        cls(chip);
-    } else {
-        ret(chip);
-    }
+// [0NNN] - Jump to a machine code routine at nnn
+// Only meaningful on the original COSMAC VIP hardware, which ran RCA 1802 code at nnn.
+// Machine code routines cannot be emulated, so the call is reported instead of being skipped.
+pub fn sys(chip: &mut Chip8, nnn: u16) -> Result<(), Chip8Error> {
+    Err(Chip8Error::MachineCodeCall {
+        pc: chip.op_address(),
+        addr: nnn,
+    })
 }
 
 // [00E0] - Clear the Display
-// - Requires out put to work.
-pub fn cls(_chip: &mut Chip8) {
-    todo!("Clear the display")
+pub fn cls(chip: &mut Chip8) -> Result<(), Chip8Error> {
+    chip.clear_display();
+    Ok(())
 }
 
 // [00EE] - Return from a subroutine
 // Interpreter sets the PC to the address at the top of the stack then subtracts 1 from the stack pointer.
-pub fn ret(chip: &mut Chip8) {
+pub fn ret(chip: &mut Chip8) -> Result<(), Chip8Error> {
     //  Set PC to the top address at the top of the stack
-    let top_of_stack = chip.pop_stack();
+    let top_of_stack = chip.pop_stack()?;
     chip.program_counter = top_of_stack;
+    Ok(())
+}
+
+// [00CN] - SCD nibble (SUPER-CHIP)
+// Scroll the display down by n pixels.
+pub fn scd(chip: &mut Chip8, n: u8) -> Result<(), Chip8Error> {
+    chip.scroll(0, n as isize);
+    Ok(())
+}
+
+// [00DN] - SCU nibble (XO-CHIP)
+// Scroll the display up by n pixels.
+pub fn scu(chip: &mut Chip8, n: u8) -> Result<(), Chip8Error> {
+    chip.scroll(0, -(n as isize));
+    Ok(())
+}
+
+// [00FB] - SCR (SUPER-CHIP)
+// Scroll the display right by 4 pixels.
+pub fn scr(chip: &mut Chip8) -> Result<(), Chip8Error> {
+    chip.scroll(4, 0);
+    Ok(())
+}
+
+// [00FC] - SCL (SUPER-CHIP)
+// Scroll the display left by 4 pixels.
+pub fn scl(chip: &mut Chip8) -> Result<(), Chip8Error> {
+    chip.scroll(-4, 0);
+    Ok(())
+}
+
+// [00FD] - EXIT (SUPER-CHIP)
+// Exit the interpreter, `emulate_cycle` reports `StepOutcome::Exited` from now on.
+pub fn exit(chip: &mut Chip8) -> Result<(), Chip8Error> {
+    chip.exited = true;
+    Ok(())
+}
+
+// [00FE] - LOW (SUPER-CHIP)
+// Switch to the 64x32 display.
+pub fn low(chip: &mut Chip8) -> Result<(), Chip8Error> {
+    chip.set_hires(false);
+    Ok(())
+}
+
+// [00FF] - HIGH (SUPER-CHIP)
+// Switch to the 128x64 display.
+pub fn high(chip: &mut Chip8) -> Result<(), Chip8Error> {
+    chip.set_hires(true);
+    Ok(())
 }
 
 // [1NNN] Jump to location NNN
 // The interpreter sets the program counter to nnn
-pub fn jp(chip: &mut Chip8) {
-    let nnn = chip.curr_op & 0x0FFF;
+pub fn jp(chip: &mut Chip8, nnn: u16) -> Result<(), Chip8Error> {
     chip.program_counter = nnn;
+    Ok(())
 }
 
 // [2NNN] Call subroutine at NNN
 // Increments stack pointer, puts the current PC on the top of the stack. PC is then set to NNN
-pub fn call(chip: &mut Chip8) {
-    let nnn = chip.curr_op & 0x0FFF;
-    chip.push_stack(chip.program_counter);
+pub fn call(chip: &mut Chip8, nnn: u16) -> Result<(), Chip8Error> {
+    chip.push_stack(chip.program_counter)?;
     chip.program_counter = nnn;
+    Ok(())
 }
 
 // [3XKK] SE Vx, Byte
 // Skip next instruction if Vx ( Register X ) = Byte
-pub fn se_vx_byte(chip: &mut Chip8) {
-    let vx = (chip.curr_op >> 2) & 0x0F;
-    let byte: u8 = chip.curr_op as u8;
-    if chip.registers[vx as usize] == byte {
-        chip.program_counter += 2;
+pub fn se_vx_byte(chip: &mut Chip8, x: u8, byte: u8) -> Result<(), Chip8Error> {
+    if chip.registers[x as usize] == byte {
+        skip_next(chip);
     }
+    Ok(())
 }
 
 // [4XKK] SNE Vx, Byte
 // Skip next instruction if Vx ( Register X ) != Byte
-pub fn sne_vx_byte(chip: &mut Chip8) {
-    let vx = (chip.curr_op >> 2) & 0x0F;
-    let byte: u8 = chip.curr_op as u8;
-    if chip.registers[vx as usize] != byte {
-        chip.program_counter += 2;
+pub fn sne_vx_byte(chip: &mut Chip8, x: u8, byte: u8) -> Result<(), Chip8Error> {
+    if chip.registers[x as usize] != byte {
+        skip_next(chip);
     }
+    Ok(())
 }
 
 // [5XY0] SE Vx, Vy
 // Skip next instruction if Vx = Vy (Register_X = Register_Y)
-pub fn se_vx_vy(chip: &mut Chip8) {
-    let vx = (chip.curr_op >> 2) & 0x0F;
-    let vy = (chip.curr_op >> 1) & 0x00F;
-    if chip.registers[vx as usize] == chip.registers[vy as usize] {
-        chip.program_counter += 2;
+pub fn se_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    if chip.registers[x as usize] == chip.registers[y as usize] {
+        skip_next(chip);
+    }
+    Ok(())
+}
+
+// [5XY2] SAVE Vx - Vy (XO-CHIP)
+// Store registers Vx through Vy in memory starting at location I, I is not changed.
+// The range is inclusive and is stored in descending order when x > y.
+pub fn save_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    let i = chip.index_register as usize;
+    for (offset, r) in register_range(x, y).enumerate() {
+        chip.write_byte(i + offset, chip.registers[r])?;
     }
+    Ok(())
+}
+
+// [5XY3] LOAD Vx - Vy (XO-CHIP)
+// Read registers Vx through Vy from memory starting at location I, I is not changed.
+pub fn load_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    let i = chip.index_register as usize;
+    for (offset, r) in register_range(x, y).enumerate() {
+        chip.registers[r] = chip.read_byte(i + offset)?;
+    }
+    Ok(())
+}
+
+// Registers x to y inclusive, counting down when x > y
+fn register_range(x: u8, y: u8) -> impl Iterator<Item = usize> {
+    let (x, y) = (x as usize, y as usize);
+    let len = x.abs_diff(y) + 1;
+    (0..len).map(move |n| if x <= y { x + n } else { x - n })
 }
 
 // [6XKK] Load Vx, Byte
 // Set Vx = Byte
-pub fn ld_vx_byte(chip: &mut Chip8) {
-    let _vx = (chip.curr_op >> 8) & 0x0F;
-    let _byte = (chip.curr_op & 0xFF) as u8;
-    chip.registers[_vx as usize] = _byte;
+pub fn ld_vx_byte(chip: &mut Chip8, x: u8, byte: u8) -> Result<(), Chip8Error> {
+    chip.registers[x as usize] = byte;
+    Ok(())
 }
 
 // [7XKK] ADD Vx, Byte
 // Set Vx = Vx + Byte
-pub fn add_vx_byte(chip: &mut Chip8) {
-    let vx = (chip.curr_op >> 2) & 0x0F;
-    let byte = chip.curr_op as u8;
-    chip.registers[vx as usize] += byte;
-}
-
-pub fn op_vx_vy(chip: &mut Chip8) {
-    match chip.curr_op & 0xF {
-        0x0 => ld_vx_vy(chip),
-        0x1 => or_vx_vy(chip),
-        0x2 => and_vx_vy(chip),
-        0x3 => xor_vx_vy(chip),
-        0x4 => add_vx_vy(chip),
-        0x5 => sub_vx_vy(chip),
-        0x6 => shr_vx_vy(chip),
-        0x7 => subn_vx_vy(chip),
-        0xE => shl_vx_vy(chip),
-        _ => unreachable!(),
-    }
+pub fn add_vx_byte(chip: &mut Chip8, x: u8, byte: u8) -> Result<(), Chip8Error> {
+    chip.registers[x as usize] = chip.registers[x as usize].wrapping_add(byte);
+    Ok(())
 }
 
 // 8xy0 - LD Vx, Vy
 // Set Vx = Vy.
 // Stores the value of register Vy in register Vx.
-pub fn ld_vx_vy(chip: &mut Chip8) {
-    let vx = (chip.curr_op >> 2) & 0x0F;
-    let vy = (chip.curr_op >> 1) & 0x00F;
-    chip.registers[vx as usize] = chip.registers[vy as usize];
+pub fn ld_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    chip.registers[x as usize] = chip.registers[y as usize];
+    Ok(())
 }
 // 8xy1 - OR Vx, Vy
 // Set Vx = Vx OR Vy.
 // PERFORMS a bitwise OR on the values of Vx and Vy
 // THEN stores the result in Vx.
-pub fn or_vx_vy(chip: &mut Chip8) {
-    let vx = (chip.curr_op >> 2) & 0x0F;
-    let vy = (chip.curr_op >> 1) & 0x00F;
-    chip.registers[vx as usize] |= chip.registers[vy as usize];
+pub fn or_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    chip.registers[x as usize] |= chip.registers[y as usize];
+    vf_reset(chip);
+    Ok(())
 }
 
 // 8xy2 - AND Vx, Vy
 // Set Vx = Vx AND Vy.
 // Performs a bitwise AND on the values of Vx and Vy, then stores the result in Vx.
-pub fn and_vx_vy(chip: &mut Chip8) {
-    let vx = (chip.curr_op >> 2) & 0x0F;
-    let vy = (chip.curr_op >> 1) & 0x00F;
-    chip.registers[vx as usize] &= chip.registers[vy as usize];
+pub fn and_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    chip.registers[x as usize] &= chip.registers[y as usize];
+    vf_reset(chip);
+    Ok(())
 }
 // 8xy3 - XOR Vx, Vy
 // Set Vx = Vx XOR Vy.
 // Performs a bitwise exclusive OR on the values of Vx and Vy, then stores the result in Vx.
 //An exclusive OR compares the corrseponding bits from two values, and if the bits are not both the same, then the corresponding bit in the result is set to 1. Otherwise, it is 0.
-pub fn xor_vx_vy(chip: &mut Chip8) {
-    let vx = (chip.curr_op >> 2) & 0x0F;
-    let vy = (chip.curr_op >> 1) & 0x00F;
-    chip.registers[vx as usize] ^= chip.registers[vy as usize];
+pub fn xor_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    chip.registers[x as usize] ^= chip.registers[y as usize];
+    vf_reset(chip);
+    Ok(())
+}
+
+// The COSMAC VIP clobbers VF on the logic operations (vf_reset quirk)
+fn vf_reset(chip: &mut Chip8) {
+    if chip.quirks.vf_reset {
+        chip.registers[0xF] = 0;
+    }
 }
 
 // 8xy4 - ADD Vx, Vy
 // Set Vx = Vx + Vy, set VF = carry.
 // The values of Vx and Vy are added together.
 // If the result is greater than 8 bits (i.e., > 255,) VF is set to 1, otherwise 0. Only the lowest 8 bits of the result are kept, and stored in Vx.
-pub fn add_vx_vy(chip: &mut Chip8) {
-    let vx = (chip.curr_op >> 2) & 0x0F;
-    let vy = (chip.curr_op >> 1) & 0x00F;
-
-    let res: u16 = (chip.registers[vx as usize] + chip.registers[vy as usize]) as u16;
-    chip.registers[vx as usize] = res as u8;
-    chip.registers[0xF_usize] = (res > 255) as u8;
+pub fn add_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    let (res, carry) = chip.registers[x as usize].overflowing_add(chip.registers[y as usize]);
+    chip.registers[x as usize] = res;
+    chip.registers[0xF_usize] = carry as u8;
+    Ok(())
 }
 
 // 8xy5 - SUB Vx, Vy
 // Set Vx = Vx - Vy, set VF = NOT borrow.
-// If Vx > Vy, then VF is set to 1, otherwise 0. Then Vy is subtracted from Vx, and the results stored in Vx.
-pub fn sub_vx_vy(chip: &mut Chip8) {
-    let vx = (chip.curr_op >> 2) & 0x0F;
-    let vy = (chip.curr_op >> 1) & 0x00F;
-    chip.registers[0xF_usize] = (vx > vy) as u8;
-    let res = chip.registers[vx as usize] + chip.registers[vy as usize];
-    chip.registers[vx as usize] = res;
+// If Vx >= Vy, then VF is set to 1, otherwise 0. Then Vy is subtracted from Vx, and the results stored in Vx.
+pub fn sub_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    let vx = chip.registers[x as usize];
+    let vy = chip.registers[y as usize];
+    chip.registers[x as usize] = vx.wrapping_sub(vy);
+    chip.registers[0xF_usize] = (vx >= vy) as u8;
+    Ok(())
 }
 
 // 8xy6 - SHR Vx {, Vy}
@@ -219,147 +292,579 @@ pub fn sub_vx_vy(chip: &mut Chip8) {
 // Store the value of register VY shifted right one bit in register VX¹
 // Set register VF to the least significant bit prior to the shift
 // VY is unchanged
-pub fn shr_vx_vy(chip: &mut Chip8) {
-    let x = (chip.curr_op >> 8) & 0xF;
-    let y = (chip.curr_op >> 4) & 0xF;
-    let vy = chip.registers[y as usize];
-    // Check lsb of vy
-    chip.registers[0xF] = vy & 0b1;
-
-    chip.registers[x as usize] = vy >> 1;
+// ¹ With the shift quirk Vx is shifted in place and Vy is ignored.
+pub fn shr_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    let src = if chip.quirks.shift { x } else { y };
+    let value = chip.registers[src as usize];
+    chip.registers[x as usize] = value >> 1;
+    // Check lsb of the source, VF is written last so it wins when X = F
+    chip.registers[0xF] = value & 0b1;
+    Ok(())
 }
 // 8xy7 - SUBN Vx, Vy
 // Set Vx = Vy - Vx, set VF = NOT borrow.
-// If Vy > Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from Vy, and the results stored in Vx.
-fn subn_vx_vy(chip: &mut Chip8) {
-    let y = ((chip.curr_op >> 4) & 0xF) as usize;
-    let x = ((chip.curr_op >> 8) & 0xF) as usize;
-    let vy = chip.registers[y];
-    let vx = chip.registers[x];
-    chip.registers[0xF] = u8::from(vy > vx);
-    chip.registers[x] = vy.wrapping_sub(vx);
+// If Vy >= Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from Vy, and the results stored in Vx.
+fn subn_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    let vy = chip.registers[y as usize];
+    let vx = chip.registers[x as usize];
+    chip.registers[x as usize] = vy.wrapping_sub(vx);
+    chip.registers[0xF] = u8::from(vy >= vx);
+    Ok(())
 }
 
 // 8xyE - SHL Vx {, Vy}
-// Set Vx = Vx SHL VY.
-// If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0. Then Vx is set to Vx shifted left by Vy.
-fn shl_vx_vy(chip: &mut Chip8) {
-    let y = ((chip.curr_op >> 4) & 0xF) as usize;
-    let x = ((chip.curr_op >> 8) & 0xF) as usize;
-    let vx = chip.registers[x];
-    let vy = chip.registers[y];
-    let lsb_vx = u8::from(vx & 0x80 == 0x80);
-    chip.registers[x] = vx.wrapping_shl(vy as u32);
+// Set Vx = Vy SHL 1.
+// Store the value of register VY shifted left one bit in register VX, VF is set to the most-significant bit prior to the shift.
+// With the shift quirk Vx is shifted in place and Vy is ignored.
+fn shl_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    let src = if chip.quirks.shift { x } else { y };
+    let value = chip.registers[src as usize];
+    chip.registers[x as usize] = value << 1;
+    chip.registers[0xF] = value >> 7;
+    Ok(())
 }
 // 9xy0 - SNE Vx, Vy
 // Skip next instruction if Vx != Vy.
 // The values of Vx and Vy are compared, and if they are not equal, the program counter is increased by 2.
-pub fn sne_vx_vy(chip: &mut Chip8) {
-    let y = ((chip.curr_op >> 4) & 0xF) as usize;
-    let x = ((chip.curr_op >> 8) & 0xF) as usize;
-    let vx = chip.registers[x];
-    let vy = chip.registers[y];
+pub fn sne_vx_vy(chip: &mut Chip8, x: u8, y: u8) -> Result<(), Chip8Error> {
+    let vx = chip.registers[x as usize];
+    let vy = chip.registers[y as usize];
     if vy.ne(&vx) {
-        chip.program_counter += 2;
+        skip_next(chip);
     }
+    Ok(())
 }
 // Annn - LD I, nnn
 // Set I = nnn.
 // The value of register I is set to nnn.
-pub fn ld_i_nnn(chip: &mut Chip8) {
-    chip.index_register = chip.curr_op & 0x0FFF;
+pub fn ld_i_nnn(chip: &mut Chip8, nnn: u16) -> Result<(), Chip8Error> {
+    chip.index_register = nnn;
+    Ok(())
 }
 
 // Bnnn - JP V0, addr
 // Jump to location nnn + V0
 // The program counter is set to nnn plus the value of V0.
-pub fn jp_nnn(chip: &mut Chip8) {
-    let _v0 = chip.registers[0] as u16;
-    let _nnn = chip.curr_op & (0x0FFF);
-    chip.program_counter = _nnn + _v0;
+// With the jump quirk this is BXNN instead: jump to xnn + Vx.
+pub fn jp_nnn(chip: &mut Chip8, nnn: u16) -> Result<(), Chip8Error> {
+    let x = if chip.quirks.jump { nnn >> 8 } else { 0 };
+    let offset = chip.registers[x as usize] as u16;
+    chip.program_counter = nnn + offset;
+    Ok(())
 }
 
 // Cxkk - RND Vx, byte
 // Set Vx = random byte AND kk.
 // The interpreter generates a random number from 0 to 255, which is then ANDed with the value kk. The results are stored in Vx. See instruction 8xy2 for more information on AND.
-pub fn rnd_vx_kk(chip: &mut Chip8) {
-    let rnd = rand::random::<u8>();
-    let kk = chip.curr_op as u8;
-    let vx = (chip.curr_op >> 2) & 0x0F;
-    chip.registers[vx as usize] = rnd & kk;
+// The number comes from the machine's own seedable generator, see `Chip8::with_seed`.
+pub fn rnd_vx_kk(chip: &mut Chip8, x: u8, kk: u8) -> Result<(), Chip8Error> {
+    let rnd = chip.random_byte();
+    chip.registers[x as usize] = rnd & kk;
+    Ok(())
 }
 
 // Dxyn - DRW Vx, Vy, nibble
 // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
-fn drw_vx_vy_n(chip: &mut Chip8) {
-    let curr_op = chip.curr_op;
-    let sprite_size = curr_op & 0xF;
-    let v_i = chip.index_register;
-}
 // The interpreter reads n bytes from memory, starting at the address stored in I.
 // These bytes are then displayed as sprites on screen at coordinates (Vx, Vy).
 // Sprites are XORed onto the existing screen. If this causes any pixels to be erased, VF is set to 1, otherwise it is set to 0.
 // If the sprite is positioned so part of it is outside the coordinates of the display
 // it wraps around to the opposite side of the screen. See instruction 8xy3 for more information on XOR, and section 2.4, Display, for more information on the Chip-8 screen and sprites.
-
-fn e_ops(chip: &mut Chip8) {}
+// SUPER-CHIP: with n = 0 a 16x16 sprite of 32 bytes is drawn instead.
+fn drw_vx_vy_n(chip: &mut Chip8, x: u8, y: u8, n: u8) -> Result<(), Chip8Error> {
+    let vx = chip.registers[x as usize];
+    let vy = chip.registers[y as usize];
+    let collision = chip.draw_sprite(vx, vy, n)?;
+    chip.registers[0xF] = collision as u8;
+    // Display wait quirk: no more instructions until the next frame
+    chip.vblank_wait = chip.quirks.display_wait;
+    Ok(())
+}
 
 // Ex9E - SKP Vx
 // Skip next instruction if key with the value of Vx is pressed.
 // Checks the keyboard, and if the key corresponding to the value of Vx is currently in the down position, PC is increased by 2.
-fn skp_vx(chip: &mut Chip8) {}
+fn skp_vx(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    let key = chip.registers[x as usize] & 0xF;
+    if chip.keypad.is_pressed(key) {
+        skip_next(chip);
+    }
+    Ok(())
+}
 
 // ExA1 - SKNP Vx
 // Skip next instruction if key with the value of Vx is not pressed.
 // Checks the keyboard, and if the key corresponding to the value of Vx is currently in the up position, PC is increased by 2.
-fn sknp_vx(chip: &mut Chip8) {}
+fn sknp_vx(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    let key = chip.registers[x as usize] & 0xF;
+    if !chip.keypad.is_pressed(key) {
+        skip_next(chip);
+    }
+    Ok(())
+}
 
-fn f_ops(chip: &mut Chip8) {}
+// F000 NNNN - LD I, long NNNN (XO-CHIP)
+// Set I = the 16-bit address stored in the word after the opcode, then skip over that word.
+fn ld_i_long(chip: &mut Chip8) -> Result<(), Chip8Error> {
+    chip.index_register = chip.fetch_word(chip.program_counter as usize)?;
+    chip.program_counter = chip.program_counter.wrapping_add(2);
+    Ok(())
+}
+
+// FN01 - PLANE n (XO-CHIP)
+// Select the bitplanes that drawing, clearing and scrolling act on, n is a 2-bit mask.
+fn plane(chip: &mut Chip8, n: u8) -> Result<(), Chip8Error> {
+    chip.planes = n & 0b11;
+    Ok(())
+}
+
+// F002 - AUDIO (XO-CHIP)
+// Load the 16-byte audio pattern buffer from memory starting at location I.
+fn audio(chip: &mut Chip8) -> Result<(), Chip8Error> {
+    let i = chip.index_register as usize;
+    for offset in 0..chip.audio_pattern.len() {
+        chip.audio_pattern[offset] = chip.read_byte(i + offset)?;
+    }
+    Ok(())
+}
 
 // Fx07 - LD Vx, DT
 // Set Vx = delay timer value.
 // The value of DT is placed into Vx.
-fn ld_vx_dt(chip: &mut Chip8) {}
+fn ld_vx_dt(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    chip.registers[x as usize] = chip.delay_timer;
+    Ok(())
+}
 
 // Fx0A - LD Vx, K
 // Wait for a key press, store the value of the key in Vx.
 // All execution stops until a key is pressed, then the value of that key is stored in Vx.
-fn ld_vx_k(chip: &mut Chip8) {}
+// The key is delivered by `Chip8::press`/`Chip8::release`, see `KeyWaitMode`.
+fn ld_vx_k(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    chip.begin_key_wait(x);
+    Ok(())
+}
 
 // Fx15 - LD DT, Vx
 // Set delay timer = Vx.
 // DT is set equal to the value of Vx.
-fn ld_dt_vx(chip: &mut Chip8) {}
+fn ld_dt_vx(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    chip.delay_timer = chip.registers[x as usize];
+    Ok(())
+}
 
 // Fx18 - LD ST, Vx
 // Set sound timer = Vx.
 // ST is set equal to the value of Vx.
-fn ld_st_vx(chip: &mut Chip8) {}
+fn ld_st_vx(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    chip.sound_timer = chip.registers[x as usize];
+    Ok(())
+}
 
 // Fx1E - ADD I, Vx
 // Set I = I + Vx.
 // The values of I and Vx are added, and the results are stored in I.
-fn add_i_vx(chip: &mut Chip8) {}
+fn add_i_vx(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    chip.index_register = chip
+        .index_register
+        .wrapping_add(chip.registers[x as usize] as u16);
+    Ok(())
+}
 
 // Fx29 - LD F, Vx
 // Set I = location of sprite for digit Vx.
 // The value of I is set to the location for the hexadecimal sprite corresponding to the value of Vx. See section 2.4, Display, for more information on the Chip-8 hexadecimal font.
-fn ld_f_vx(chip: &mut Chip8) {}
+fn ld_f_vx(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    chip.index_register = Chip8::font_address(chip.registers[x as usize]);
+    Ok(())
+}
+
+// Fx30 - LD HF, Vx (SUPER-CHIP)
+// Set I = location of the large 8x10 sprite for digit Vx.
+fn ld_hf_vx(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    chip.index_register = Chip8::big_font_address(chip.registers[x as usize]);
+    Ok(())
+}
 
 // Fx33 - LD B, Vx
 // Store BCD representation of Vx in memory locations I, I+1, and I+2.
 // The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at location in I, the tens digit at location I+1, and the ones digit at location I+2.
-fn ld_b_vx(chip: &mut Chip8) {}
+fn ld_b_vx(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    let vx = chip.registers[x as usize];
+    let i = chip.index_register as usize;
+    chip.write_byte(i, vx / 100)?;
+    chip.write_byte(i + 1, (vx / 10) % 10)?;
+    chip.write_byte(i + 2, vx % 10)?;
+    Ok(())
+}
+
+// Fx3A - PITCH Vx (XO-CHIP)
+// Set the playback rate of the audio pattern, see `Chip8::audio_sample_rate`.
+fn pitch_vx(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    chip.pitch = chip.registers[x as usize];
+    Ok(())
+}
 
 // Fx55 - LD [I], Vx
 // Store registers V0 through Vx in memory starting at location I.
 // The interpreter copies the values of registers V0 through Vx into memory, starting at the address in I.
-fn ld_i_vx(chip: &mut Chip8) {}
+fn ld_i_vx(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    let i = chip.index_register as usize;
+    for r in 0..=x as usize {
+        chip.write_byte(i + r, chip.registers[r])?;
+    }
+    memory_increment(chip, x);
+    Ok(())
+}
 
 // Fx65 - LD Vx, [I]
 // Read registers V0 through Vx from memory starting at location I.
 // The interpreter reads values from memory starting at location I into registers V0 through Vx.
-fn ld_vx_mem_val(chip: &mut Chip8) {}
+fn ld_vx_mem_val(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    let i = chip.index_register as usize;
+    for r in 0..=x as usize {
+        chip.registers[r] = chip.read_byte(i + r)?;
+    }
+    memory_increment(chip, x);
+    Ok(())
+}
+
+// Fx75 - LD R, Vx (SUPER-CHIP)
+// Store V0 through Vx in the RPL user flags, the HP-48 only had 8 of them but XO-CHIP allows all 16.
+fn ld_r_vx(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    let n = x as usize + 1;
+    chip.rpl[..n].copy_from_slice(&chip.registers[..n]);
+    Ok(())
+}
+
+// Fx85 - LD Vx, R (SUPER-CHIP)
+// Read V0 through Vx from the RPL user flags.
+fn ld_vx_r(chip: &mut Chip8, x: u8) -> Result<(), Chip8Error> {
+    let n = x as usize + 1;
+    chip.registers[..n].copy_from_slice(&chip.rpl[..n]);
+    Ok(())
+}
+
+// Skip the next instruction, XO-CHIP's four byte F000 NNNN is skipped as a whole
+fn skip_next(chip: &mut Chip8) {
+    let next = chip.fetch_word(chip.program_counter as usize);
+    let len = if chip.xo_chip && next == Ok(0xF000) {
+        4
+    } else {
+        2
+    };
+    chip.program_counter = chip.program_counter.wrapping_add(len);
+}
+
+// The COSMAC VIP leaves I past the last register transferred, the CHIP-48 on it
+// (memory_increment and memory_increment_by_x quirks)
+fn memory_increment(chip: &mut Chip8, x: u8) {
+    if chip.quirks.memory_increment {
+        let by_x = chip.quirks.memory_increment_by_x;
+        let step = if by_x { x as u16 } else { x as u16 + 1 };
+        chip.index_register = chip.index_register.wrapping_add(step);
+    }
+}
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use crate::{Chip8, Chip8Error, Quirks, StepOutcome};
+
+    fn run(program: &[u8], cycles: usize) -> Chip8 {
+        run_with(Quirks::default(), program, cycles)
+    }
+
+    fn run_with(quirks: Quirks, program: &[u8], cycles: usize) -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.set_quirks(quirks);
+        chip.initialize_ram();
+        chip.load_program(program).unwrap();
+        for _ in 0..cycles {
+            chip.emulate_cycle().unwrap();
+        }
+        chip
+    }
+
+    fn run_xo_chip(program: &[u8], cycles: usize) -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.set_quirks(Quirks::xo_chip());
+        chip.set_xo_chip(true);
+        chip.initialize_ram();
+        chip.load_program(program).unwrap();
+        for _ in 0..cycles {
+            chip.emulate_cycle().unwrap();
+        }
+        chip
+    }
+
+    #[test]
+    fn test_operands_hit_high_registers() {
+        // LD VA, 0x12 ; ADD VA, 0x01 ; SE VA, 0x13 ; LD V0, 0xFF ; LD VB, VA
+        let chip = run(
+            &[0x6A, 0x12, 0x7A, 0x01, 0x3A, 0x13, 0x60, 0xFF, 0x8B, 0xA0],
+            4,
+        );
+        assert_eq!(chip.registers[0xA], 0x13);
+        assert_eq!(chip.registers[0xB], 0x13);
+        assert_eq!(chip.registers[0x0], 0x00);
+    }
+
+    #[test]
+    fn test_sub_vx_vy() {
+        // LD V1, 0x05 ; LD V2, 0x07 ; SUB V1, V2
+        let chip = run(&[0x61, 0x05, 0x62, 0x07, 0x81, 0x25], 3);
+        assert_eq!(chip.registers[1], 0xFE);
+        assert_eq!(chip.registers[0xF], 0);
+    }
+
+    #[test]
+    fn test_sub_equal_operands_does_not_borrow() {
+        // LD V0, 0x09 ; SUB V0, V0
+        let chip = run(&[0x60, 0x09, 0x80, 0x05], 2);
+        assert_eq!(chip.registers[0], 0);
+        assert_eq!(chip.registers[0xF], 1);
+
+        // LD V1, 0x09 ; LD V2, 0x09 ; LD VF, 0x00 ; SUBN V1, V2
+        let chip = run(&[0x61, 0x09, 0x62, 0x09, 0x6F, 0x00, 0x81, 0x27], 4);
+        assert_eq!(chip.registers[1], 0);
+        assert_eq!(chip.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_bcd_and_register_dump() {
+        // LD V0, 234 ; LD I, 0x300 ; LD B, V0 ; LD V2, [I]
+        let chip = run(&[0x60, 0xEA, 0xA3, 0x00, 0xF0, 0x33, 0xF2, 0x65], 4);
+        assert_eq!(chip.read_byte(0x300), Ok(2));
+        assert_eq!(chip.read_byte(0x301), Ok(3));
+        assert_eq!(chip.read_byte(0x302), Ok(4));
+        assert_eq!(chip.registers[..3], [2, 3, 4]);
+    }
+
+    #[test]
+    fn test_font_and_timers() {
+        // LD V1, 0x0A ; LD F, V1 ; LD DT, V1 ; LD V2, DT
+        let chip = run(&[0x61, 0x0A, 0xF1, 0x29, 0xF1, 0x15, 0xF2, 0x07], 4);
+        assert_eq!(chip.index_register, 0x0A * 5);
+        assert_eq!(chip.registers[2], 0x0A);
+    }
+
+    #[test]
+    fn test_ld_vx_k_waits_for_key() {
+        let mut chip = run(&[0xF3, 0x0A, 0x12, 0x00], 3);
+        assert_eq!(chip.program_counter, 0x202);
+        assert!(chip.waiting_for_key());
+
+        chip.press(0x7);
+        chip.release(0x7);
+        assert_eq!(chip.registers[3], 0x7);
+    }
+
+    #[test]
+    fn test_skp_and_sknp() {
+        // LD V0, 0x04 ; SKP V0 ; LD V1, 0x01 ; SKNP V0 ; LD V2, 0x01
+        let mut chip = Chip8::new();
+        chip.load_program(&[0x60, 0x04, 0xE0, 0x9E, 0x61, 0x01, 0xE0, 0xA1, 0x62, 0x01])
+            .unwrap();
+        chip.press(0x4);
+        for _ in 0..4 {
+            chip.emulate_cycle().unwrap();
+        }
+        assert_eq!(chip.registers[1], 0x00);
+        assert_eq!(chip.registers[2], 0x01);
+    }
+
+    #[test]
+    fn test_sys_is_unsupported() {
+        let mut chip = run(&[], 0);
+        chip.load_program(&[0x01, 0x23]).unwrap();
+        assert_eq!(
+            chip.emulate_cycle(),
+            Err(Chip8Error::MachineCodeCall {
+                pc: 0x200,
+                addr: 0x123
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_opcode() {
+        let mut chip = run(&[0x60, 0x01, 0x81, 0x2F], 1);
+        assert_eq!(
+            chip.emulate_cycle(),
+            Err(Chip8Error::InvalidOpcode {
+                pc: 0x202,
+                op: 0x812F
+            })
+        );
+    }
+
+    #[test]
+    fn test_add_wraps_with_carry() {
+        // LD V0, 0xFF ; ADD V0, 0x02 ; LD V1, 0xFF ; ADD V0, V1
+        let chip = run(&[0x60, 0xFF, 0x70, 0x02, 0x61, 0xFF, 0x80, 0x14], 4);
+        assert_eq!(chip.registers[0], 0x00);
+        assert_eq!(chip.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_shift_quirk() {
+        // LD V0, 0x81 ; LD V1, 0x04 ; SHR V0, V1 ; SHL V1, V0
+        let program = &[0x60, 0x81, 0x61, 0x04, 0x80, 0x16, 0x81, 0x0E];
+        let vip = run_with(Quirks::cosmac_vip(), program, 4);
+        assert_eq!(vip.registers[..2], [0x02, 0x04]);
+        assert_eq!(vip.registers[0xF], 0);
+
+        let schip = run_with(Quirks::super_chip(), program, 4);
+        assert_eq!(schip.registers[..2], [0x40, 0x08]);
+        assert_eq!(schip.registers[0xF], 0);
+
+        // SHR VF, VF: the flag wins
+        let flag = run_with(Quirks::super_chip(), &[0x6F, 0x03, 0x8F, 0xF6], 2);
+        assert_eq!(flag.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_jump_quirk() {
+        // LD V0, 0x04 ; LD V3, 0x10 ; JP V0, 0x300
+        let program = &[0x60, 0x04, 0x63, 0x10, 0xB3, 0x00];
+        assert_eq!(
+            run_with(Quirks::cosmac_vip(), program, 3).program_counter,
+            0x304
+        );
+        assert_eq!(
+            run_with(Quirks::super_chip(), program, 3).program_counter,
+            0x310
+        );
+    }
+
+    #[test]
+    fn test_memory_and_vf_reset_quirks() {
+        // LD I, 0x300 ; LD [I], V2 ; LD VF, 0x01 ; OR V0, V1
+        let program = &[0xA3, 0x00, 0xF2, 0x55, 0x6F, 0x01, 0x80, 0x11];
+        let vip = run_with(Quirks::cosmac_vip(), program, 4);
+        assert_eq!(vip.index_register, 0x303);
+        assert_eq!(vip.registers[0xF], 0);
+
+        let schip = run_with(Quirks::super_chip(), program, 4);
+        assert_eq!(schip.index_register, 0x300);
+        assert_eq!(schip.registers[0xF], 1);
+
+        let chip48 = run_with(Quirks::chip48(), program, 4);
+        assert_eq!(chip48.index_register, 0x302);
+        assert_eq!(chip48.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_display_wait_quirk() {
+        // DRW V0, V0, 1 ; LD V1, 0x01
+        let mut chip = run_with(Quirks::cosmac_vip(), &[0xD0, 0x01, 0x61, 0x01], 1);
+        assert_eq!(chip.emulate_cycle(), Ok(StepOutcome::WaitingForVblank));
+        chip.tick_timers();
+        chip.emulate_cycle().unwrap();
+        assert_eq!(chip.registers[1], 0x01);
+
+        let chip = run_with(Quirks::xo_chip(), &[0xD0, 0x01, 0x61, 0x01], 2);
+        assert_eq!(chip.registers[1], 0x01);
+    }
+
+    #[test]
+    fn test_super_chip_display_ops() {
+        // HIGH ; LD I, 0x20A ; DRW V0, V0, 0 ; SCR ; EXIT ; 16x16 sprite whose first row is 0xC000
+        let program = [
+            0x00, 0xFF, 0xA2, 0x0A, 0xD0, 0x00, 0x00, 0xFB, 0x00, 0xFD, 0xC0, 0x00,
+        ];
+        let mut chip = run_with(Quirks::super_chip(), &program, 5);
+        assert!(chip.is_hires());
+        let display = chip.display();
+        assert_eq!(display.width(), 128);
+        assert!(!display.is_lit(0, 0) && display.is_lit(4, 0) && display.is_lit(5, 0));
+        assert_eq!(chip.emulate_cycle(), Ok(StepOutcome::Exited));
+        assert!(chip.exited());
+        assert_eq!(chip.program_counter, 0x20A);
+    }
+
+    #[test]
+    fn test_big_font_and_rpl_flags() {
+        // LD V0, 0x0A ; LD V1, 0x22 ; LD HF, V0 ; LD R, V1 ; LD V0, 0 ; LD V1, 0 ; LD V1, R
+        let program = [
+            0x60, 0x0A, 0x61, 0x22, 0xF0, 0x30, 0xF1, 0x75, 0x60, 0x00, 0x61, 0x00, 0xF1, 0x85,
+        ];
+        let chip = run_with(Quirks::super_chip(), &program, 7);
+        assert_eq!(chip.index_register, 0x50 + 10 * 0xA);
+        assert_eq!(chip.ram[chip.index_register as usize], 0x7E);
+        assert_eq!(chip.registers[..2], [0x0A, 0x22]);
+    }
+
+    #[test]
+    fn test_extensions_need_their_platform() {
+        // HIGH is a machine code call and SAVE V1 - V2 invalid on the VIP
+        let mut chip = run(&[0x00, 0xFF, 0x51, 0x22], 0);
+        assert_eq!(
+            chip.emulate_cycle(),
+            Err(Chip8Error::MachineCodeCall {
+                pc: 0x200,
+                addr: 0x0FF
+            })
+        );
+        assert!(!chip.is_hires());
+        let mut chip = run_with(Quirks::super_chip(), &[0x00, 0xFF, 0x51, 0x22], 1);
+        assert!(chip.is_hires());
+        assert_eq!(
+            chip.emulate_cycle(),
+            Err(Chip8Error::InvalidOpcode {
+                pc: 0x202,
+                op: 0x5122
+            })
+        );
+    }
+
+    #[test]
+    fn test_xo_chip_long_load_and_skip() {
+        // SE V0, 0 ; I := long 0x1234 ; LD V1, 0x01 ; I := long 0xFFF0
+        let program = [
+            0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x61, 0x01, 0xF0, 0x00, 0xFF, 0xF0,
+        ];
+        let mut chip = run_xo_chip(&program, 0);
+        chip.run_frame(3).unwrap();
+        assert_eq!(chip.registers[1], 0x01);
+        assert_eq!(chip.index_register, 0xFFF0);
+        assert_eq!(chip.program_counter, 0x20C);
+    }
+
+    #[test]
+    fn test_xo_chip_register_ranges_and_audio() {
+        // LD I, 0x300 ; LD V1, 0xAA ; LD V2, 0xBB ; SAVE V2 - V1 ; LOAD V3 - V4 ; AUDIO ; PITCH V1
+        let program = [
+            0xA3, 0x00, 0x61, 0xAA, 0x62, 0xBB, 0x52, 0x12, 0x53, 0x43, 0xF0, 0x02, 0xF1, 0x3A,
+        ];
+        let chip = run_xo_chip(&program, 7);
+        assert_eq!(chip.ram[0x300..0x302], [0xBB, 0xAA]);
+        assert_eq!(chip.registers[3..5], [0xBB, 0xAA]);
+        assert_eq!(chip.index_register, 0x300);
+        assert_eq!(chip.audio_pattern()[..2], [0xBB, 0xAA]);
+        assert_eq!(chip.pitch(), 0xAA);
+    }
+
+    #[test]
+    fn test_xo_chip_plane_select() {
+        // PLANE 2 ; LD I, 0x208 ; DRW V0, V0, 1 ; JP 0x206 ; sprite 0x80
+        let program = [0xF2, 0x01, 0xA2, 0x08, 0xD0, 0x01, 0x12, 0x06, 0x80];
+        let chip = run_xo_chip(&program, 3);
+        assert_eq!(chip.display().pixel(0, 0), crate::Pixel::Plane2);
+    }
+
+    #[test]
+    fn test_rnd_is_seeded() {
+        // RND V0, 0xFF ; RND V1, 0x0F ; JP 0x200
+        let program = [0xC0, 0xFF, 0xC1, 0x0F, 0x12, 0x00];
+        let run_seeded = |seed| {
+            let mut chip = Chip8::with_seed(seed);
+            chip.load_program(&program).unwrap();
+            chip.run_frame(30).unwrap();
+            chip.registers
+        };
+        assert_eq!(run_seeded(1), run_seeded(1));
+        assert_ne!(run_seeded(1), run_seeded(2));
+        assert!(run_seeded(1)[1] <= 0x0F);
+    }
+}
@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+use super::Bit;
+
+/// Number of keys on the hexadecimal keypad, `0x0..=0xF`.
+pub const KEY_COUNT: usize = 16;
+
+/// State of the 16-key hexadecimal keypad.
+///
+/// The original layout is
+/// ```text
+/// 1 2 3 C
+/// 4 5 6 D
+/// 7 8 9 E
+/// A 0 B F
+/// ```
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Keypad {
+    keys: [Bit; KEY_COUNT],
+}
+
+impl Keypad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keys outside of `0x0..=0xF` are ignored.
+    pub fn press(&mut self, key: u8) {
+        if let Some(state) = self.keys.get_mut(key as usize) {
+            *state = true;
+        }
+    }
+
+    /// Keys outside of `0x0..=0xF` are ignored.
+    pub fn release(&mut self, key: u8) {
+        if let Some(state) = self.keys.get_mut(key as usize) {
+            *state = false;
+        }
+    }
+
+    /// False for keys outside of `0x0..=0xF`.
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys.get(key as usize).copied().unwrap_or(false)
+    }
+
+    /// Iterate over the keys that are currently held down, lowest first.
+    pub fn pressed(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..KEY_COUNT as u8).filter(move |&key| self.is_pressed(key))
+    }
+
+    pub fn release_all(&mut self) {
+        self.keys = [false; KEY_COUNT];
+    }
+}
+
+/// When a pending `FX0A` (LD Vx, K) completes.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyWaitMode {
+    /// As soon as a key goes down.
+    Press,
+    /// When a key that went down during the wait is released again, as on the COSMAC VIP.
+    Release,
+}
+
+impl Default for KeyWaitMode {
+    fn default() -> Self {
+        KeyWaitMode::Release
+    }
+}
+
+/// A pending `FX0A`, waiting to store a key into `register`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct KeyWait {
+    pub(crate) register: u8,
+    // Key pressed during the wait, only used in `KeyWaitMode::Release`
+    pub(crate) pressed: Option<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Keypad;
+
+    #[test]
+    pub fn test_press_and_release() {
+        let mut keypad = Keypad::new();
+        keypad.press(0xA);
+        keypad.press(0x3);
+        assert!(keypad.is_pressed(0xA));
+        assert_eq!(keypad.pressed().collect::<Vec<_>>(), vec![0x3, 0xA]);
+
+        keypad.release(0xA);
+        assert!(!keypad.is_pressed(0xA));
+        keypad.release_all();
+        assert_eq!(keypad.pressed().count(), 0);
+    }
+
+    #[test]
+    pub fn test_invalid_key() {
+        let mut keypad = Keypad::new();
+        keypad.press(0x10);
+        keypad.release(0xFF);
+        assert!(!keypad.is_pressed(0x10));
+        assert_eq!(keypad.pressed().count(), 0);
+    }
+}
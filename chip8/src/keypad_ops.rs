@@ -0,0 +1,118 @@
+use crate::chip8::Chip8;
+use crate::keypad::{KeyWait, KeyWaitMode, Keypad, KEY_COUNT};
+
+impl Chip8 {
+    pub fn keypad(&self) -> &Keypad {
+        &self.keypad
+    }
+
+    // Key went down, completes a pending FX0A in press mode
+    // Keys outside of 0x0..=0xF are ignored, here and in `release`
+    pub fn press(&mut self, key: u8) {
+        if key as usize >= KEY_COUNT {
+            return;
+        }
+        self.keypad.press(key);
+        match (self.key_wait_mode, &mut self.key_wait) {
+            (_, None) => {}
+            (KeyWaitMode::Press, Some(_)) => self.finish_key_wait(key),
+            (KeyWaitMode::Release, Some(wait)) => {
+                wait.pressed.get_or_insert(key);
+            }
+        }
+    }
+
+    // Key went up, completes a pending FX0A in release mode if it is the key pressed during the wait
+    pub fn release(&mut self, key: u8) {
+        if key as usize >= KEY_COUNT {
+            return;
+        }
+        self.keypad.release(key);
+        if let Some(wait) = self.key_wait {
+            if self.key_wait_mode == KeyWaitMode::Release && wait.pressed == Some(key) {
+                self.finish_key_wait(key);
+            }
+        }
+    }
+
+    pub fn key_wait_mode(&self) -> KeyWaitMode {
+        self.key_wait_mode
+    }
+
+    pub fn set_key_wait_mode(&mut self, mode: KeyWaitMode) {
+        self.key_wait_mode = mode;
+    }
+
+    // True while an FX0A is blocking execution
+    pub fn waiting_for_key(&self) -> bool {
+        self.key_wait.is_some()
+    }
+
+    pub(crate) fn begin_key_wait(&mut self, register: u8) {
+        self.key_wait = Some(KeyWait {
+            register,
+            pressed: None,
+        });
+    }
+
+    fn finish_key_wait(&mut self, key: u8) {
+        if let Some(wait) = self.key_wait.take() {
+            self.registers[wait.register as usize] = key;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Chip8, KeyWaitMode};
+
+    fn waiting_chip(mode: KeyWaitMode) -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.set_key_wait_mode(mode);
+        // LD V3, K ; LD V4, 0x01
+        chip.load_program(&[0xF3, 0x0A, 0x64, 0x01]).unwrap();
+        chip.emulate_cycle().unwrap();
+        chip.emulate_cycle().unwrap();
+        chip
+    }
+
+    #[test]
+    pub fn test_key_wait_on_press() {
+        let mut chip = waiting_chip(KeyWaitMode::Press);
+        assert!(chip.waiting_for_key());
+        assert_eq!(chip.registers[4], 0);
+
+        chip.press(0x10);
+        assert!(chip.waiting_for_key());
+        chip.press(0x7);
+        assert!(!chip.waiting_for_key());
+        assert_eq!(chip.registers[3], 0x7);
+        chip.emulate_cycle().unwrap();
+        assert_eq!(chip.registers[4], 1);
+    }
+
+    #[test]
+    pub fn test_key_wait_on_release() {
+        let mut chip = waiting_chip(KeyWaitMode::Release);
+        chip.press(0x7);
+        chip.press(0x2);
+        assert!(chip.waiting_for_key());
+
+        chip.release(0x2);
+        assert!(chip.waiting_for_key());
+        chip.release(0x7);
+        assert!(!chip.waiting_for_key());
+        assert_eq!(chip.registers[3], 0x7);
+    }
+
+    #[test]
+    pub fn test_held_key_does_not_complete_wait() {
+        let mut chip = Chip8::new();
+        chip.set_key_wait_mode(KeyWaitMode::Release);
+        chip.press(0x5);
+        chip.load_program(&[0xF3, 0x0A]).unwrap();
+        chip.emulate_cycle().unwrap();
+        chip.release(0x5);
+        assert!(chip.waiting_for_key());
+    }
+}
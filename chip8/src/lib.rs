@@ -1,15 +1,57 @@
 pub mod chip8;
+mod analysis;
+mod clock;
+mod dap;
+mod debugger;
+mod decode;
+mod disasm;
+mod display;
+mod display_ops;
+mod error;
+mod expr;
+mod gdb;
 mod instruction;
+mod keypad;
+mod keypad_ops;
+mod movie;
+mod quirks;
 mod ram_ops;
+mod rewind;
+mod rng;
+mod snapshot;
 mod stack_ops;
+mod symbols;
 mod test_rom;
+mod timer_ops;
+mod trace;
+mod trace_diff;
 mod utils;
-pub use self::chip8::Chip8;
+pub use self::analysis::{analyze, analyze_with_symbols, Analysis, ByteKind};
+pub use self::chip8::{Chip8, Pixel};
+pub use self::clock::{Clock, InstructionRate, TIMER_HZ};
+pub use self::dap::DapServer;
+pub use self::debugger::{Debugger, Register, StopReason, WatchKind};
+pub use self::decode::{decode, decode_for, DecodedOp};
+pub use self::disasm::{disassemble, Line, LineKind, Listing, Syntax};
+pub use self::display::{Display, DEFAULT_PALETTE};
+pub use self::error::{
+    Chip8Error, ExprError, MovieError, SnapshotError, StepOutcome, SymbolError, TraceError,
+};
+pub use self::expr::Expr;
+pub use self::gdb::GdbStub;
+pub use self::keypad::{KeyWaitMode, Keypad, KEY_COUNT};
+pub use self::movie::{Movie, MovieEvent, MoviePlayer, MovieRecorder};
+pub use self::quirks::Quirks;
+pub use self::ram_ops::MemoryAccess;
+pub use self::rewind::RewindBuffer;
+pub use self::snapshot::SNAPSHOT_VERSION;
+pub use self::symbols::SymbolMap;
+pub use self::trace::{TraceFormat, TraceRecord, Tracer};
+pub use self::trace_diff::{diff_traces, parse_trace, TraceDiff, TraceField};
 
 use std::collections::VecDeque;
 type Bit = bool;
 type Word = u16;
 type Byte = u8;
-type Tribble = u16;
-type Ram = [u8; 4096];
+type Ram = Vec<u8>;
 type Stack = VecDeque<u16>;
@@ -0,0 +1,438 @@
+// Input movies
+//
+// A movie is a ROM run from power-on plus every keypad change and the frame it
+// happened before. Keys only change between frames and the machine is otherwise
+// deterministic, so replaying the events through `press`/`release` at the same frames
+// reproduces the run bit for bit.
+//
+// Movie files are plain text so they can be attached to bug reports and diffed:
+//   CHIP8-MOVIE 1
+//   rom-crc32 1A2B3C4D
+//   seed 434849502D380000
+//   cycles-per-frame 11
+//   quirks memory_increment vf_reset display_wait clipping
+//   xo-chip 0
+//   key-wait release
+//   frames 600
+//   120 +5
+//   126 -5
+// Event lines are `<frame> +<key>` for a press and `<frame> -<key>` for a release.
+
+use std::fmt::Write;
+
+use crate::chip8::Chip8;
+use crate::error::{Chip8Error, MovieError};
+use crate::keypad::{KeyWaitMode, KEY_COUNT};
+use crate::quirks::Quirks;
+use crate::rng::DEFAULT_SEED;
+use crate::snapshot::crc32;
+
+const MOVIE_VERSION: u32 = 1;
+
+/// A keypad change, delivered before frame `frame` runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MovieEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// Everything needed to replay a recorded run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    /// CRC-32 of the ROM the movie was recorded with.
+    pub rom_crc32: u32,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub xo_chip: bool,
+    pub key_wait_mode: KeyWaitMode,
+    /// Instructions per frame, movies need a fixed rate to be reproducible.
+    pub cycles_per_frame: usize,
+    /// Length of the recording in frames.
+    pub frames: u64,
+    /// Keypad changes in the order they happened.
+    pub events: Vec<MovieEvent>,
+}
+
+impl Movie {
+    /// An empty movie for `rom` with the default machine settings.
+    pub fn new(rom: &[u8], cycles_per_frame: usize) -> Self {
+        Self {
+            rom_crc32: crc32(rom),
+            seed: DEFAULT_SEED,
+            quirks: Quirks::default(),
+            xo_chip: false,
+            key_wait_mode: KeyWaitMode::default(),
+            cycles_per_frame,
+            frames: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// The machine the movie starts from: `rom` loaded into a freshly powered on `Chip8`.
+    pub fn power_on(&self, rom: &[u8]) -> Result<Chip8, MovieError> {
+        let actual = crc32(rom);
+        if actual != self.rom_crc32 {
+            return Err(MovieError::RomMismatch {
+                expected: self.rom_crc32,
+                actual,
+            });
+        }
+        let mut chip = Chip8::with_seed(self.seed);
+        chip.set_quirks(self.quirks);
+        chip.set_xo_chip(self.xo_chip);
+        chip.set_key_wait_mode(self.key_wait_mode);
+        chip.initialize_ram();
+        chip.load_program(rom).map_err(MovieError::Load)?;
+        Ok(chip)
+    }
+
+    /// Play the whole movie without a frontend and return the final machine.
+    ///
+    /// A machine that faults stays halted, check [`Chip8::fault`] on the result.
+    pub fn replay(&self, rom: &[u8]) -> Result<Chip8, MovieError> {
+        let mut chip = self.power_on(rom)?;
+        let mut player = MoviePlayer::new(self.clone());
+        while !player.finished() {
+            if player.run_frame(&mut chip).is_err() {
+                break;
+            }
+        }
+        Ok(chip)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "CHIP8-MOVIE {}", MOVIE_VERSION);
+        let _ = writeln!(out, "rom-crc32 {:08X}", self.rom_crc32);
+        let _ = writeln!(out, "seed {:016X}", self.seed);
+        let _ = writeln!(out, "cycles-per-frame {}", self.cycles_per_frame);
+        let quirks: Vec<&str> = quirk_flags(&self.quirks)
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect();
+        let _ = writeln!(out, "quirks {}", quirks.join(" "));
+        let _ = writeln!(out, "xo-chip {}", self.xo_chip as u8);
+        let key_wait = match self.key_wait_mode {
+            KeyWaitMode::Press => "press",
+            KeyWaitMode::Release => "release",
+        };
+        let _ = writeln!(out, "key-wait {}", key_wait);
+        let _ = writeln!(out, "frames {}", self.frames);
+        for event in &self.events {
+            let sign = if event.pressed { '+' } else { '-' };
+            let _ = writeln!(out, "{} {}{:X}", event.frame, sign, event.key);
+        }
+        out
+    }
+
+    pub fn parse(text: &str) -> Result<Self, MovieError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let (line, magic) = lines.next().ok_or(MovieError::Parse { line: 1 })?;
+        let version = match magic.split_once(' ') {
+            Some(("CHIP8-MOVIE", version)) => {
+                version.parse().map_err(|_| MovieError::Parse { line })?
+            }
+            _ => return Err(MovieError::Parse { line }),
+        };
+        if version == 0 || version > MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion { version });
+        }
+
+        let end = text.lines().count() + 1;
+        let mut movie = Movie::new(&[], 0);
+        let mut header = |key: &str| -> Result<(usize, &str), MovieError> {
+            let (line, text) = lines.next().ok_or(MovieError::Parse { line: end })?;
+            match text.split_once(' ') {
+                Some((found, value)) if found == key => Ok((line, value.trim())),
+                None if text == key => Ok((line, "")),
+                _ => Err(MovieError::Parse { line }),
+            }
+        };
+
+        let (line, value) = header("rom-crc32")?;
+        movie.rom_crc32 = u32::from_str_radix(value, 16).map_err(|_| MovieError::Parse { line })?;
+        let (line, value) = header("seed")?;
+        movie.seed = u64::from_str_radix(value, 16).map_err(|_| MovieError::Parse { line })?;
+        let (line, value) = header("cycles-per-frame")?;
+        movie.cycles_per_frame = value.parse().map_err(|_| MovieError::Parse { line })?;
+
+        let (line, value) = header("quirks")?;
+        let mut quirks = Quirks {
+            shift: false,
+            jump: false,
+            memory_increment: false,
+            memory_increment_by_x: false,
+            vf_reset: false,
+            display_wait: false,
+            clipping: false,
+            super_chip_instructions: false,
+        };
+        for name in value.split_whitespace() {
+            match name {
+                "shift" => quirks.shift = true,
+                "jump" => quirks.jump = true,
+                "memory_increment" => quirks.memory_increment = true,
+                "memory_increment_by_x" => quirks.memory_increment_by_x = true,
+                "vf_reset" => quirks.vf_reset = true,
+                "display_wait" => quirks.display_wait = true,
+                "clipping" => quirks.clipping = true,
+                "super_chip_instructions" => quirks.super_chip_instructions = true,
+                _ => return Err(MovieError::Parse { line }),
+            }
+        }
+        movie.quirks = quirks;
+
+        let (line, value) = header("xo-chip")?;
+        movie.xo_chip = match value {
+            "0" => false,
+            "1" => true,
+            _ => return Err(MovieError::Parse { line }),
+        };
+        let (line, value) = header("key-wait")?;
+        movie.key_wait_mode = match value {
+            "press" => KeyWaitMode::Press,
+            "release" => KeyWaitMode::Release,
+            _ => return Err(MovieError::Parse { line }),
+        };
+        let (line, value) = header("frames")?;
+        movie.frames = value.parse().map_err(|_| MovieError::Parse { line })?;
+
+        for (line, text) in lines {
+            let event = parse_event(text).ok_or(MovieError::Parse { line })?;
+            let in_order = movie
+                .events
+                .last()
+                .map_or(true, |last| last.frame <= event.frame);
+            if !in_order || event.frame >= movie.frames {
+                return Err(MovieError::Parse { line });
+            }
+            movie.events.push(event);
+        }
+        Ok(movie)
+    }
+}
+
+fn quirk_flags(quirks: &Quirks) -> [(&'static str, bool); 8] {
+    [
+        ("shift", quirks.shift),
+        ("jump", quirks.jump),
+        ("memory_increment", quirks.memory_increment),
+        ("memory_increment_by_x", quirks.memory_increment_by_x),
+        ("vf_reset", quirks.vf_reset),
+        ("display_wait", quirks.display_wait),
+        ("clipping", quirks.clipping),
+        ("super_chip_instructions", quirks.super_chip_instructions),
+    ]
+}
+
+fn parse_event(text: &str) -> Option<MovieEvent> {
+    let (frame, change) = text.split_once(' ')?;
+    let pressed = match change.chars().next()? {
+        '+' => true,
+        '-' => false,
+        _ => return None,
+    };
+    let key = u8::from_str_radix(&change[1..], 16).ok()?;
+    if key as usize >= KEY_COUNT {
+        return None;
+    }
+    Some(MovieEvent {
+        frame: frame.parse().ok()?,
+        key,
+        pressed,
+    })
+}
+
+/// Records keypad input while driving the machine one frame at a time.
+///
+/// Start from [`Movie::power_on`], then route every key and frame through the recorder.
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    pub fn new(mut movie: Movie) -> Self {
+        movie.frames = 0;
+        movie.events.clear();
+        Self { movie }
+    }
+
+    pub fn press(&mut self, chip: &mut Chip8, key: u8) {
+        chip.press(key);
+        self.record(key, true);
+    }
+
+    pub fn release(&mut self, chip: &mut Chip8, key: u8) {
+        chip.release(key);
+        self.record(key, false);
+    }
+
+    pub fn run_frame(&mut self, chip: &mut Chip8) -> Result<(), Chip8Error> {
+        self.movie.frames += 1;
+        chip.run_frame(self.movie.cycles_per_frame)
+    }
+
+    /// Frames recorded so far.
+    pub fn frame(&self) -> u64 {
+        self.movie.frames
+    }
+
+    /// The recorded movie. Key changes after the last frame never reached the machine and
+    /// are dropped.
+    pub fn finish(mut self) -> Movie {
+        let frames = self.movie.frames;
+        self.movie.events.retain(|event| event.frame < frames);
+        self.movie
+    }
+
+    fn record(&mut self, key: u8, pressed: bool) {
+        self.movie.events.push(MovieEvent {
+            frame: self.movie.frames,
+            key,
+            pressed,
+        });
+    }
+}
+
+/// Feeds a movie's input back into a machine started with [`Movie::power_on`].
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: u64,
+    next_event: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        Self {
+            movie,
+            frame: 0,
+            next_event: 0,
+        }
+    }
+
+    /// Deliver the input recorded before the current frame, then run it.
+    pub fn run_frame(&mut self, chip: &mut Chip8) -> Result<(), Chip8Error> {
+        while let Some(event) = self.movie.events.get(self.next_event) {
+            if event.frame > self.frame {
+                break;
+            }
+            if event.pressed {
+                chip.press(event.key);
+            } else {
+                chip.release(event.key);
+            }
+            self.next_event += 1;
+        }
+        self.frame += 1;
+        chip.run_frame(self.movie.cycles_per_frame)
+    }
+
+    /// Frames played so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.movie.frames
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Movie, MovieRecorder};
+    use crate::{MovieError, Quirks};
+
+    // Wait for a key, add a random number to V2 for it, draw the key's digit, repeat
+    // 0x200: LD V0, K ; RND V1, 0xFF ; ADD V2, V1 ; LD F, V0 ; DRW V2, V3, 5 ; JP 0x200
+    const ROM: [u8; 12] = [
+        0xF0, 0x0A, 0xC1, 0xFF, 0x82, 0x14, 0xF0, 0x29, 0xD2, 0x35, 0x12, 0x00,
+    ];
+
+    fn record() -> (Movie, Vec<u8>) {
+        let mut movie = Movie::new(&ROM, 10);
+        movie.seed = 1234;
+        movie.quirks = Quirks::super_chip();
+
+        let mut chip = movie.power_on(&ROM).unwrap();
+        let mut recorder = MovieRecorder::new(movie);
+        for frame in 0..40 {
+            match frame % 10 {
+                2 => recorder.press(&mut chip, (frame / 10) as u8 + 3),
+                5 => recorder.release(&mut chip, (frame / 10) as u8 + 3),
+                _ => {}
+            }
+            recorder.run_frame(&mut chip).unwrap();
+        }
+        (recorder.finish(), chip.save_state())
+    }
+
+    #[test]
+    fn test_replay_is_bit_exact() {
+        let (movie, recorded) = record();
+        assert_eq!(movie.frames, 40);
+        assert_eq!(movie.events.len(), 8);
+
+        let replayed = movie.replay(&ROM).unwrap();
+        assert_eq!(replayed.save_state(), recorded);
+        assert_eq!(replayed.registers[0], 6);
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let (movie, recorded) = record();
+        let text = movie.to_text();
+        assert!(text.starts_with("CHIP8-MOVIE 1\n"));
+        assert!(text.contains("\n2 +3\n5 -3\n"));
+
+        let parsed = Movie::parse(&text).unwrap();
+        assert_eq!(parsed, movie);
+        assert_eq!(parsed.replay(&ROM).unwrap().save_state(), recorded);
+    }
+
+    #[test]
+    fn test_rejects_bad_movies() {
+        let (movie, _) = record();
+        let text = movie.to_text();
+        assert!(matches!(
+            movie.replay(&ROM[..10]),
+            Err(MovieError::RomMismatch { .. })
+        ));
+        assert_eq!(
+            Movie::parse(&text.replace("CHIP8-MOVIE 1", "CHIP8-MOVIE 2")),
+            Err(MovieError::UnsupportedVersion { version: 2 })
+        );
+        assert_eq!(
+            Movie::parse(&text.replace("xo-chip 0", "xo-chip 2")),
+            Err(MovieError::Parse { line: 6 })
+        );
+        assert_eq!(
+            Movie::parse(&format!("{}1 +3\n", text)),
+            Err(MovieError::Parse { line: 17 })
+        );
+    }
+
+    #[test]
+    fn test_drops_keys_after_the_last_frame() {
+        let movie = Movie::new(&ROM, 10);
+        let mut chip = movie.power_on(&ROM).unwrap();
+        let mut recorder = MovieRecorder::new(movie);
+        recorder.press(&mut chip, 1);
+        recorder.run_frame(&mut chip).unwrap();
+        recorder.release(&mut chip, 1);
+
+        let movie = recorder.finish();
+        assert_eq!(movie.events.len(), 1);
+        assert_eq!(Movie::parse(&movie.to_text()), Ok(movie));
+    }
+}
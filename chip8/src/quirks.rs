@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+/// Behaviours that differ between CHIP-8 interpreters.
+///
+/// ROMs are written against one particular interpreter, pick the preset for the
+/// platform a ROM was written for. The default is the original COSMAC VIP.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift Vx in place instead of shifting Vy into Vx.
+    pub shift: bool,
+    /// `BNNN` jumps to `XNN + VX` instead of `NNN + V0`.
+    pub jump: bool,
+    /// `FX55`/`FX65` leave I pointing past the last register stored or loaded.
+    pub memory_increment: bool,
+    /// With `memory_increment`, I only moves X bytes and points at the last register, as on the CHIP-48.
+    #[serde(default)]
+    pub memory_increment_by_x: bool,
+    /// `8XY1`, `8XY2` and `8XY3` reset VF to 0.
+    pub vf_reset: bool,
+    /// `DXYN` waits for the next 60 Hz vertical blank, at most one sprite is drawn per frame.
+    pub display_wait: bool,
+    /// Sprites are cut off at the screen edges instead of wrapping around.
+    pub clipping: bool,
+    /// The SUPER-CHIP instructions (`00CN`, `00FB`-`00FF`, `DXY0`, `FX30`, `FX75`, `FX85`) are
+    /// available, otherwise they are machine code calls or invalid as on the VIP.
+    #[serde(default)]
+    pub super_chip_instructions: bool,
+}
+
+impl Quirks {
+    pub fn cosmac_vip() -> Self {
+        Self {
+            shift: false,
+            jump: false,
+            memory_increment: true,
+            memory_increment_by_x: false,
+            vf_reset: true,
+            display_wait: true,
+            clipping: true,
+            super_chip_instructions: false,
+        }
+    }
+
+    // SUPER-CHIP inherited everything but FX55/FX65 from the CHIP-48, which still moved I
+    // (one byte short of the VIP)
+    pub fn chip48() -> Self {
+        Self {
+            memory_increment: true,
+            memory_increment_by_x: true,
+            ..Self::super_chip()
+        }
+    }
+
+    pub fn super_chip() -> Self {
+        Self {
+            shift: true,
+            jump: true,
+            memory_increment: false,
+            memory_increment_by_x: false,
+            vf_reset: false,
+            display_wait: false,
+            clipping: true,
+            super_chip_instructions: true,
+        }
+    }
+
+    pub fn xo_chip() -> Self {
+        Self {
+            shift: false,
+            jump: false,
+            memory_increment: true,
+            memory_increment_by_x: false,
+            vf_reset: false,
+            display_wait: false,
+            clipping: false,
+            super_chip_instructions: true,
+        }
+    }
+
+    /// The preset for a platform's short name: `vip`, `chip48`, `schip` or `xochip`.
+    ///
+    /// `xochip` ROMs also need [`Chip8::set_xo_chip`](crate::Chip8::set_xo_chip).
+    pub fn by_name(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" => Some(Self::cosmac_vip()),
+            "chip48" => Some(Self::chip48()),
+            "schip" => Some(Self::super_chip()),
+            "xochip" => Some(Self::xo_chip()),
+            _ => None,
+        }
+    }
+
+    /// Every preset with a display name, for frontends.
+    pub fn presets() -> [(&'static str, Quirks); 4] {
+        [
+            ("COSMAC VIP", Self::cosmac_vip()),
+            ("CHIP-48", Self::chip48()),
+            ("SUPER-CHIP 1.1", Self::super_chip()),
+            ("XO-CHIP", Self::xo_chip()),
+        ]
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::cosmac_vip()
+    }
+}
@@ -1,52 +1,125 @@
-use std::{
-    fmt::{write, Write},
-    path::Path,
-    string,
-};
+use std::{fmt::Write, path::Path};
 
 use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use std::cell::RefCell;
 
-const TOTAL_RAM_SIZE: usize = 4096;
-const FONT_SIZE: usize = 80;
-const FREE_RAM_SIZE: usize = TOTAL_RAM_SIZE - FONT_SIZE; // total RAM space - FONT space
+pub(crate) const TOTAL_RAM_SIZE: usize = 4096;
+pub(crate) const XO_CHIP_RAM_SIZE: usize = 0x10000;
+const FONT_CHAR_SIZE: usize = 5;
 
 const FONT_ADDRESS_START: usize = 0x000;
 const FONT_ADDRESS_END: usize = 0x050;
+// SUPER-CHIP 8x10 digits, stored right after the small font
+const BIG_FONT_CHAR_SIZE: usize = 10;
+const BIG_FONT_ADDRESS_START: usize = FONT_ADDRESS_END;
+const BIG_FONT_ADDRESS_END: usize = 0x0F0;
 const PROGRAM_ADDRESS_START: usize = 0x200;
-const PROGRAM_SPACE_SIZE: usize = TOTAL_RAM_SIZE - 0x200;
+
+/// One byte read or written through [`Chip8::read_byte`] or [`Chip8::write_byte`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: u16,
+    /// The byte read, or the byte written.
+    pub value: u8,
+    pub write: bool,
+}
+
 impl Chip8 {
     pub fn initialize_ram(&mut self) {
-        self.ram = [0; 4096];
+        self.ram = vec![0; self.ram.len()];
         self.load_font();
     }
     pub fn load_font(&mut self) {
-        self.ram[0x000..FONT_ADDRESS_END].clone_from_slice(&FONT_ARRAY);
+        self.ram[FONT_ADDRESS_START..FONT_ADDRESS_END].clone_from_slice(&FONT_ARRAY);
+        self.ram[BIG_FONT_ADDRESS_START..BIG_FONT_ADDRESS_END].clone_from_slice(&BIG_FONT_ARRAY);
+    }
+
+    // Address of the built-in sprite for the hex digit in the low nibble
+    pub(crate) fn font_address(digit: u8) -> u16 {
+        (FONT_ADDRESS_START + (digit & 0xF) as usize * FONT_CHAR_SIZE) as u16
     }
 
-    pub fn load_program(&mut self, program: &[u8]) {
+    // Address of the large SUPER-CHIP sprite for the hex digit in the low nibble
+    pub(crate) fn big_font_address(digit: u8) -> u16 {
+        (BIG_FONT_ADDRESS_START + (digit & 0xF) as usize * BIG_FONT_CHAR_SIZE) as u16
+    }
+
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), Chip8Error> {
         for (i, v) in program.iter().enumerate() {
-            self.write_byte(i + PROGRAM_ADDRESS_START, *v)
+            self.write_byte(i + PROGRAM_ADDRESS_START, *v)?;
         }
+        Ok(())
     }
 
-    // zeroes out the program space (0x200 : 0xFFF)
+    // zeroes out the program space (0x200 : 0xFFF, or 0xFFFF in XO-CHIP mode)
     pub fn reset_ram(&mut self) {
-        self.ram[PROGRAM_ADDRESS_START..].copy_from_slice(&[0; PROGRAM_SPACE_SIZE]);
+        self.ram[PROGRAM_ADDRESS_START..].fill(0);
         self.load_font();
     }
 
-    pub fn read_byte(&self, address: usize) -> u8 {
-        self.ram[address]
+    pub fn read_byte(&self, address: usize) -> Result<u8, Chip8Error> {
+        let value = self
+            .ram
+            .get(address)
+            .copied()
+            .ok_or(Chip8Error::MemoryOutOfBounds { addr: address })?;
+        self.log_access(address, value, false);
+        Ok(value)
     }
-    pub fn read_word(&self, address: usize) -> u16 {
-        let byte1 = self.ram[address] as u16;
-        let byte2 = self.ram[(address + 1)] as u16;
-        (byte1 << 8) | byte2
+    pub fn read_word(&self, address: usize) -> Result<u16, Chip8Error> {
+        let byte1 = self.read_byte(address)? as u16;
+        let byte2 = self.read_byte(address + 1)? as u16;
+        Ok((byte1 << 8) | byte2)
     }
 
     // Write a byte to ram
-    pub fn write_byte(&mut self, address: usize, byte: u8) {
-        self.ram[address] = byte;
+    pub fn write_byte(&mut self, address: usize, byte: u8) -> Result<(), Chip8Error> {
+        let cell = self
+            .ram
+            .get_mut(address)
+            .ok_or(Chip8Error::MemoryOutOfBounds { addr: address })?;
+        *cell = byte;
+        self.log_access(address, byte, true);
+        Ok(())
+    }
+
+    // Read an instruction word, fetches are not data accesses and never show up in the memory log
+    pub(crate) fn fetch_word(&self, address: usize) -> Result<u16, Chip8Error> {
+        match self.ram.get(address..address + 2) {
+            Some(word) => Ok(u16::from_be_bytes([word[0], word[1]])),
+            None => Err(Chip8Error::MemoryOutOfBounds {
+                addr: address.max(self.ram.len()),
+            }),
+        }
+    }
+
+    // Start or stop recording every `read_byte` and `write_byte`, see `take_memory_log`
+    pub fn set_memory_log(&mut self, enabled: bool) {
+        match (enabled, &self.memory_log) {
+            (true, None) => self.memory_log = Some(RefCell::new(Vec::new())),
+            (false, _) => self.memory_log = None,
+            _ => {}
+        }
+    }
+
+    // The accesses recorded since the last call, oldest first. `emulate_cycle` clears the log
+    // before each instruction so after it only that instruction's accesses are left
+    pub fn take_memory_log(&mut self) -> Vec<MemoryAccess> {
+        match &self.memory_log {
+            Some(log) => log.take(),
+            None => Vec::new(),
+        }
+    }
+
+    fn log_access(&self, address: usize, value: u8, write: bool) {
+        if let Some(log) = &self.memory_log {
+            log.borrow_mut().push(MemoryAccess {
+                address: address as u16,
+                value,
+                write,
+            });
+        }
     }
 
     // Write a word to RAM
@@ -59,6 +132,22 @@ impl Chip8 {
         self.ram.len()
     }
 
+    // XO-CHIP mode grows RAM to 64 KiB, the first 4 KiB are kept when switching, and enables
+    // the XO-CHIP instructions. Use together with `Quirks::xo_chip` for Octo ROMs.
+    pub fn set_xo_chip(&mut self, enabled: bool) {
+        self.xo_chip = enabled;
+        let size = if enabled {
+            XO_CHIP_RAM_SIZE
+        } else {
+            TOTAL_RAM_SIZE
+        };
+        self.ram.resize(size, 0);
+    }
+
+    pub fn is_xo_chip(&self) -> bool {
+        self.xo_chip
+    }
+
     // Ram dump
     pub fn dump_to_file<P: AsRef<Path>>(&self, path: P, bytes_per_row: usize) {
         // println!("{:?}", self.ram.clone())
@@ -131,34 +220,135 @@ impl Chip8 {
     }
 }
 
+const FONT_ARRAY: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const BIG_FONT_ARRAY: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 #[cfg(test)]
 mod tests {
-    use super::Chip8;
+    use super::{Chip8, MemoryAccess};
+    use crate::test_rom::TEST_PROGRAM;
+    use crate::Chip8Error;
     // use super::*;
     #[test]
     pub fn test_font_initialization() {
         let mut chip = Chip8::default();
         chip.load_font();
-        assert_eq!(chip.read_byte(0x000), 0xF0);
+        assert_eq!(chip.read_byte(0x000), Ok(0xF0));
     }
 
     #[test]
     pub fn test_byte_rwops() {
         let mut chip = Chip8::default();
-        chip.write_byte(0x000, 0xAF);
+        chip.write_byte(0x000, 0xAF).unwrap();
+
+        assert_eq!(chip.read_byte(0x000), Ok(0xAF));
+    }
+
+    #[test]
+    pub fn test_out_of_bounds() {
+        let mut chip = Chip8::default();
+        let oob = Chip8Error::MemoryOutOfBounds { addr: 0x1000 };
+        assert_eq!(chip.read_byte(0x1000), Err(oob));
+        assert_eq!(chip.read_word(0xFFF), Err(oob));
+        assert_eq!(chip.write_byte(0x1000, 0xAF), Err(oob));
+        assert!(chip.load_program(&[0u8; 0xE01]).is_err());
+    }
+
+    #[test]
+    pub fn test_memory_log() {
+        let mut chip = Chip8::default();
+        chip.write_byte(0x300, 0xAB).unwrap();
+        chip.set_memory_log(true);
+        chip.write_byte(0x301, 0xCD).unwrap();
+        assert_eq!(chip.read_word(0x300), Ok(0xABCD));
+        assert_eq!(chip.fetch_word(0x300), Ok(0xABCD));
+        let log = chip.take_memory_log();
+        assert_eq!(
+            log,
+            [
+                MemoryAccess {
+                    address: 0x301,
+                    value: 0xCD,
+                    write: true
+                },
+                MemoryAccess {
+                    address: 0x300,
+                    value: 0xAB,
+                    write: false
+                },
+                MemoryAccess {
+                    address: 0x301,
+                    value: 0xCD,
+                    write: false
+                },
+            ]
+        );
+        assert!(chip.take_memory_log().is_empty());
+        chip.set_memory_log(false);
+        chip.read_byte(0x300).unwrap();
+        assert!(chip.take_memory_log().is_empty());
+    }
+
+    #[test]
+    pub fn test_xo_chip_address_space() {
+        let mut chip = Chip8::default();
+        chip.write_byte(0x300, 0xAB).unwrap();
+        chip.set_xo_chip(true);
+        assert_eq!(chip.total_ram(), 0x10000);
+        assert_eq!(chip.read_byte(0x300), Ok(0xAB));
+        chip.write_byte(0xFFFF, 0xCD).unwrap();
+        assert_eq!(chip.read_word(0xFFFE), Ok(0x00CD));
+        assert!(chip.load_program(&[0u8; 0xFE00]).is_ok());
+        assert!(chip.load_program(&[0u8; 0xFE01]).is_err());
 
-        assert_eq!(chip.read_byte(0x000), 0xAF);
+        chip.set_xo_chip(false);
+        assert_eq!(chip.total_ram(), 0x1000);
     }
     #[test]
     pub fn test_reset_ram() {
         let mut chip = Chip8::default();
         chip.initialize_ram();
         chip.load_font();
-        chip.load_program(&[0xB2, 0xF2]);
-        chip.dump_to_file("../ram_prereset.txt", 8);
+        chip.load_program(&[0xB2, 0xF2]).unwrap();
+        chip.dump_to_file(std::env::temp_dir().join("ram_prereset.txt"), 8);
 
         chip.reset_ram();
-        chip.dump_to_file("../ram_post_rest.txt", 8);
+        chip.dump_to_file(std::env::temp_dir().join("ram_post_rest.txt"), 8);
     }
 
     #[test]
@@ -166,25 +356,7 @@ mod tests {
         let mut chip8 = Chip8::default();
         chip8.initialize_ram();
         chip8.load_font();
-        chip8.dump_to_file("../ram_dump.txt", 8);
+        chip8.load_program(&TEST_PROGRAM).unwrap();
+        chip8.dump_to_file(std::env::temp_dir().join("ram_dump.txt"), 8);
     }
 }
-
-const FONT_ARRAY: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
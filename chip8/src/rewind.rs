@@ -0,0 +1,268 @@
+// Rewind history
+//
+// Only the most recent frame is kept as a full save state. Every older frame is a
+// reverse delta that turns the frame after it back into that frame: the XOR of the
+// two snapshots with runs of zero bytes (unchanged state) collapsed. Most frames only
+// touch a few registers and pixels, so a delta is usually a handful of bytes.
+
+use std::collections::VecDeque;
+
+use crate::chip8::Chip8;
+use crate::clock::TIMER_HZ;
+use crate::error::SnapshotError;
+
+const DEFAULT_SECONDS: usize = 10;
+const DEFAULT_MAX_BYTES: usize = 32 * 1024 * 1024;
+
+/// A bounded history of frames to step back through.
+///
+/// Call [`RewindBuffer::push`] once per emulated frame. The oldest frames are dropped
+/// once either the frame count or the memory limit is exceeded.
+pub struct RewindBuffer {
+    // Full snapshot of the most recent frame
+    newest: Option<Vec<u8>>,
+    // Reverse deltas, oldest first. The back one turns `newest` into the frame before it.
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+    max_frames: usize,
+    max_bytes: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(max_frames: usize, max_bytes: usize) -> Self {
+        Self {
+            newest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+            max_frames,
+            max_bytes,
+        }
+    }
+
+    /// Record the current state of `chip` as the newest frame.
+    pub fn push(&mut self, chip: &Chip8) {
+        let state = chip.save_state();
+        if let Some(newest) = self.newest.take() {
+            let delta = encode_delta(&state, &newest);
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.newest = Some(state);
+        self.trim();
+    }
+
+    /// Number of frames that can be stepped back.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Bytes held by the history.
+    pub fn memory_usage(&self) -> usize {
+        self.delta_bytes + self.newest.as_ref().map_or(0, Vec::len)
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+    }
+
+    /// Load the frame `frames` back into `chip` and forget every frame after it.
+    ///
+    /// Stops at the oldest frame, returns how many frames were actually stepped back.
+    /// `rewind(chip, 0)` restores the newest frame.
+    pub fn rewind(&mut self, chip: &mut Chip8, frames: usize) -> Result<usize, SnapshotError> {
+        let mut state = match self.newest.take() {
+            Some(state) => state,
+            None => return Ok(0),
+        };
+        let mut stepped = 0;
+        while stepped < frames {
+            match self.deltas.pop_back() {
+                Some(delta) => {
+                    self.delta_bytes -= delta.len();
+                    state = apply_delta(&state, &delta);
+                    stepped += 1;
+                }
+                None => break,
+            }
+        }
+        let result = chip.load_state(&state);
+        self.newest = Some(state);
+        result.map(|_| stepped)
+    }
+
+    /// Load the frame `frames` back into `chip` but keep the history, for scrubbing.
+    pub fn peek(&self, chip: &mut Chip8, frames: usize) -> Result<usize, SnapshotError> {
+        let mut state = match &self.newest {
+            Some(state) => state.clone(),
+            None => return Ok(0),
+        };
+        let stepped = frames.min(self.deltas.len());
+        for delta in self.deltas.iter().rev().take(stepped) {
+            state = apply_delta(&state, delta);
+        }
+        chip.load_state(&state).map(|_| stepped)
+    }
+
+    fn trim(&mut self) {
+        while self.deltas.len() > self.max_frames
+            || (self.memory_usage() > self.max_bytes && !self.deltas.is_empty())
+        {
+            if let Some(oldest) = self.deltas.pop_front() {
+                self.delta_bytes -= oldest.len();
+            }
+        }
+    }
+}
+
+// The last ten seconds at 60 frames per second, at most 32 MiB
+impl Default for RewindBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_SECONDS * TIMER_HZ as usize, DEFAULT_MAX_BYTES)
+    }
+}
+
+// Delta turning `from` into `to`:
+//   varint len(to), then (varint zero run, varint literal count, literal bytes) until len(to) is covered
+// where the bytes are `from XOR to`, `from` being zero-padded if it is shorter.
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = to
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| from.get(i).copied().unwrap_or(0) ^ byte)
+        .collect();
+
+    let mut delta = Vec::new();
+    write_varint(&mut delta, to.len());
+    let mut i = 0;
+    while i < xor.len() {
+        let zeros = xor[i..].iter().take_while(|byte| **byte == 0).count();
+        i += zeros;
+        let literals = xor[i..].iter().take_while(|byte| **byte != 0).count();
+        write_varint(&mut delta, zeros);
+        write_varint(&mut delta, literals);
+        delta.extend_from_slice(&xor[i..i + literals]);
+        i += literals;
+    }
+    delta
+}
+
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+    let mut to: Vec<u8> = (0..len)
+        .map(|i| from.get(i).copied().unwrap_or(0))
+        .collect();
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literals = read_varint(delta, &mut pos);
+        for (byte, xor) in to[i..i + literals].iter_mut().zip(&delta[pos..]) {
+            *byte ^= xor;
+        }
+        pos += literals;
+        i += literals;
+    }
+    to
+}
+
+// LEB128
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_delta, encode_delta, RewindBuffer};
+    use crate::Chip8;
+
+    fn counting_chip() -> Chip8 {
+        let mut chip = Chip8::new();
+        // ADD V0, 0x01 ; JP 0x200
+        chip.load_program(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let from = [1, 2, 3, 4, 5, 6];
+        for to in [
+            &[1, 2, 9, 4, 5, 7][..],
+            &[1, 2],
+            &[1, 2, 3, 4, 5, 6, 0, 0, 8],
+            &[],
+        ] {
+            assert_eq!(apply_delta(&from, &encode_delta(&from, to)), to);
+        }
+        // Unchanged state only costs the length and one empty run
+        assert!(encode_delta(&[0xAA; 4096], &[0xAA; 4096]).len() <= 6);
+    }
+
+    #[test]
+    fn test_rewind_frames() {
+        let mut chip = counting_chip();
+        let mut rewind = RewindBuffer::default();
+        rewind.push(&chip);
+        for _ in 0..10 {
+            chip.run_frame(2).unwrap();
+            rewind.push(&chip);
+        }
+        assert_eq!(chip.registers[0], 10);
+        assert_eq!(rewind.len(), 10);
+
+        assert_eq!(rewind.peek(&mut chip, 4), Ok(4));
+        assert_eq!(chip.registers[0], 6);
+        assert_eq!(rewind.len(), 10);
+
+        assert_eq!(rewind.rewind(&mut chip, 3), Ok(3));
+        assert_eq!(chip.registers[0], 7);
+        assert_eq!(rewind.len(), 7);
+
+        assert_eq!(rewind.rewind(&mut chip, 100), Ok(7));
+        assert_eq!(chip.registers[0], 0);
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut chip = counting_chip();
+        let mut rewind = RewindBuffer::new(5, usize::MAX);
+        for _ in 0..20 {
+            chip.run_frame(2).unwrap();
+            rewind.push(&chip);
+        }
+        assert_eq!(rewind.len(), 5);
+        rewind.rewind(&mut chip, 5).unwrap();
+        assert_eq!(chip.registers[0], 15);
+
+        let mut rewind = RewindBuffer::new(100, 0);
+        rewind.push(&chip);
+        chip.run_frame(2).unwrap();
+        rewind.push(&chip);
+        assert!(rewind.is_empty());
+        assert_eq!(rewind.memory_usage(), chip.save_state().len());
+    }
+}
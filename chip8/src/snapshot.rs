@@ -0,0 +1,477 @@
+// Save states
+//
+// A snapshot is a compact binary blob:
+//   magic    4 bytes  "C8SS"
+//   version  u16      payload format, see `SNAPSHOT_VERSION`
+//   length   u32      payload length in bytes
+//   payload           machine state, little-endian, in the order of `write_payload`
+//   crc32    u32      checksum of everything before it
+// Fields added in later versions go at the end of the payload. Loading an older
+// snapshot gives them their defaults, only snapshots from a newer build are rejected.
+
+use crate::chip8::{Chip8, Pixel};
+use crate::error::{Chip8Error, SnapshotError};
+use crate::keypad::{KeyWait, KeyWaitMode, KEY_COUNT};
+use crate::quirks::Quirks;
+use crate::ram_ops::{TOTAL_RAM_SIZE, XO_CHIP_RAM_SIZE};
+use crate::rng::Rng;
+use crate::stack_ops::STACK_SIZE;
+
+const MAGIC: &[u8; 4] = b"C8SS";
+// Magic, version and payload length
+const HEADER_SIZE: usize = 10;
+const CHECKSUM_SIZE: usize = 4;
+
+/// Format version written by [`Chip8::save_state`].
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+impl Chip8 {
+    /// Capture the whole machine state, see [`Chip8::load_state`].
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut payload = Writer::default();
+        self.write_payload(&mut payload);
+
+        let mut out = Vec::with_capacity(HEADER_SIZE + payload.buf.len() + CHECKSUM_SIZE);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        out.extend_from_slice(&(payload.buf.len() as u32).to_le_bytes());
+        out.extend_from_slice(&payload.buf);
+        let checksum = crc32(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    /// Restore a snapshot taken by [`Chip8::save_state`], possibly by an older version.
+    ///
+    /// The machine is left untouched when the snapshot is rejected.
+    pub fn load_state(&mut self, snapshot: &[u8]) -> Result<(), SnapshotError> {
+        if snapshot.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return Err(SnapshotError::Truncated);
+        }
+        if &snapshot[..4] != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let (body, checksum) = snapshot.split_at(snapshot.len() - CHECKSUM_SIZE);
+        let version = u16::from_le_bytes([body[4], body[5]]);
+        if version == 0 || version > SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion { version });
+        }
+        let length = u32::from_le_bytes([body[6], body[7], body[8], body[9]]) as usize;
+        if body.len() - HEADER_SIZE != length {
+            return Err(SnapshotError::Truncated);
+        }
+        let expected = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        if crc32(body) != expected {
+            return Err(SnapshotError::ChecksumMismatch);
+        }
+
+        let mut reader = Reader {
+            data: &body[HEADER_SIZE..],
+            version,
+        };
+        let mut state = Self::read_payload(&mut reader)?;
+        if !reader.data.is_empty() {
+            return Err(SnapshotError::InvalidData);
+        }
+        // A tracer watches the machine rather than being part of it
+        state.tracer = self.tracer.take();
+        state.memory_log = self.memory_log.take();
+        *self = state;
+        Ok(())
+    }
+
+    fn write_payload(&self, w: &mut Writer) {
+        w.bytes(&self.registers);
+        w.u16(self.index_register);
+        w.u16(self.program_counter);
+        w.u16(self.curr_op);
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+
+        w.u8(self.stack.len() as u8);
+        for address in &self.stack {
+            w.u16(*address);
+        }
+
+        w.bool(self.xo_chip);
+        w.bytes(&self.ram);
+
+        // Four 2-bit pixels per byte, the first pixel in the lowest bits
+        w.bool(self.hires);
+        w.u8(self.planes);
+        for chunk in self.bit_map.chunks(4) {
+            let packed = chunk
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (i, pixel)| acc | (*pixel as u8) << (i * 2));
+            w.u8(packed);
+        }
+
+        w.u16(self.keypad.pressed().fold(0, |acc, key| acc | 1 << key));
+        match self.key_wait {
+            None => w.u8(0),
+            Some(wait) => {
+                w.u8(1);
+                w.u8(wait.register);
+                w.u8(wait.pressed.map_or(0xFF, |key| key));
+            }
+        }
+        w.bool(self.key_wait_mode == KeyWaitMode::Press);
+
+        let q = self.quirks;
+        let flags = [
+            q.shift,
+            q.jump,
+            q.memory_increment,
+            q.vf_reset,
+            q.display_wait,
+            q.clipping,
+            q.memory_increment_by_x,
+            q.super_chip_instructions,
+        ];
+        w.u8(flags
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, flag)| acc | (*flag as u8) << i));
+
+        w.bool(self.vblank_wait);
+        w.bool(self.exited);
+        write_fault(w, self.fault);
+
+        w.bytes(&self.rpl);
+        w.bytes(&self.audio_pattern);
+        w.u8(self.pitch);
+
+        // Version 2
+        w.u64(self.rng.state);
+    }
+
+    fn read_payload(r: &mut Reader) -> Result<Self, SnapshotError> {
+        let mut chip = Chip8::default();
+        chip.registers.copy_from_slice(r.bytes(16)?);
+        chip.index_register = r.u16()?;
+        chip.program_counter = r.u16()?;
+        chip.curr_op = r.u16()?;
+        chip.delay_timer = r.u8()?;
+        chip.sound_timer = r.u8()?;
+
+        let depth = r.u8()? as usize;
+        if depth > STACK_SIZE {
+            return Err(SnapshotError::InvalidData);
+        }
+        for _ in 0..depth {
+            chip.stack.push_back(r.u16()?);
+        }
+        chip.stack_pointer = depth as u16;
+
+        chip.xo_chip = r.bool()?;
+        let ram_size = if chip.xo_chip {
+            XO_CHIP_RAM_SIZE
+        } else {
+            TOTAL_RAM_SIZE
+        };
+        chip.ram = r.bytes(ram_size)?.to_vec();
+
+        chip.hires = r.bool()?;
+        chip.planes = r.u8()? & 0b11;
+        let pixels = chip.screen_width() * chip.screen_height();
+        chip.bit_map = r
+            .bytes(pixels / 4)?
+            .iter()
+            .flat_map(|packed| (0..4).map(move |i| Pixel::from_bits(packed >> (i * 2))))
+            .collect();
+
+        let keys = r.u16()?;
+        for key in (0..KEY_COUNT as u8).filter(|key| keys & 1 << key != 0) {
+            chip.keypad.press(key);
+        }
+        chip.key_wait = match r.u8()? {
+            0 => None,
+            1 => {
+                let register = r.u8()?;
+                let pressed = r.u8()?;
+                if register > 0xF || (pressed > 0xF && pressed != 0xFF) {
+                    return Err(SnapshotError::InvalidData);
+                }
+                Some(KeyWait {
+                    register,
+                    pressed: (pressed != 0xFF).then(|| pressed),
+                })
+            }
+            _ => return Err(SnapshotError::InvalidData),
+        };
+        chip.key_wait_mode = if r.bool()? {
+            KeyWaitMode::Press
+        } else {
+            KeyWaitMode::Release
+        };
+
+        let flags = r.u8()?;
+        let flag = |i: u8| flags & 1 << i != 0;
+        chip.quirks = Quirks {
+            shift: flag(0),
+            jump: flag(1),
+            memory_increment: flag(2),
+            vf_reset: flag(3),
+            display_wait: flag(4),
+            clipping: flag(5),
+            memory_increment_by_x: flag(6),
+            super_chip_instructions: flag(7),
+        };
+
+        chip.vblank_wait = r.bool()?;
+        chip.exited = r.bool()?;
+        chip.fault = read_fault(r)?;
+
+        chip.rpl.copy_from_slice(r.bytes(16)?);
+        chip.audio_pattern.copy_from_slice(r.bytes(16)?);
+        chip.pitch = r.u8()?;
+
+        // Version 1 snapshots predate the owned RNG, they get the default seed
+        if r.version >= 2 {
+            chip.rng = Rng { state: r.u64()? };
+        }
+
+        // Frontends have to redraw whatever was on screen before
+        chip.draw_flag = true;
+        Ok(chip)
+    }
+}
+
+fn write_fault(w: &mut Writer, fault: Option<Chip8Error>) {
+    match fault {
+        None => w.u8(0),
+        Some(Chip8Error::StackUnderflow { pc }) => {
+            w.u8(1);
+            w.u16(pc);
+        }
+        Some(Chip8Error::StackOverflow { pc }) => {
+            w.u8(2);
+            w.u16(pc);
+        }
+        Some(Chip8Error::InvalidOpcode { pc, op }) => {
+            w.u8(3);
+            w.u16(pc);
+            w.u16(op);
+        }
+        Some(Chip8Error::MachineCodeCall { pc, addr }) => {
+            w.u8(4);
+            w.u16(pc);
+            w.u16(addr);
+        }
+        Some(Chip8Error::MemoryOutOfBounds { addr }) => {
+            w.u8(5);
+            w.u32(addr as u32);
+        }
+    }
+}
+
+fn read_fault(r: &mut Reader) -> Result<Option<Chip8Error>, SnapshotError> {
+    let fault = match r.u8()? {
+        0 => return Ok(None),
+        1 => Chip8Error::StackUnderflow { pc: r.u16()? },
+        2 => Chip8Error::StackOverflow { pc: r.u16()? },
+        3 => Chip8Error::InvalidOpcode {
+            pc: r.u16()?,
+            op: r.u16()?,
+        },
+        4 => Chip8Error::MachineCodeCall {
+            pc: r.u16()?,
+            addr: r.u16()?,
+        },
+        5 => Chip8Error::MemoryOutOfBounds {
+            addr: r.u32()? as usize,
+        },
+        _ => return Err(SnapshotError::InvalidData),
+    };
+    Ok(Some(fault))
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    // Format version of the snapshot being read, for migrations
+    version: u16,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.data.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::InvalidData),
+        }
+    }
+}
+
+// CRC-32 (IEEE), bit by bit since snapshots are small
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, CHECKSUM_SIZE, HEADER_SIZE, SNAPSHOT_VERSION};
+    use crate::{Chip8, Quirks, SnapshotError};
+
+    fn running_chip() -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.set_quirks(Quirks::super_chip());
+        chip.initialize_ram();
+        // HIGH ; LD V3, 0x2A ; CALL 0x20A ; JP 0x206 ; LD I, font(0) ; DRW V0, V0, 5 ; RET
+        chip.load_program(&[
+            0x00, 0xFF, 0x63, 0x2A, 0x22, 0x0A, 0x12, 0x06, 0x00, 0x00, 0xA0, 0x00, 0xD0, 0x05,
+            0x00, 0xEE,
+        ])
+        .unwrap();
+        chip.run_frame(5).unwrap();
+        chip.press(0x7);
+        chip
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let chip = running_chip();
+        let snapshot = chip.save_state();
+
+        let mut restored = Chip8::new();
+        restored.load_state(&snapshot).unwrap();
+        assert_eq!(restored.save_state(), snapshot);
+        assert_eq!(restored.registers[3], 0x2A);
+        assert_eq!(restored.stack.len(), 1);
+        assert!(restored.is_hires() && restored.display().is_lit(0, 0));
+        assert!(restored.keypad().is_pressed(0x7));
+        assert_eq!(restored.quirks(), Quirks::super_chip());
+
+        // Both machines carry on identically
+        let mut original = chip;
+        original.run_frame(10).unwrap();
+        restored.run_frame(10).unwrap();
+        assert_eq!(original.save_state(), restored.save_state());
+    }
+
+    #[test]
+    fn test_rejects_bad_snapshots() {
+        let mut chip = running_chip();
+        let snapshot = chip.save_state();
+        let before = snapshot.clone();
+
+        let mut corrupt = snapshot.clone();
+        corrupt[20] ^= 0xFF;
+        assert_eq!(
+            chip.load_state(&corrupt),
+            Err(SnapshotError::ChecksumMismatch)
+        );
+
+        let mut magic = snapshot.clone();
+        magic[0] = b'X';
+        assert_eq!(chip.load_state(&magic), Err(SnapshotError::BadMagic));
+
+        let mut newer = snapshot.clone();
+        newer[4..6].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            chip.load_state(&newer),
+            Err(SnapshotError::UnsupportedVersion {
+                version: SNAPSHOT_VERSION + 1
+            })
+        );
+
+        assert_eq!(
+            chip.load_state(&snapshot[..100]),
+            Err(SnapshotError::Truncated)
+        );
+        assert_eq!(chip.save_state(), before);
+    }
+
+    #[test]
+    fn test_migrates_version_1() {
+        let mut chip = running_chip();
+        chip.reseed(99);
+        let snapshot = chip.save_state();
+
+        // Version 1 is version 2 without the trailing RNG state
+        let payload = &snapshot[HEADER_SIZE..snapshot.len() - CHECKSUM_SIZE - 8];
+        let mut v1 = b"C8SS".to_vec();
+        v1.extend_from_slice(&1u16.to_le_bytes());
+        v1.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        v1.extend_from_slice(payload);
+        v1.extend_from_slice(&crc32(&v1).to_le_bytes());
+
+        let mut restored = Chip8::new();
+        restored.load_state(&v1).unwrap();
+        assert_eq!(restored.registers[3], 0x2A);
+        assert_eq!(restored.rng, Chip8::new().rng);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
@@ -1,31 +1,69 @@
 use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+
+// Nesting depth of the original interpreter
+pub(crate) const STACK_SIZE: usize = 16;
 
 impl Chip8 {
     pub fn init_stack(&mut self) {
         self.stack = Default::default();
+        self.stack_pointer = 0;
+    }
+
+    pub fn pop_stack(&mut self) -> Result<u16, Chip8Error> {
+        let value = self.stack.pop_back().ok_or(Chip8Error::StackUnderflow {
+            pc: self.op_address(),
+        })?;
+        self.stack_pointer = self.stack.len() as u16;
+        Ok(value)
     }
 
-    pub fn pop_stack(&mut self) -> u16 {
-        self.stack.pop_back().unwrap()
+    pub fn push_stack(&mut self, value: u16) -> Result<(), Chip8Error> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err(Chip8Error::StackOverflow {
+                pc: self.op_address(),
+            });
+        }
+        self.stack.push_back(value);
+        self.stack_pointer = self.stack.len() as u16;
+        Ok(())
     }
 
-    pub fn push_stack(&mut self, value: u16) {
-        self.stack.push_back(value)
+    /// Return addresses on the stack, outermost call first.
+    pub fn call_stack(&self) -> impl DoubleEndedIterator<Item = u16> + '_ {
+        self.stack.iter().copied()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Chip8;
+    use super::STACK_SIZE;
+    use crate::{Chip8, Chip8Error};
 
     #[test]
     pub fn test_basic_stack_ops() {
         let mut chip8 = Chip8::new();
         chip8.init_stack();
-        chip8.push_stack(0x0FFF);
-        chip8.push_stack(0xFAF);
+        chip8.push_stack(0x0FFF).unwrap();
+        chip8.push_stack(0xFAF).unwrap();
 
-        assert_eq!(chip8.pop_stack(), 0xFAF);
-        assert_eq!(chip8.pop_stack(), 0xFFF);
+        assert_eq!(chip8.pop_stack(), Ok(0xFAF));
+        assert_eq!(chip8.pop_stack(), Ok(0xFFF));
+    }
+
+    #[test]
+    pub fn test_stack_bounds() {
+        let mut chip8 = Chip8::new();
+        assert!(matches!(
+            chip8.pop_stack(),
+            Err(Chip8Error::StackUnderflow { .. })
+        ));
+        for _ in 0..STACK_SIZE {
+            chip8.push_stack(0x200).unwrap();
+        }
+        assert!(matches!(
+            chip8.push_stack(0x200),
+            Err(Chip8Error::StackOverflow { .. })
+        ));
     }
 }
@@ -0,0 +1,224 @@
+// Symbol maps
+//
+// Text format, one entry per line, as written by `chip8_emu assemble --symbols`:
+//   0200 main          a name for an address
+//   0210 .data 8       8 bytes of data starting at an address
+//   0202 .line 14      the instruction at an address comes from source line 14
+// Addresses are hexadecimal, blank lines and lines starting with `#` or `;` are ignored.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::error::SymbolError;
+
+/// Names, data regions and source lines for the addresses of a ROM.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    // The first name given to each address
+    names: BTreeMap<u16, String>,
+    addresses: BTreeMap<String, u16>,
+    // Start and length, sorted by start
+    data: BTreeMap<u16, usize>,
+    lines: BTreeMap<u16, usize>,
+}
+
+impl SymbolMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            let error = SymbolError::Parse { line: index + 1 };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let address = u16::from_str_radix(fields[0], 16).map_err(|_| error)?;
+            match fields[1..] {
+                [".data", len] => symbols.add_data(address, len.parse().map_err(|_| error)?),
+                [".line", number] => symbols.add_line(address, number.parse().map_err(|_| error)?),
+                [name] if !name.starts_with('.') => symbols.insert(name, address),
+                _ => return Err(error),
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.data.is_empty() && self.lines.is_empty()
+    }
+
+    pub fn insert(&mut self, name: &str, address: u16) {
+        self.names
+            .entry(address)
+            .or_insert_with(|| name.to_string());
+        self.addresses.insert(name.to_string(), address);
+    }
+
+    pub fn add_data(&mut self, address: u16, len: usize) {
+        self.data.insert(address, len);
+    }
+
+    pub fn add_line(&mut self, address: u16, line: usize) {
+        self.lines.insert(address, line);
+    }
+
+    /// Name of exactly `address`.
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    /// Every name and its address, in address order.
+    pub fn names(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.names
+            .iter()
+            .map(|(address, name)| (*address, name.as_str()))
+    }
+
+    /// `main`, `draw+4` relative to the closest name before it, or `0x2F0`.
+    pub fn describe(&self, address: u16) -> String {
+        match self.names.range(..=address).next_back() {
+            Some((start, name)) if *start == address => name.clone(),
+            // Only within a short distance, a far away name says nothing about the address
+            Some((start, name)) if address - start < 0x100 => {
+                format!("{}+{}", name, address - start)
+            }
+            _ => format!("{:#05X}", address),
+        }
+    }
+
+    /// Parse an address given by name, `name+offset` or number.
+    ///
+    /// Numbers are hexadecimal with or without `0x`, `#` or `$` in front.
+    pub fn resolve(&self, text: &str) -> Option<u16> {
+        let text = text.trim();
+        if let Some(address) = self.address(text) {
+            return Some(address);
+        }
+        if let Some((name, offset)) = text.split_once('+') {
+            let base = self.address(name.trim())?;
+            let offset = parse_offset(offset.trim())?;
+            return base.checked_add(offset);
+        }
+        let digits = text
+            .strip_prefix("0x")
+            .or_else(|| text.strip_prefix("0X"))
+            .or_else(|| text.strip_prefix('#'))
+            .or_else(|| text.strip_prefix('$'))
+            .unwrap_or(text);
+        u16::from_str_radix(digits, 16).ok()
+    }
+
+    /// True inside an annotated data region.
+    pub fn is_data(&self, address: u16) -> bool {
+        match self.data.range(..=address).next_back() {
+            Some((start, len)) => ((address - start) as usize) < *len,
+            None => false,
+        }
+    }
+
+    pub fn data_regions(&self) -> impl Iterator<Item = (u16, usize)> + '_ {
+        self.data.iter().map(|(start, len)| (*start, *len))
+    }
+
+    /// Source line of the instruction at `address`.
+    pub fn line(&self, address: u16) -> Option<usize> {
+        self.lines.get(&address).copied()
+    }
+
+    /// First instruction generated by source line `line`.
+    pub fn line_address(&self, line: usize) -> Option<u16> {
+        self.lines
+            .iter()
+            .find(|(_, source)| **source == line)
+            .map(|(address, _)| *address)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let mut names: Vec<(&String, &u16)> = self.addresses.iter().collect();
+        names.sort_by_key(|(name, address)| (**address, name.as_str()));
+        for (name, address) in names {
+            let _ = writeln!(out, "{:04X} {}", address, name);
+        }
+        for (address, len) in &self.data {
+            let _ = writeln!(out, "{:04X} .data {}", address, len);
+        }
+        for (address, line) in &self.lines {
+            let _ = writeln!(out, "{:04X} .line {}", address, line);
+        }
+        out
+    }
+}
+
+fn parse_offset(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SymbolMap;
+    use crate::SymbolError;
+
+    const MAP: &str = "\
+# game.sym
+0202 main
+0202 start
+0210 draw
+0230 sprites
+0230 .data 8
+0202 .line 3
+";
+
+    #[test]
+    fn test_parse_and_lookup() {
+        let symbols = SymbolMap::parse(MAP).unwrap();
+        assert_eq!(symbols.name(0x202), Some("main"));
+        assert_eq!(symbols.address("start"), Some(0x202));
+        assert_eq!(symbols.describe(0x214), "draw+4");
+        assert_eq!(symbols.describe(0x1FE), "0x1FE");
+        assert!(symbols.is_data(0x237));
+        assert!(!symbols.is_data(0x238));
+        assert_eq!(symbols.line(0x202), Some(3));
+        assert_eq!(symbols.line_address(3), Some(0x202));
+        assert_eq!(SymbolMap::parse(&symbols.to_text()).unwrap(), symbols);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let symbols = SymbolMap::parse(MAP).unwrap();
+        assert_eq!(symbols.resolve("draw"), Some(0x210));
+        assert_eq!(symbols.resolve("draw+0x10"), Some(0x220));
+        assert_eq!(symbols.resolve("draw + 2"), Some(0x212));
+        assert_eq!(symbols.resolve("0x2F0"), Some(0x2F0));
+        assert_eq!(symbols.resolve("#2F0"), Some(0x2F0));
+        assert_eq!(symbols.resolve("2F0"), Some(0x2F0));
+        assert_eq!(symbols.resolve("nowhere"), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            SymbolMap::parse("0200 main\nzz main"),
+            Err(SymbolError::Parse { line: 2 })
+        );
+        assert_eq!(
+            SymbolMap::parse("0200 .data x"),
+            Err(SymbolError::Parse { line: 1 })
+        );
+        assert_eq!(
+            SymbolMap::parse("0200"),
+            Err(SymbolError::Parse { line: 1 })
+        );
+    }
+}
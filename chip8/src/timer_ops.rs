@@ -0,0 +1,77 @@
+use crate::chip8::Chip8;
+use crate::error::{Chip8Error, StepOutcome};
+
+impl Chip8 {
+    // Count the delay and sound timers down by one, call this at 60 Hz
+    // Also marks the vertical blank that DXYN waits for under the display wait quirk
+    pub fn tick_timers(&mut self) {
+        self.vblank_wait = false;
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    // Emulate one 60 Hz frame: run `cycles_per_frame` instructions then tick the timers once
+    // The frame ends early when a DXYN waits for the vertical blank or the program exits.
+    // Stops at the first error without ticking the timers.
+    pub fn run_frame(&mut self, cycles_per_frame: usize) -> Result<(), Chip8Error> {
+        for _ in 0..cycles_per_frame {
+            match self.emulate_cycle()? {
+                StepOutcome::WaitingForVblank | StepOutcome::Exited => break,
+                _ => {}
+            }
+        }
+        self.tick_timers();
+        Ok(())
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // The buzzer sounds for as long as the sound timer is non-zero
+    pub fn beeper_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    // XO-CHIP 128-bit audio pattern, played one bit at a time while the beeper is active
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    // Bits of the audio pattern played per second, 4000 at the default pitch of 64
+    pub fn audio_sample_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Chip8;
+
+    #[test]
+    pub fn test_timers_tick_per_frame() {
+        let mut chip = Chip8::new();
+        // LD V0, 0x03 ; LD DT, V0 ; LD ST, V0 ; JP 0x206
+        chip.load_program(&[0x60, 0x03, 0xF0, 0x15, 0xF0, 0x18, 0x12, 0x06])
+            .unwrap();
+        chip.run_frame(10).unwrap();
+        assert_eq!(chip.delay_timer(), 2);
+        assert!(chip.beeper_active());
+
+        chip.run_frame(10).unwrap();
+        chip.run_frame(10).unwrap();
+        assert_eq!(chip.delay_timer(), 0);
+        assert!(!chip.beeper_active());
+
+        chip.tick_timers();
+        assert_eq!(chip.sound_timer(), 0);
+    }
+}
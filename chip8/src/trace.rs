@@ -0,0 +1,391 @@
+// Execution traces
+//
+// A `Tracer` attached with `Chip8::set_tracer` sees every instruction `emulate_cycle`
+// executes and records the machine state right after it: the cycle count, the address and
+// word of the instruction, its mnemonic, V0..VF, I, SP, the timers and optionally the RAM
+// bytes it wrote. Records can be kept in a bounded ring (for the GUI) and streamed to a
+// writer in one of two formats, one record per line:
+//
+//   Text        cycle  pc   op   mnemonic            V0..VF                           I       SP    DT    ST
+//               42     0204 F055 LD [I], V0          05000000000000000000000000000000 I=0301 SP=0 DT=00 ST=00 [0300]=05
+//   JSON lines  {"cycle":42,"pc":516,"opcode":61525,"mnemonic":"LD [I], V0","v":[5,0,...],...}
+//
+// Filters limit which instructions are recorded, the cycle count still counts them all.
+// Opcode classes are the leading hex digit of the instruction, `8` for the ALU and so on.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::chip8::Chip8;
+use crate::decode::decode;
+use crate::disasm::{format_op, Syntax};
+use crate::symbols::SymbolMap;
+
+/// The machine right after one traced instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceRecord {
+    /// Instructions executed before this one since the tracer was attached.
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub mnemonic: String,
+    pub v: [u8; 16],
+    pub i: u16,
+    /// Call stack depth.
+    pub sp: u8,
+    pub dt: u8,
+    pub st: u8,
+    /// Address and value of every RAM byte written, when the tracer records them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub writes: Vec<(u16, u8)>,
+}
+
+impl TraceRecord {
+    pub fn to_text(&self) -> String {
+        let mut line = format!(
+            "{:<6} {:04X} {:04X} {:<19} ",
+            self.cycle, self.pc, self.opcode, self.mnemonic
+        );
+        for value in &self.v {
+            let _ = write!(line, "{:02X}", value);
+        }
+        let _ = write!(
+            line,
+            " I={:04X} SP={:X} DT={:02X} ST={:02X}",
+            self.i, self.sp, self.dt, self.st
+        );
+        for (address, value) in &self.writes {
+            let _ = write!(line, " [{:04X}]={:02X}", address, value);
+        }
+        line
+    }
+
+    /// Read back a line written by [`to_text`](Self::to_text), spacing may differ.
+    pub fn from_text(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let registers = fields.iter().position(|field| field.starts_with("I="))?;
+        if registers < 4 {
+            return None;
+        }
+        let hex = |field: &str, prefix: &str| {
+            field
+                .strip_prefix(prefix)
+                .and_then(|value| u16::from_str_radix(value, 16).ok())
+        };
+        let values = fields[registers - 1];
+        if values.len() != 32 || !values.is_ascii() {
+            return None;
+        }
+        let mut v = [0; 16];
+        for (index, value) in v.iter_mut().enumerate() {
+            *value = u8::from_str_radix(&values[index * 2..index * 2 + 2], 16).ok()?;
+        }
+        let mut writes = Vec::new();
+        for field in fields.get(registers + 4..)? {
+            let (address, value) = field.strip_prefix('[')?.split_once("]=")?;
+            let address = u16::from_str_radix(address, 16).ok()?;
+            writes.push((address, u8::from_str_radix(value, 16).ok()?));
+        }
+        Some(Self {
+            cycle: fields[0].parse().ok()?,
+            pc: hex(fields[1], "")?,
+            opcode: hex(fields[2], "")?,
+            mnemonic: fields[3..registers - 1].join(" "),
+            v,
+            i: hex(fields[registers], "I=")?,
+            sp: hex(fields.get(registers + 1)?, "SP=")? as u8,
+            dt: hex(fields.get(registers + 2)?, "DT=")? as u8,
+            st: hex(fields.get(registers + 3)?, "ST=")? as u8,
+            writes,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("trace records always serialize")
+    }
+}
+
+/// How [`Tracer::set_output`] writes records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    JsonLines,
+}
+
+/// Records executed instructions, see [`Chip8::set_tracer`].
+pub struct Tracer {
+    cycle: u64,
+    capacity: usize,
+    ring: VecDeque<TraceRecord>,
+    output: Option<(Box<dyn Write + Send>, TraceFormat)>,
+    output_error: Option<io::Error>,
+    // Inclusive, `None` traces everywhere
+    range: Option<(u16, u16)>,
+    // Bit n set traces instructions starting with hex digit n
+    classes: u16,
+    writes: bool,
+    // Symbol names for the operands of mnemonics
+    labels: BTreeMap<u16, String>,
+}
+
+impl Tracer {
+    /// A tracer keeping the last `capacity` records, 0 keeps none.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            cycle: 0,
+            capacity,
+            ring: VecDeque::with_capacity(capacity.min(4096)),
+            output: None,
+            output_error: None,
+            range: None,
+            classes: 0xFFFF,
+            writes: false,
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// Also write every record to `output`.
+    pub fn set_output(&mut self, output: Box<dyn Write + Send>, format: TraceFormat) {
+        self.output = Some((output, format));
+        self.output_error = None;
+    }
+
+    /// The error that stopped the output, records are still kept in the ring.
+    pub fn output_error(&self) -> Option<&io::Error> {
+        self.output_error.as_ref()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.output {
+            Some((output, _)) => output.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Only trace instructions from `start` to `end`, both included.
+    pub fn set_range(&mut self, range: Option<(u16, u16)>) {
+        self.range = range;
+    }
+
+    /// Only trace instructions whose leading hex digit is in `classes`, e.g. `[0x8, 0xD]`.
+    ///
+    /// An empty list traces all of them.
+    pub fn set_classes(&mut self, classes: &[u8]) {
+        self.classes = classes
+            .iter()
+            .fold(0, |mask, class| mask | 1 << (class & 0xF));
+        if self.classes == 0 {
+            self.classes = 0xFFFF;
+        }
+    }
+
+    /// Record the RAM bytes each instruction writes.
+    pub fn set_writes(&mut self, enabled: bool) {
+        self.writes = enabled;
+    }
+
+    /// Show addresses in mnemonics by name.
+    pub fn set_symbols(&mut self, symbols: &SymbolMap) {
+        self.labels = symbols
+            .names()
+            .map(|(address, name)| (address, name.to_string()))
+            .collect();
+    }
+
+    /// The kept records, oldest first.
+    pub fn records(&self) -> impl DoubleEndedIterator<Item = &TraceRecord> + ExactSizeIterator {
+        self.ring.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.ring.clear();
+    }
+
+    /// Instructions executed since the tracer was attached.
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    fn traces(&self, pc: u16, opcode: u16) -> bool {
+        let in_range = match self.range {
+            Some((start, end)) => (start..=end).contains(&pc),
+            None => true,
+        };
+        in_range && self.classes & (1 << (opcode >> 12)) != 0
+    }
+
+    fn record(&mut self, chip: &Chip8, pc: u16) {
+        let cycle = self.cycle;
+        self.cycle += 1;
+        let opcode = chip.curr_op;
+        if !self.traces(pc, opcode) {
+            return;
+        }
+        // Four bytes for the address of `LD I, long`, zero past the end of RAM
+        let mut bytes = [0; 4];
+        for (offset, byte) in bytes.iter_mut().enumerate() {
+            *byte = chip.ram.get(pc as usize + offset).copied().unwrap_or(0);
+        }
+        let writes = match (&chip.memory_log, self.writes) {
+            (Some(log), true) => log
+                .borrow()
+                .iter()
+                .filter(|access| access.write)
+                .map(|access| (access.address, access.value))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let record = TraceRecord {
+            cycle,
+            pc,
+            opcode,
+            mnemonic: format_op(decode(opcode), &bytes, Syntax::Cowgod, &self.labels),
+            v: chip.registers,
+            i: chip.index_register,
+            sp: chip.stack.len() as u8,
+            dt: chip.delay_timer,
+            st: chip.sound_timer,
+            writes,
+        };
+
+        if let Some((output, format)) = &mut self.output {
+            let line = match format {
+                TraceFormat::Text => record.to_text(),
+                TraceFormat::JsonLines => record.to_json(),
+            };
+            if let Err(err) = writeln!(output, "{}", line) {
+                self.output = None;
+                self.output_error = Some(err);
+            }
+        }
+        if self.capacity > 0 {
+            if self.ring.len() == self.capacity {
+                self.ring.pop_front();
+            }
+            self.ring.push_back(record);
+        }
+    }
+}
+
+impl Chip8 {
+    // Attach a tracer to every executed instruction, or detach it with `None`
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        if tracer.as_ref().map_or(false, |tracer| tracer.writes) {
+            self.set_memory_log(true);
+        }
+        self.tracer = tracer;
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    // Called by `emulate_cycle` after the instruction at `pc` executed
+    pub(crate) fn trace(&mut self, pc: u16) {
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.record(self, pc);
+            // Writes are recorded from the next instruction on when enabled late
+            if tracer.writes {
+                self.set_memory_log(true);
+            }
+            self.tracer = Some(tracer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TraceFormat, TraceRecord, Tracer};
+    use crate::{Chip8, SymbolMap};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    // 200: LD V0, 05
+    // 202: LD I, 300
+    // 204: LD [I], V0
+    // 206: CALL 20A
+    // 20A: JP 20A
+    const PROGRAM: [u8; 12] = [
+        0x60, 0x05, 0xA3, 0x00, 0xF0, 0x55, 0x22, 0x0A, 0x00, 0x00, 0x12, 0x0A,
+    ];
+
+    fn chip(tracer: Tracer) -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.load_program(&PROGRAM).unwrap();
+        chip.set_tracer(Some(tracer));
+        chip
+    }
+
+    // A writer the test can still read after handing it to the tracer
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_text_trace() {
+        let mut tracer = Tracer::new(3);
+        tracer.set_writes(true);
+        tracer.set_symbols(&SymbolMap::parse("020A idle").unwrap());
+        let output = Shared::default();
+        tracer.set_output(Box::new(output.clone()), TraceFormat::Text);
+        let mut chip = chip(tracer);
+        for _ in 0..5 {
+            chip.emulate_cycle().unwrap();
+        }
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[2],
+            "2      0204 F055 LD [I], V0          05000000000000000000000000000000 I=0301 SP=0 DT=00 ST=00 [0300]=05"
+        );
+        assert!(lines[3].contains("CALL idle"));
+
+        let tracer = chip.tracer().unwrap();
+        let cycles: Vec<u64> = tracer.records().map(|record| record.cycle).collect();
+        assert_eq!(cycles, [2, 3, 4]);
+        assert_eq!(tracer.records().last().unwrap().sp, 1);
+    }
+
+    #[test]
+    fn test_json_trace_and_filters() {
+        let mut tracer = Tracer::new(10);
+        tracer.set_range(Some((0x202, 0x208)));
+        tracer.set_classes(&[0xA, 0x2]);
+        let output = Shared::default();
+        tracer.set_output(Box::new(output.clone()), TraceFormat::JsonLines);
+        let mut chip = chip(tracer);
+        for _ in 0..5 {
+            chip.emulate_cycle().unwrap();
+        }
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let records: Vec<TraceRecord> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let pcs: Vec<u16> = records.iter().map(|record| record.pc).collect();
+        assert_eq!(pcs, [0x202, 0x206]);
+        assert_eq!(records[0].i, 0x300);
+        assert_eq!(records[1].mnemonic, "CALL #20A");
+        assert_eq!(chip.tracer().unwrap().cycle(), 5);
+    }
+}
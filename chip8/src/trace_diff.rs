@@ -0,0 +1,216 @@
+// Comparing execution traces
+//
+// Two traces, typically a known-good one from another interpreter and one from this
+// emulator, are compared record by record. The first record where the program counter,
+// the instruction, a register, the timers or the RAM writes differ is the divergence, and
+// usually points straight at a quirk both interpreters handle differently. Mnemonics and
+// cycle numbers are not compared, they depend on symbols and on where tracing started.
+
+use std::fmt;
+
+use crate::error::TraceError;
+use crate::trace::TraceRecord;
+
+/// What differs between two trace records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceField {
+    Pc,
+    Opcode,
+    V(u8),
+    I,
+    Sp,
+    Dt,
+    St,
+    /// The RAM bytes the instruction wrote.
+    Writes,
+    /// One of the traces has no more records.
+    End,
+}
+
+/// The first place two traces disagree, see [`diff_traces`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDiff {
+    /// Position of the differing records in both traces.
+    pub index: usize,
+    pub fields: Vec<TraceField>,
+}
+
+/// Read a trace in either format `Tracer` writes, blank lines and `#` comments are skipped.
+pub fn parse_trace(text: &str) -> Result<Vec<TraceRecord>, TraceError> {
+    let mut records = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let record = if line.starts_with('{') {
+            serde_json::from_str(line).ok()
+        } else {
+            TraceRecord::from_text(line)
+        };
+        records.push(record.ok_or(TraceError::Parse { line: index + 1 })?);
+    }
+    Ok(records)
+}
+
+/// The first divergence between `expected` and `actual`, `None` when they match.
+pub fn diff_traces(expected: &[TraceRecord], actual: &[TraceRecord]) -> Option<TraceDiff> {
+    for (index, (left, right)) in expected.iter().zip(actual).enumerate() {
+        let fields = compare(left, right);
+        if !fields.is_empty() {
+            return Some(TraceDiff { index, fields });
+        }
+    }
+    if expected.len() != actual.len() {
+        return Some(TraceDiff {
+            index: expected.len().min(actual.len()),
+            fields: vec![TraceField::End],
+        });
+    }
+    None
+}
+
+fn compare(left: &TraceRecord, right: &TraceRecord) -> Vec<TraceField> {
+    let mut fields = Vec::new();
+    if left.pc != right.pc {
+        fields.push(TraceField::Pc);
+    }
+    if left.opcode != right.opcode {
+        fields.push(TraceField::Opcode);
+    }
+    for x in 0..16 {
+        if left.v[x] != right.v[x] {
+            fields.push(TraceField::V(x as u8));
+        }
+    }
+    if left.i != right.i {
+        fields.push(TraceField::I);
+    }
+    if left.sp != right.sp {
+        fields.push(TraceField::Sp);
+    }
+    if left.dt != right.dt {
+        fields.push(TraceField::Dt);
+    }
+    if left.st != right.st {
+        fields.push(TraceField::St);
+    }
+    if left.writes != right.writes {
+        fields.push(TraceField::Writes);
+    }
+    fields
+}
+
+impl TraceDiff {
+    /// A readable account of the divergence with `context` records before and after it.
+    ///
+    /// Records both traces agree on are printed once, differing ones as a `-` line from
+    /// `expected` and a `+` line from `actual`.
+    pub fn report(
+        &self,
+        expected: &[TraceRecord],
+        actual: &[TraceRecord],
+        context: usize,
+    ) -> String {
+        let mut lines = Vec::new();
+        let shared = self.index.min(expected.len());
+        for record in &expected[shared.saturating_sub(context)..shared] {
+            lines.push(format!("  {}", record.to_text()));
+        }
+        let after = |records: &[TraceRecord], sign: char, lines: &mut Vec<String>| {
+            let end = records.len().min(self.index + context + 1);
+            for record in records.get(self.index..end).unwrap_or_default() {
+                lines.push(format!("{} {}", sign, record.to_text()));
+            }
+        };
+        after(expected, '-', &mut lines);
+        after(actual, '+', &mut lines);
+
+        let fields: Vec<String> = self.fields.iter().map(ToString::to_string).collect();
+        let cycle = match expected.get(self.index).or_else(|| actual.get(self.index)) {
+            Some(record) => format!(" (cycle {})", record.cycle),
+            None => String::new(),
+        };
+        lines.push(format!(
+            "first difference at record {}{}: {}",
+            self.index + 1,
+            cycle,
+            fields.join(", ")
+        ));
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for TraceField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceField::Pc => write!(f, "PC"),
+            TraceField::Opcode => write!(f, "opcode"),
+            TraceField::V(x) => write!(f, "V{:X}", x),
+            TraceField::I => write!(f, "I"),
+            TraceField::Sp => write!(f, "SP"),
+            TraceField::Dt => write!(f, "DT"),
+            TraceField::St => write!(f, "ST"),
+            TraceField::Writes => write!(f, "memory writes"),
+            TraceField::End => write!(f, "end of trace"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_traces, parse_trace, TraceField};
+    use crate::{Chip8, Quirks, TraceRecord, Tracer};
+
+    // 200: LD V1, 81
+    // 202: LD V2, 02
+    // 204: SHR V1, V2
+    // 206: JP 206
+    const PROGRAM: [u8; 8] = [0x61, 0x81, 0x62, 0x02, 0x81, 0x26, 0x12, 0x06];
+
+    fn trace(quirks: Quirks) -> Vec<TraceRecord> {
+        let mut chip = Chip8::new();
+        chip.set_quirks(quirks);
+        chip.load_program(&PROGRAM).unwrap();
+        chip.set_tracer(Some(Tracer::new(16)));
+        for _ in 0..5 {
+            chip.emulate_cycle().unwrap();
+        }
+        chip.take_tracer().unwrap().records().cloned().collect()
+    }
+
+    #[test]
+    fn test_shift_quirk_divergence() {
+        let mut shift_vx = Quirks::default();
+        shift_vx.shift = !shift_vx.shift;
+        let expected = trace(Quirks::default());
+        let actual = trace(shift_vx);
+        assert_eq!(diff_traces(&expected, &expected), None);
+
+        let diff = diff_traces(&expected, &actual).unwrap();
+        assert_eq!(diff.index, 2);
+        assert!(diff.fields.contains(&TraceField::V(1)));
+        let report = diff.report(&expected, &actual, 1);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("  1 "));
+        assert!(lines[1].starts_with("- 2 ") && lines[3].starts_with("+ 2 "));
+        assert!(lines[5].starts_with("first difference at record 3 (cycle 2): V1"));
+
+        let shorter = diff_traces(&expected, &expected[..3]).unwrap();
+        assert_eq!((shorter.index, shorter.fields), (3, vec![TraceField::End]));
+    }
+
+    #[test]
+    fn test_parse_trace() {
+        let mut record = trace(Quirks::default()).remove(1);
+        record.mnemonic = "LD V2, #02".to_string();
+        record.writes = vec![(0x300, 0x05), (0xFFF, 0x10)];
+        let text = format!("# expected\n{}\n\n{}\n", record.to_text(), record.to_json());
+        assert_eq!(parse_trace(&text), Ok(vec![record.clone(), record]));
+        assert_eq!(
+            parse_trace("0 0200 6181 LD V1, #81 V=00").map_err(|err| err.to_string()),
+            Err("malformed trace record at line 1".to_string())
+        );
+    }
+}
@@ -0,0 +1,904 @@
+// Octo assembler
+//
+// One pass over the token stream. Jumps, calls, `i :=`, `i := long` and `:unpack`
+// may name a label that is only defined further down; they leave a fixup that is
+// patched once every label is known. Every other operand must already be defined.
+//
+// Like Octo, 0x200 holds a `jump main` so data and subroutines can come first.
+
+use std::collections::BTreeMap;
+
+use crate::calc::evaluate;
+use crate::error::AsmError;
+use crate::lexer::{parse_number, tokenize, Token};
+use crate::program::{Program, PROGRAM_START};
+
+const IMAGE_SIZE: usize = 0x10000;
+// Guards against macros that expand themselves forever
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// Assemble Octo source into a ROM image loadable by `Chip8::load_program`.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut assembler = Assembler::new(tokenize(source));
+    assembler.run()?;
+    assembler.finish()
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+#[derive(Clone, Copy)]
+enum FixupKind {
+    // Low 12 bits of the opcode word
+    Address,
+    // The word after `F000`
+    Long,
+    // `:unpack`, the immediate of `6XKK` holds the nibble and the high address bits
+    UnpackHigh(u8),
+    UnpackLow,
+}
+
+struct Fixup {
+    at: usize,
+    kind: FixupKind,
+    name: Token,
+}
+
+enum Block {
+    // `loop`, with the jumps that `while` leaves to patch once `again` is reached
+    Loop { start: u16, exits: Vec<usize> },
+    // `if ... begin`, the jump taken when the condition fails
+    If { skip: usize },
+    Else { end: usize },
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+}
+
+#[derive(Clone, Copy)]
+enum Condition {
+    Equal(u8, Operand),
+    NotEqual(u8, Operand),
+    Key(u8),
+    NotKey(u8),
+}
+
+impl Condition {
+    fn negate(self) -> Self {
+        match self {
+            Condition::Equal(x, rhs) => Condition::NotEqual(x, rhs),
+            Condition::NotEqual(x, rhs) => Condition::Equal(x, rhs),
+            Condition::Key(x) => Condition::NotKey(x),
+            Condition::NotKey(x) => Condition::Key(x),
+        }
+    }
+
+    // The skip instruction after which the next instruction only runs when the condition holds
+    fn skip_unless(self) -> u16 {
+        let x = |x: u8| (x as u16) << 8;
+        match self {
+            Condition::Equal(vx, Operand::Byte(kk)) => 0x4000 | x(vx) | kk as u16,
+            Condition::NotEqual(vx, Operand::Byte(kk)) => 0x3000 | x(vx) | kk as u16,
+            Condition::Equal(vx, Operand::Register(vy)) => 0x9000 | x(vx) | (vy as u16) << 4,
+            Condition::NotEqual(vx, Operand::Register(vy)) => 0x5000 | x(vx) | (vy as u16) << 4,
+            Condition::Key(vx) => 0xE0A1 | x(vx),
+            Condition::NotKey(vx) => 0xE09E | x(vx),
+        }
+    }
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    pos: usize,
+    image: Vec<u8>,
+    here: usize,
+    // One past the highest address written
+    end: usize,
+    labels: BTreeMap<String, u16>,
+    consts: BTreeMap<String, f64>,
+    aliases: BTreeMap<String, u8>,
+    macros: BTreeMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Block, Token)>,
+    lines: Vec<(u16, usize)>,
+    data: Vec<(u16, usize)>,
+    expansions: usize,
+}
+
+impl Assembler {
+    fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            image: vec![0; IMAGE_SIZE],
+            here: PROGRAM_START as usize,
+            end: PROGRAM_START as usize,
+            labels: BTreeMap::new(),
+            consts: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            macros: BTreeMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            lines: Vec::new(),
+            data: Vec::new(),
+            expansions: 0,
+        }
+    }
+
+    fn run(&mut self) -> Result<(), AsmError> {
+        let main = Token {
+            text: "main".to_string(),
+            line: 1,
+            column: 1,
+        };
+        self.fixups.push(Fixup {
+            at: self.here,
+            kind: FixupKind::Address,
+            name: main,
+        });
+        self.emit_word(0x1000)?;
+
+        while let Some(token) = self.tokens.get(self.pos).cloned() {
+            self.pos += 1;
+            self.statement(&token)?;
+        }
+        match self.blocks.last() {
+            Some((Block::Loop { .. }, open)) => Err(open.error("`loop` without `again`")),
+            Some((_, open)) => Err(open.error("`begin` without `end`")),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(mut self) -> Result<Program, AsmError> {
+        if !self.labels.contains_key("main") {
+            return Err(AsmError::new(1, 1, "missing `: main` label"));
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let address = *self.labels.get(&fixup.name.text).ok_or_else(|| {
+                fixup
+                    .name
+                    .error(format!("undefined label `{}`", fixup.name.text))
+            })?;
+            match fixup.kind {
+                FixupKind::Address => {
+                    let address = check_address(address as f64, &fixup.name)?;
+                    self.image[fixup.at] = (self.image[fixup.at] & 0xF0) | (address >> 8) as u8;
+                    self.image[fixup.at + 1] = address as u8;
+                }
+                FixupKind::Long => {
+                    self.image[fixup.at..fixup.at + 2].copy_from_slice(&address.to_be_bytes());
+                }
+                FixupKind::UnpackHigh(nibble) => {
+                    self.image[fixup.at + 1] = nibble << 4 | (address >> 8) as u8 & 0x0F;
+                }
+                FixupKind::UnpackLow => self.image[fixup.at + 1] = address as u8,
+            }
+        }
+        self.lines.sort_unstable();
+        Ok(Program {
+            bytes: self.image[PROGRAM_START as usize..self.end].to_vec(),
+            labels: self.labels,
+            data: self.data,
+            lines: self.lines,
+        })
+    }
+
+    fn statement(&mut self, token: &Token) -> Result<(), AsmError> {
+        let op = |high: u16, x: u8| high | (x as u16) << 8;
+        match token.text.as_str() {
+            // `:next` labels the byte after the next one, the operand of the coming instruction
+            ":" | ":next" => {
+                let name = self.name()?;
+                let address = self.here + (token.text == ":next") as usize;
+                if address >= IMAGE_SIZE {
+                    return Err(name.error(format!("label `{}` is past 0xFFFF", name.text)));
+                }
+                self.define_label(&name, address as u16)?;
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value_token = self.next(token)?;
+                let value = self.value(&value_token)?;
+                if self.consts.insert(name.text.clone(), value).is_some() {
+                    return Err(name.error(format!("constant `{}` is already defined", name.text)));
+                }
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc(token)?;
+                self.consts.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register(token)?;
+                self.aliases.insert(name.text, register);
+            }
+            ":macro" => self.define_macro(token)?,
+            ":byte" => {
+                let value = match self.tokens.get(self.pos) {
+                    Some(open) if open.text == "{" => self.calc(token)?,
+                    _ => {
+                        let value_token = self.next(token)?;
+                        self.value(&value_token)?
+                    }
+                };
+                let byte = check_byte(value, token)?;
+                self.emit_data(byte, token)?;
+            }
+            ":org" => {
+                let value_token = self.next(token)?;
+                let value = self.value(&value_token)?;
+                if value < PROGRAM_START as f64 || value >= IMAGE_SIZE as f64 {
+                    return Err(value_token.error(format!(
+                        "`:org` must be between 0x200 and 0xFFFF, found {:#X}",
+                        value as i64
+                    )));
+                }
+                self.here = value as usize;
+            }
+            ":call" => self.address_op(0x2000, token)?,
+            ":unpack" => {
+                let nibble_token = self.next(token)?;
+                let nibble = check_nibble(self.value(&nibble_token)?, &nibble_token)?;
+                let target = self.next(token)?;
+                let address = self.resolve_address(&target, FixupKind::UnpackHigh(nibble), 0)?;
+                self.emit_op(0x6000 | (nibble as u16) << 4 | address >> 8 & 0x0F, token)?;
+                let address = self.resolve_address(&target, FixupKind::UnpackLow, 0)?;
+                self.emit_op(0x6100 | (address & 0xFF), token)?;
+            }
+            ":breakpoint" => {
+                self.name()?;
+            }
+            "clear" => self.emit_op(0x00E0, token)?,
+            "return" | ";" => self.emit_op(0x00EE, token)?,
+            "scroll-right" => self.emit_op(0x00FB, token)?,
+            "scroll-left" => self.emit_op(0x00FC, token)?,
+            "exit" => self.emit_op(0x00FD, token)?,
+            "lores" => self.emit_op(0x00FE, token)?,
+            "hires" => self.emit_op(0x00FF, token)?,
+            "audio" => self.emit_op(0xF002, token)?,
+            "scroll-down" | "scroll-up" | "plane" => {
+                let value_token = self.next(token)?;
+                let n = check_nibble(self.value(&value_token)?, &value_token)? as u16;
+                let word = match token.text.as_str() {
+                    "scroll-down" => 0x00C0 | n,
+                    "scroll-up" => 0x00D0 | n,
+                    _ => 0xF001 | n << 8,
+                };
+                self.emit_op(word, token)?;
+            }
+            "jump" => self.address_op(0x1000, token)?,
+            "jump0" => self.address_op(0xB000, token)?,
+            "native" => self.address_op(0x0000, token)?,
+            "sprite" => {
+                let x = self.register(token)?;
+                let y = self.register(token)?;
+                let value_token = self.next(token)?;
+                let n = check_nibble(self.value(&value_token)?, &value_token)?;
+                self.emit_op(op(0xD000, x) | (y as u16) << 4 | n as u16, token)?;
+            }
+            "save" | "load" => {
+                let x = self.register(token)?;
+                let range = self
+                    .tokens
+                    .get(self.pos)
+                    .map_or(false, |dash| dash.text == "-");
+                if range {
+                    self.pos += 1;
+                    let y = self.register(token)?;
+                    let low = if token.text == "save" { 0x2 } else { 0x3 };
+                    self.emit_op(op(0x5000, x) | (y as u16) << 4 | low, token)?;
+                } else {
+                    let low = if token.text == "save" { 0x55 } else { 0x65 };
+                    self.emit_op(op(0xF000, x) | low, token)?;
+                }
+            }
+            "saveflags" | "loadflags" | "bcd" => {
+                let x = self.register(token)?;
+                let low = match token.text.as_str() {
+                    "saveflags" => 0x75,
+                    "loadflags" => 0x85,
+                    _ => 0x33,
+                };
+                self.emit_op(op(0xF000, x) | low, token)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=", token)?;
+                let x = self.register(token)?;
+                let low = match token.text.as_str() {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.emit_op(op(0xF000, x) | low, token)?;
+            }
+            "i" => self.index_statement(token)?,
+            "if" => {
+                let condition = self.condition(token)?;
+                let keyword = self.next(token)?;
+                match keyword.text.as_str() {
+                    "then" => self.emit_op(condition.skip_unless(), token)?,
+                    "begin" => {
+                        self.emit_op(condition.negate().skip_unless(), token)?;
+                        let skip = self.here;
+                        self.emit_op(0x1000, token)?;
+                        self.blocks.push((Block::If { skip }, token.clone()));
+                    }
+                    other => {
+                        return Err(
+                            keyword.error(format!("expected `then` or `begin`, found `{}`", other))
+                        )
+                    }
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some((Block::If { skip }, open)) => {
+                    let end = self.here;
+                    self.emit_op(0x1000, token)?;
+                    self.patch_jump(skip, token)?;
+                    self.blocks.push((Block::Else { end }, open));
+                }
+                _ => return Err(token.error("`else` without `begin`")),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::If { skip: at }, _)) | Some((Block::Else { end: at }, _)) => {
+                    self.patch_jump(at, token)?;
+                }
+                _ => return Err(token.error("`end` without `begin`")),
+            },
+            "loop" => {
+                let start = self.here as u16;
+                let block = Block::Loop {
+                    start,
+                    exits: Vec::new(),
+                };
+                self.blocks.push((block, token.clone()));
+            }
+            "while" => {
+                let condition = self.condition(token)?;
+                self.emit_op(condition.negate().skip_unless(), token)?;
+                let exit = self.here;
+                self.emit_op(0x1000, token)?;
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(block, _)| match block {
+                        Block::Loop { exits, .. } => Some(exits),
+                        _ => None,
+                    }) {
+                    Some(exits) => exits.push(exit),
+                    None => return Err(token.error("`while` outside of `loop`")),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop { start, exits }, _)) => {
+                    self.emit_op(0x1000 | check_address(start as f64, token)?, token)?;
+                    for exit in exits {
+                        self.patch_jump(exit, token)?;
+                    }
+                }
+                _ => return Err(token.error("`again` without `loop`")),
+            },
+            text => {
+                if let Some(value) = parse_number(text) {
+                    let byte = check_byte(value, token)?;
+                    self.emit_data(byte, token)?;
+                } else if let Some(x) = self.register_name(text) {
+                    self.register_statement(x, token)?;
+                } else if self.macros.contains_key(text) {
+                    self.expand_macro(token)?;
+                } else if is_name(text) {
+                    // A bare label calls it
+                    let address = self.resolve_address(token, FixupKind::Address, 0)?;
+                    self.emit_op(0x2000 | address, token)?;
+                } else {
+                    return Err(token.error(format!("unexpected `{}`", text)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // `vx := ...`, `vx += ...` and the other register operations
+    fn register_statement(&mut self, x: u8, token: &Token) -> Result<(), AsmError> {
+        let vx = (x as u16) << 8;
+        let operator = self.next(token)?;
+        let rhs = self.next(&operator)?;
+        let vy = self.register_name(&rhs.text).map(|y| (y as u16) << 4);
+        let alu = |low: u16| vy.map(|vy| 0x8000 | vx | vy | low);
+        let word = match (operator.text.as_str(), rhs.text.as_str()) {
+            (":=", "random") => {
+                let mask_token = self.next(&rhs)?;
+                let mask = check_byte(self.value(&mask_token)?, &mask_token)?;
+                Some(0xC000 | vx | mask as u16)
+            }
+            (":=", "delay") => Some(0xF007 | vx),
+            (":=", "key") => Some(0xF00A | vx),
+            (":=", _) => alu(0x0),
+            ("|=", _) => alu(0x1),
+            ("&=", _) => alu(0x2),
+            ("^=", _) => alu(0x3),
+            ("+=", _) => alu(0x4),
+            ("-=", _) => alu(0x5),
+            (">>=", _) => alu(0x6),
+            ("=-", _) => alu(0x7),
+            ("<<=", _) => alu(0xE),
+            _ => {
+                return Err(operator.error(format!(
+                    "expected an assignment operator after `{}`, found `{}`",
+                    token.text, operator.text
+                )))
+            }
+        };
+        let word = match (word, operator.text.as_str()) {
+            (Some(word), _) => word,
+            // Only `:=`, `+=` and `-=` take a constant
+            (None, ":=") => 0x6000 | vx | check_byte(self.value(&rhs)?, &rhs)? as u16,
+            (None, "+=") => 0x7000 | vx | check_byte(self.value(&rhs)?, &rhs)? as u16,
+            (None, "-=") => {
+                let byte = check_byte(self.value(&rhs)?, &rhs)?;
+                0x7000 | vx | byte.wrapping_neg() as u16
+            }
+            (None, _) => {
+                return Err(rhs.error(format!("expected a register, found `{}`", rhs.text)))
+            }
+        };
+        self.emit_op(word, token)
+    }
+
+    // `i := addr`, `i := long addr`, `i := hex vx`, `i := bighex vx` and `i += vx`
+    fn index_statement(&mut self, token: &Token) -> Result<(), AsmError> {
+        let operator = self.next(token)?;
+        match operator.text.as_str() {
+            "+=" => {
+                let x = self.register(token)?;
+                self.emit_op(0xF01E | (x as u16) << 8, token)
+            }
+            ":=" => {
+                let rhs = self.next(&operator)?;
+                match rhs.text.as_str() {
+                    "hex" | "bighex" => {
+                        let x = self.register(token)?;
+                        let low = if rhs.text == "hex" { 0x29 } else { 0x30 };
+                        self.emit_op(0xF000 | (x as u16) << 8 | low, token)
+                    }
+                    "long" => {
+                        let target = self.next(&rhs)?;
+                        self.emit_op(0xF000, token)?;
+                        let address = self.resolve_address(&target, FixupKind::Long, 0)?;
+                        self.emit_word(address)
+                    }
+                    _ => {
+                        let address = self.resolve_address(&rhs, FixupKind::Address, 0)?;
+                        self.emit_op(0xA000 | address, token)
+                    }
+                }
+            }
+            other => Err(operator.error(format!(
+                "expected `:=` or `+=` after `i`, found `{}`",
+                other
+            ))),
+        }
+    }
+
+    // `vx == operand`, `vx != operand`, `vx key` or `vx -key`
+    fn condition(&mut self, token: &Token) -> Result<Condition, AsmError> {
+        let x = self.register(token)?;
+        let operator = self.next(token)?;
+        match operator.text.as_str() {
+            "key" => return Ok(Condition::Key(x)),
+            "-key" => return Ok(Condition::NotKey(x)),
+            "==" | "!=" => {}
+            "<" | ">" | "<=" | ">=" => {
+                return Err(operator.error(format!(
+                    "comparison `{}` is not supported, compare through `vf` instead",
+                    operator.text
+                )))
+            }
+            other => {
+                return Err(operator.error(format!("expected a comparison, found `{}`", other)))
+            }
+        }
+        let rhs = self.next(&operator)?;
+        let operand = match self.register_name(&rhs.text) {
+            Some(y) => Operand::Register(y),
+            None => Operand::Byte(check_byte(self.value(&rhs)?, &rhs)?),
+        };
+        Ok(if operator.text == "==" {
+            Condition::Equal(x, operand)
+        } else {
+            Condition::NotEqual(x, operand)
+        })
+    }
+
+    fn define_macro(&mut self, token: &Token) -> Result<(), AsmError> {
+        let name = self.name()?;
+        let mut params = Vec::new();
+        loop {
+            let param = self.next(token)?;
+            if param.text == "{" {
+                break;
+            }
+            params.push(param.text);
+        }
+        let body = self.block(token)?;
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    // Splice the macro body into the token stream in place of the invocation
+    fn expand_macro(&mut self, token: &Token) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(token.error(format!("macro `{}` expands too deeply", token.text)));
+        }
+        let count = self.macros[&token.text].params.len();
+        let mut args = Vec::new();
+        for _ in 0..count {
+            args.push(self.next(token)?);
+        }
+        let definition = &self.macros[&token.text];
+        let body: Vec<Token> = definition
+            .body
+            .iter()
+            .map(
+                |part| match definition.params.iter().position(|p| *p == part.text) {
+                    Some(index) => args[index].clone(),
+                    None => part.clone(),
+                },
+            )
+            .collect();
+        self.tokens.splice(self.pos..self.pos, body);
+        Ok(())
+    }
+
+    // `{ expression }`, the opening brace is the next token
+    fn calc(&mut self, token: &Token) -> Result<f64, AsmError> {
+        let open = self.next(token)?;
+        if open.text != "{" {
+            return Err(open.error(format!("expected `{{`, found `{}`", open.text)));
+        }
+        let body = self.block(&open)?;
+        let here = self.here as f64;
+        let lookup = |name: &str| match name {
+            "HERE" => Some(here),
+            _ => self
+                .consts
+                .get(name)
+                .copied()
+                .or_else(|| self.labels.get(name).map(|address| *address as f64)),
+        };
+        evaluate(&body, &open, &lookup)
+    }
+
+    // Tokens up to the `}` matching an already consumed `{`
+    fn block(&mut self, open: &Token) -> Result<Vec<Token>, AsmError> {
+        let mut depth = 0;
+        let mut body = Vec::new();
+        loop {
+            let token = self
+                .tokens
+                .get(self.pos)
+                .cloned()
+                .ok_or_else(|| open.error("`{` without `}`"))?;
+            self.pos += 1;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(body),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+    }
+
+    fn next(&mut self, after: &Token) -> Result<Token, AsmError> {
+        let token =
+            self.tokens.get(self.pos).cloned().ok_or_else(|| {
+                after.error(format!("unexpected end of file after `{}`", after.text))
+            })?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, text: &str, after: &Token) -> Result<(), AsmError> {
+        let token = self.next(after)?;
+        if token.text == text {
+            Ok(())
+        } else {
+            Err(token.error(format!("expected `{}`, found `{}`", text, token.text)))
+        }
+    }
+
+    // A name being defined
+    fn name(&mut self) -> Result<Token, AsmError> {
+        let previous = self.tokens[self.pos - 1].clone();
+        let token = self.next(&previous)?;
+        if !is_name(&token.text) || self.register_name(&token.text).is_some() {
+            return Err(token.error(format!("`{}` is not a valid name", token.text)));
+        }
+        Ok(token)
+    }
+
+    fn register(&mut self, after: &Token) -> Result<u8, AsmError> {
+        let token = self.next(after)?;
+        self.register_name(&token.text)
+            .ok_or_else(|| token.error(format!("expected a register, found `{}`", token.text)))
+    }
+
+    fn register_name(&self, text: &str) -> Option<u8> {
+        if let Some(register) = self.aliases.get(text) {
+            return Some(*register);
+        }
+        let digit = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+        match digit.len() {
+            1 => u8::from_str_radix(digit, 16).ok(),
+            _ => None,
+        }
+    }
+
+    // A number, constant or already defined label
+    fn value(&self, token: &Token) -> Result<f64, AsmError> {
+        parse_number(&token.text)
+            .or_else(|| self.consts.get(&token.text).copied())
+            .or_else(|| self.labels.get(&token.text).map(|address| *address as f64))
+            .ok_or_else(|| token.error(format!("undefined name `{}`", token.text)))
+    }
+
+    // An address operand, leaving a fixup at `self.here + offset` for undefined labels
+    fn resolve_address(
+        &mut self,
+        token: &Token,
+        kind: FixupKind,
+        offset: usize,
+    ) -> Result<u16, AsmError> {
+        if parse_number(&token.text).is_none()
+            && !self.consts.contains_key(&token.text)
+            && !self.labels.contains_key(&token.text)
+            && is_name(&token.text)
+        {
+            self.fixups.push(Fixup {
+                at: self.here + offset,
+                kind,
+                name: token.clone(),
+            });
+            return Ok(0);
+        }
+        let value = self.value(token)?;
+        match kind {
+            FixupKind::Long => {
+                if value < 0.0 || value > 0xFFFF as f64 {
+                    return Err(token.error(format!(
+                        "address {:#X} does not fit in 16 bits",
+                        value as i64
+                    )));
+                }
+                Ok(value as u16)
+            }
+            FixupKind::Address => check_address(value, token),
+            // `:unpack` takes any 16-bit value and keeps the low 12 bits
+            _ => Ok(value as u16 & 0x0FFF),
+        }
+    }
+
+    fn address_op(&mut self, high: u16, token: &Token) -> Result<(), AsmError> {
+        let target = self.next(token)?;
+        let address = self.resolve_address(&target, FixupKind::Address, 0)?;
+        self.emit_op(high | address, token)
+    }
+
+    fn define_label(&mut self, name: &Token, address: u16) -> Result<(), AsmError> {
+        if self.labels.insert(name.text.clone(), address).is_some() {
+            return Err(name.error(format!("label `{}` is already defined", name.text)));
+        }
+        Ok(())
+    }
+
+    // Point the placeholder jump at `at` to the current address
+    fn patch_jump(&mut self, at: usize, token: &Token) -> Result<(), AsmError> {
+        let target = check_address(self.here as f64, token)?;
+        self.image[at..at + 2].copy_from_slice(&(0x1000 | target).to_be_bytes());
+        Ok(())
+    }
+
+    fn emit_op(&mut self, word: u16, token: &Token) -> Result<(), AsmError> {
+        self.lines.push((self.here as u16, token.line));
+        self.emit_word(word)
+            .map_err(|_| token.error("program does not fit in 64 KiB"))
+    }
+
+    fn emit_data(&mut self, byte: u8, token: &Token) -> Result<(), AsmError> {
+        let address = self.here as u16;
+        match self.data.last_mut() {
+            Some((start, len)) if *start as usize + *len == self.here => *len += 1,
+            _ => self.data.push((address, 1)),
+        }
+        self.emit_byte(byte)
+            .map_err(|_| token.error("program does not fit in 64 KiB"))
+    }
+
+    fn emit_word(&mut self, word: u16) -> Result<(), AsmError> {
+        let [high, low] = word.to_be_bytes();
+        self.emit_byte(high)?;
+        self.emit_byte(low)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AsmError> {
+        if self.here >= IMAGE_SIZE {
+            return Err(AsmError::new(1, 1, "program does not fit in 64 KiB"));
+        }
+        self.image[self.here] = byte;
+        self.here += 1;
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn check_byte(value: f64, token: &Token) -> Result<u8, AsmError> {
+    if (-128.0..=255.0).contains(&value) {
+        Ok(value as i64 as u8)
+    } else {
+        Err(token.error(format!("{} does not fit in a byte", value)))
+    }
+}
+
+fn check_nibble(value: f64, token: &Token) -> Result<u8, AsmError> {
+    if (0.0..=15.0).contains(&value) {
+        Ok(value as u8)
+    } else {
+        Err(token.error(format!("{} does not fit in a nibble", value)))
+    }
+}
+
+fn check_address(value: f64, token: &Token) -> Result<u16, AsmError> {
+    if (0.0..=0xFFF as f64).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(token.error(format!(
+            "address {:#X} does not fit in 12 bits, use `i := long`",
+            value as i64
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+    use chip8::{Chip8, Quirks};
+
+    fn bytes(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().bytes
+    }
+
+    #[test]
+    fn test_instructions_and_forward_labels() {
+        let program = assemble(
+            ": main\n\
+             \tv0 := 0x2A v1 += 1 v2 := v0 v2 -= v1 v3 := random 0x0F\n\
+             \ti := sprite sprite v0 v1 5\n\
+             \tdraw jump main\n\
+             : draw clear return\n\
+             : sprite 0xF0 0b10010000",
+        )
+        .unwrap();
+        assert_eq!(
+            program.bytes,
+            [
+                0x12, 0x02, 0x60, 0x2A, 0x71, 0x01, 0x82, 0x00, 0x82, 0x15, 0xC3, 0x0F, 0xA2, 0x18,
+                0xD0, 0x15, 0x22, 0x14, 0x12, 0x02, 0x00, 0xE0, 0x00, 0xEE, 0xF0, 0x90
+            ]
+        );
+        assert_eq!(program.labels["draw"], 0x214);
+        assert_eq!(program.data, [(0x218, 2)]);
+        assert_eq!(program.lines[0], (0x202, 2));
+        assert!(program
+            .symbol_map()
+            .contains("0214 draw\n0218 sprite\n0218 .data 2\n"));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        assert_eq!(
+            bytes(": main if v0 == 3 then v1 := 1 if v0 != v2 then v1 := 2 if v4 key then exit"),
+            [0x12, 0x02, 0x40, 0x03, 0x61, 0x01, 0x50, 0x20, 0x61, 0x02, 0xE4, 0xA1, 0x00, 0xFD]
+        );
+        assert_eq!(
+            bytes(": main if v0 == 1 begin v1 := 1 else v1 := 2 end"),
+            [0x12, 0x02, 0x30, 0x01, 0x12, 0x0A, 0x61, 0x01, 0x12, 0x0C, 0x61, 0x02]
+        );
+        assert_eq!(
+            bytes(": main loop v0 += 1 while v0 != 10 again"),
+            [0x12, 0x02, 0x70, 0x01, 0x40, 0x0A, 0x12, 0x0A, 0x12, 0x02]
+        );
+    }
+
+    #[test]
+    fn test_directives() {
+        let source = "\
+            :const SPEED 3\n\
+            :alias x v5\n\
+            :macro twice reg { reg += SPEED reg += SPEED }\n\
+            :calc DOUBLE { SPEED * 2 }\n\
+            : main\n\
+            \ttwice x\n\
+            \tx := DOUBLE\n\
+            \t:unpack 0xA data\n\
+            \ti := long data\n\
+            \t:byte { DOUBLE + 1 }\n\
+            :org 0x300\n\
+            : data 1 2";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            &program.bytes[..17],
+            [
+                0x12, 0x02, 0x75, 0x03, 0x75, 0x03, 0x65, 0x06, 0x60, 0xA3, 0x61, 0x00, 0xF0, 0x00,
+                0x03, 0x00, 0x07
+            ]
+        );
+        assert_eq!(program.bytes.len(), 0x102);
+        assert_eq!(program.labels["data"], 0x300);
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let err = |source: &str| {
+            let err = assemble(source).unwrap_err();
+            (err.line, err.column, err.message)
+        };
+        assert_eq!(
+            err(": main\n  v0 := 256"),
+            (2, 9, "256 does not fit in a byte".to_string())
+        );
+        assert_eq!(
+            err(": main\n jump nowhere"),
+            (2, 7, "undefined label `nowhere`".to_string())
+        );
+        assert_eq!(err(": main\n  v0 @= 1").1, 6);
+        assert_eq!(err(": main\n loop v0 += 1").0, 2);
+        assert_eq!(err(": main : main").2, "label `main` is already defined");
+        assert_eq!(err("v0 := 1").2, "missing `: main` label");
+        assert_eq!(err(": main\n  :calc X { 1 + }").1, 11);
+        assert_eq!(
+            err(": main\n  :org 0xFFFF\n  :next x"),
+            (3, 9, "label `x` is past 0xFFFF".to_string())
+        );
+        assert_eq!(
+            assemble(": main\n jump nowhere").unwrap_err().to_string(),
+            "2:7: undefined label `nowhere`"
+        );
+    }
+
+    #[test]
+    fn test_program_runs() {
+        let program = assemble(
+            ": main\n\
+             \ti := dot\n\
+             \tv0 := 2 v1 := 1\n\
+             \tsprite v0 v1 1\n\
+             \texit\n\
+             : dot 0b10000000",
+        )
+        .unwrap();
+        let mut chip = Chip8::new();
+        chip.set_quirks(Quirks::super_chip());
+        chip.load_program(&program.bytes).unwrap();
+        for _ in 0..3 {
+            chip.run_frame(10).unwrap();
+        }
+        assert!(chip.exited());
+        assert!(chip.display().is_lit(2, 1));
+        assert!(!chip.display().is_lit(3, 1));
+    }
+}
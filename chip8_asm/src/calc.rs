@@ -0,0 +1,157 @@
+// `:calc` expressions
+//
+// Like Octo, binary operators have no precedence and are evaluated right to left:
+// `2 * 3 + 4` is `2 * (3 + 4)`. Parentheses group as usual. Values are floating
+// point until they are used as an operand.
+
+use crate::error::AsmError;
+use crate::lexer::{parse_number, Token};
+
+const BINARY: [&str; 19] = [
+    "+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", ">", "<=", ">=",
+    "==", "!=",
+];
+const UNARY: [&str; 8] = ["-", "~", "!", "abs", "sqrt", "floor", "ceil", "sign"];
+
+struct Calc<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    lookup: &'a dyn Fn(&str) -> Option<f64>,
+    // Reported when the expression ends early
+    open: &'a Token,
+}
+
+/// Evaluate `tokens`, the contents of a `{ ... }` block opened by `open`.
+pub(crate) fn evaluate(
+    tokens: &[Token],
+    open: &Token,
+    lookup: &dyn Fn(&str) -> Option<f64>,
+) -> Result<f64, AsmError> {
+    let mut calc = Calc {
+        tokens,
+        pos: 0,
+        lookup,
+        open,
+    };
+    let value = calc.expression()?;
+    match calc.tokens.get(calc.pos) {
+        Some(extra) => Err(extra.error(format!("unexpected `{}` in expression", extra.text))),
+        None => Ok(value),
+    }
+}
+
+impl<'a> Calc<'a> {
+    fn next(&mut self) -> Result<&'a Token, AsmError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| self.open.error("expression ends early"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expression(&mut self) -> Result<f64, AsmError> {
+        let left = self.unary()?;
+        let op = match self.tokens.get(self.pos) {
+            Some(op) if BINARY.contains(&op.text.as_str()) => op,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.expression()?;
+        let (a, b) = (left as i64, right as i64);
+        let truth = |value: bool| if value { 1.0 } else { 0.0 };
+        Ok(match op.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" | "%" if right == 0.0 => return Err(op.error("division by zero")),
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.checked_shl(b as u32).unwrap_or(0) as f64,
+            ">>" => a.checked_shr(b as u32).unwrap_or(0) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => truth(left < right),
+            ">" => truth(left > right),
+            "<=" => truth(left <= right),
+            ">=" => truth(left >= right),
+            "==" => truth(left == right),
+            _ => truth(left != right),
+        })
+    }
+
+    fn unary(&mut self) -> Result<f64, AsmError> {
+        let token = self.next()?;
+        if UNARY.contains(&token.text.as_str()) {
+            let value = self.unary()?;
+            return Ok(match token.text.as_str() {
+                "-" => -value,
+                "~" => !(value as i64) as f64,
+                "!" => (value == 0.0) as u8 as f64,
+                "abs" => value.abs(),
+                "sqrt" => value.sqrt(),
+                "floor" => value.floor(),
+                "ceil" => value.ceil(),
+                _ => value.signum(),
+            });
+        }
+        if token.text == "(" {
+            let value = self.expression()?;
+            let close = self.next()?;
+            if close.text != ")" {
+                return Err(close.error(format!("expected `)`, found `{}`", close.text)));
+            }
+            return Ok(value);
+        }
+        if let Some(value) = parse_number(&token.text) {
+            return Ok(value);
+        }
+        match token.text.as_str() {
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            name => (self.lookup)(name)
+                .ok_or_else(|| token.error(format!("undefined name `{}` in expression", name))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::evaluate;
+    use crate::error::AsmError;
+    use crate::lexer::tokenize;
+
+    fn calc(source: &str) -> Result<f64, AsmError> {
+        let tokens = tokenize(source);
+        let lookup = |name: &str| if name == "SIZE" { Some(8.0) } else { None };
+        evaluate(&tokens[1..], &tokens[0], &lookup)
+    }
+
+    #[test]
+    fn test_right_to_left() {
+        assert_eq!(calc("{ 2 * 3 + 4").unwrap(), 14.0);
+        assert_eq!(calc("{ ( 2 * 3 ) + 4").unwrap(), 10.0);
+        assert_eq!(calc("{ 10 - 4 - 3").unwrap(), 9.0);
+        assert_eq!(calc("{ SIZE * 2 + - 1").unwrap(), 8.0);
+        assert_eq!(calc("{ 0xF0 >> 4 & 0x0F").unwrap(), 15.0);
+        assert_eq!(calc("{ floor ( 7 / 2 )").unwrap(), 3.0);
+        assert_eq!(calc("{ 3 min 2 == 2").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_errors_point_at_tokens() {
+        let err = calc("{ 1 +").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 1));
+        let err = calc("{ 1 + WIDTH").unwrap_err();
+        assert_eq!(
+            (err.line, err.column, err.message.as_str()),
+            (1, 7, "undefined name `WIDTH` in expression")
+        );
+        assert_eq!(calc("{ 1 / 0").unwrap_err().column, 5);
+        assert_eq!(calc("{ 1 2").unwrap_err().column, 5);
+    }
+}
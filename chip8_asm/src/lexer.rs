@@ -0,0 +1,110 @@
+// Tokenizer
+//
+// Octo source is a stream of whitespace separated tokens, `#` starts a comment that
+// runs to the end of the line. Braces and parentheses are tokens of their own and
+// need the surrounding whitespace too, `{ 1 + 2 }` rather than `{1+2}`.
+
+use crate::error::AsmError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Token {
+    pub(crate) text: String,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+
+impl Token {
+    pub(crate) fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError::new(self.line, self.column, message)
+    }
+}
+
+pub(crate) fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let mut start = None;
+        for (column, c) in code
+            .char_indices()
+            .chain(std::iter::once((code.len(), ' ')))
+        {
+            match (c.is_whitespace(), start) {
+                (true, Some(from)) => {
+                    tokens.push(Token {
+                        text: code[from..column].to_string(),
+                        line: index + 1,
+                        column: code[..from].chars().count() + 1,
+                    });
+                    start = None;
+                }
+                (false, None) => start = Some(column),
+                _ => {}
+            }
+        }
+    }
+    tokens
+}
+
+// Decimal, `0x` hexadecimal or `0b` binary, optionally negated
+pub(crate) fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(bin) = digits
+        .strip_prefix("0b")
+        .or_else(|| digits.strip_prefix("0B"))
+    {
+        i64::from_str_radix(bin, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse::<f64>().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_number, tokenize};
+
+    #[test]
+    fn test_tokenize_positions() {
+        let tokens = tokenize(": main # entry\n\tv0 := 0x2A\n\n  jump main");
+        let found: Vec<(&str, usize, usize)> = tokens
+            .iter()
+            .map(|token| (token.text.as_str(), token.line, token.column))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (":", 1, 1),
+                ("main", 1, 3),
+                ("v0", 2, 2),
+                (":=", 2, 5),
+                ("0x2A", 2, 8),
+                ("jump", 4, 3),
+                ("main", 4, 8)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("42"), Some(42.0));
+        assert_eq!(parse_number("-1"), Some(-1.0));
+        assert_eq!(parse_number("0xFF"), Some(255.0));
+        assert_eq!(parse_number("0b1010"), Some(10.0));
+        assert_eq!(parse_number("1.5"), Some(1.5));
+        assert_eq!(parse_number("v0"), None);
+        assert_eq!(parse_number("0xZZ"), None);
+    }
+}
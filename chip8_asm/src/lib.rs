@@ -0,0 +1,13 @@
+//! Assembler for Octo, the CHIP-8 assembly language of <https://johnearnest.github.io/Octo/>.
+//!
+//! Supports labels, `:const`, `:alias`, `:macro`, `:calc`, `:byte`, `:org`, `:call`,
+//! `:unpack` and `:next`, `loop`/`while`/`again`, `if`/`then` and `if`/`begin`/`else`/`end`,
+//! the SUPER-CHIP and XO-CHIP instructions and raw bytes for sprites.
+mod assembler;
+mod calc;
+mod error;
+mod lexer;
+mod program;
+pub use self::assembler::assemble;
+pub use self::error::AsmError;
+pub use self::program::{Program, PROGRAM_START};
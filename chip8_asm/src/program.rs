@@ -0,0 +1,40 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Address programs are assembled for, where `Chip8::load_program` puts them.
+pub const PROGRAM_START: u16 = 0x200;
+
+/// An assembled ROM and what the assembler knows about it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    /// The ROM image, starting at [`PROGRAM_START`].
+    pub bytes: Vec<u8>,
+    /// Every label and its address.
+    pub labels: BTreeMap<String, u16>,
+    /// Runs of literal data bytes as (address, length).
+    pub data: Vec<(u16, usize)>,
+    /// Source line of every instruction as (address, line), in address order.
+    pub lines: Vec<(u16, usize)>,
+}
+
+impl Program {
+    /// Symbol map text, one entry per line:
+    ///   `0200 main`        a label
+    ///   `0210 .data 8`     a run of data bytes
+    ///   `0202 .line 14`    the instruction at an address comes from a source line
+    pub fn symbol_map(&self) -> String {
+        let mut out = String::new();
+        let mut labels: Vec<(&String, &u16)> = self.labels.iter().collect();
+        labels.sort_by_key(|(name, address)| (**address, name.as_str()));
+        for (name, address) in labels {
+            let _ = writeln!(out, "{:04X} {}", address, name);
+        }
+        for (address, len) in &self.data {
+            let _ = writeln!(out, "{:04X} .data {}", address, len);
+        }
+        for (address, line) in &self.lines {
+            let _ = writeln!(out, "{:04X} .line {}", address, line);
+        }
+        out
+    }
+}
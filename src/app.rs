@@ -1,4 +1,89 @@
-use chip8::Chip8;
+use chip8::{
+    analyze_with_symbols, Chip8, Chip8Error, Clock, Debugger, Expr, InstructionRate, Listing,
+    Movie, MoviePlayer, MovieRecorder, Quirks, Register, RewindBuffer, StepOutcome, StopReason,
+    SymbolMap, Syntax, Tracer, WatchKind, DEFAULT_PALETTE, TIMER_HZ,
+};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Never try to catch up on more than this many frames at once (e.g. after the window was hidden)
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+// Cycles run between wall-clock checks when the instruction rate is unlimited
+const UNLIMITED_BATCH: usize = 256;
+
+// Run as many cycles as fit in half a frame of wall time, then tick the timers once
+fn run_unlimited_frame(chip8: &mut Chip8) -> Result<(), Chip8Error> {
+    let start = Instant::now();
+    'frame: while start.elapsed() < Clock::frame_duration() / 2 {
+        for _ in 0..UNLIMITED_BATCH {
+            match chip8.emulate_cycle()? {
+                StepOutcome::WaitingForVblank | StepOutcome::Exited => break 'frame,
+                _ => {}
+            }
+        }
+    }
+    chip8.tick_timers();
+    Ok(())
+}
+
+// Number of quick-save slots, bound to F1..F4 (load) and Shift+F1..F4 (save)
+const QUICK_SAVE_SLOTS: usize = 4;
+
+fn quick_save_slot(key: egui::Key) -> Option<usize> {
+    match key {
+        egui::Key::F1 => Some(0),
+        egui::Key::F2 => Some(1),
+        egui::Key::F3 => Some(2),
+        egui::Key::F4 => Some(3),
+        _ => None,
+    }
+}
+
+// Keypad rows as laid out on the COSMAC VIP
+const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+// Map the left-hand 1234/QWER/ASDF/ZXCV block of the host keyboard onto the hex keypad
+fn keypad_key(key: egui::Key) -> Option<u8> {
+    use egui::Key::*;
+    let key = match key {
+        Num1 => 0x1,
+        Num2 => 0x2,
+        Num3 => 0x3,
+        Num4 => 0xC,
+        Q => 0x4,
+        W => 0x5,
+        E => 0x6,
+        R => 0xD,
+        A => 0x7,
+        S => 0x8,
+        D => 0x9,
+        F => 0xE,
+        Z => 0xA,
+        X => 0x0,
+        C => 0xB,
+        V => 0xF,
+        _ => return None,
+    };
+    Some(key)
+}
+
+// Input movie being recorded or played back, see `chip8::Movie`
+enum MovieState {
+    Idle,
+    Recording(MovieRecorder),
+    Playing(MoviePlayer),
+}
+
+impl Default for MovieState {
+    fn default() -> Self {
+        MovieState::Idle
+    }
+}
+
 // We derive Deserialize/Serialize so we can persist app state on shutdown
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
@@ -9,6 +94,50 @@ pub struct Chip8App {
     #[serde(skip)]
     value: f32,
     chip8: Chip8,
+    rate: InstructionRate,
+    #[serde(skip)]
+    running: bool,
+    #[serde(skip)]
+    clock: Clock,
+    #[serde(skip)]
+    screen: Option<egui::TextureHandle>,
+    // Save states, see `Chip8::save_state`
+    slots: [Option<Vec<u8>>; QUICK_SAVE_SLOTS],
+    #[serde(skip)]
+    status: Option<String>,
+    #[serde(skip)]
+    history: RewindBuffer,
+    // Frames back from the newest one shown while scrubbing the timeline, 0 when live
+    #[serde(skip)]
+    rewind_position: usize,
+    rom_path: String,
+    // Contents of `rom_path` once loaded, movies are recorded against it
+    #[serde(skip)]
+    rom: Vec<u8>,
+    symbols_path: String,
+    // Names for the addresses of `rom`, used by the disassembly and the call stack
+    #[serde(skip)]
+    symbols: SymbolMap,
+    movie_path: String,
+    #[serde(skip)]
+    movie: MovieState,
+    syntax: Syntax,
+    // Keep the instruction at PC in view while running
+    follow_pc: bool,
+    // Disassembly of `rom` following its control flow, rebuilt when it or `syntax` changes
+    #[serde(skip)]
+    listing: Option<Listing>,
+    #[serde(skip)]
+    debugger: Debugger,
+    // Address, symbol or register typed into the debugger panel
+    debug_input: String,
+    // Condition for the next breakpoint, empty for an unconditional one
+    breakpoint_condition: String,
+    watch_input: String,
+    // Trace filters, an address range like `200-2FF` and opcode classes like `8 D`
+    trace_range: String,
+    trace_classes: String,
+    trace_writes: bool,
 }
 
 impl Default for Chip8App {
@@ -17,6 +146,30 @@ impl Default for Chip8App {
             label: "Chip8 Emulator".to_owned(),
             value: 0.0f32,
             chip8: Chip8::new(),
+            rate: InstructionRate::default(),
+            running: false,
+            clock: Clock::new(),
+            screen: None,
+            slots: Default::default(),
+            status: None,
+            history: RewindBuffer::default(),
+            rewind_position: 0,
+            rom_path: String::new(),
+            rom: Vec::new(),
+            symbols_path: String::new(),
+            symbols: SymbolMap::new(),
+            movie_path: String::new(),
+            movie: MovieState::Idle,
+            syntax: Syntax::default(),
+            follow_pc: true,
+            listing: None,
+            debugger: Debugger::new(),
+            debug_input: String::new(),
+            breakpoint_condition: String::new(),
+            watch_input: String::new(),
+            trace_range: String::new(),
+            trace_classes: String::new(),
+            trace_writes: false,
         }
     }
 }
@@ -35,38 +188,403 @@ impl Chip8App {
     }
 }
 
+// Save the machine into a slot, or restore it; the result is shown in the CPU panel
+fn quick_save(chip8: &Chip8, slots: &mut [Option<Vec<u8>>], slot: usize) -> String {
+    slots[slot] = Some(chip8.save_state());
+    format!("Saved slot {}", slot + 1)
+}
+
+fn quick_load(chip8: &mut Chip8, slots: &[Option<Vec<u8>>], slot: usize) -> String {
+    match &slots[slot] {
+        Some(state) => match chip8.load_state(state) {
+            Ok(()) => format!("Loaded slot {}", slot + 1),
+            Err(err) => format!("Slot {}: {}", slot + 1, err),
+        },
+        None => format!("Slot {} is empty", slot + 1),
+    }
+}
+
+// Seed for a freshly loaded ROM, so games differ between sessions but movies record it
+fn session_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+// Power on a new machine with the ROM at `path`, keeping the current settings
+fn load_rom(chip8: &mut Chip8, rom: &mut Vec<u8>, path: &str) -> Result<String, String> {
+    let data = std::fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+    let mut fresh = Chip8::with_seed(session_seed());
+    fresh.set_quirks(chip8.quirks());
+    fresh.set_xo_chip(chip8.is_xo_chip());
+    fresh.set_key_wait_mode(chip8.key_wait_mode());
+    fresh.initialize_ram();
+    fresh
+        .load_program(&data)
+        .map_err(|err| format!("{}: {}", path, err))?;
+    fresh.set_tracer(chip8.take_tracer());
+    *chip8 = fresh;
+    *rom = data;
+    Ok(format!("Loaded {}", path))
+}
+
+fn load_symbols(path: &str) -> Result<SymbolMap, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    SymbolMap::parse(&text).map_err(|err| format!("{}: {}", path, err))
+}
+
+// Status line for a debugger stop, naming where the program counter is now
+fn stop_message(stop: StopReason, chip8: &Chip8, symbols: &SymbolMap) -> String {
+    let pc = chip8.program_counter();
+    format!("Stopped ({}) at {}", stop, symbols.describe(pc))
+}
+
+// Records kept for the trace panel
+const TRACE_RECORDS: usize = 256;
+
+fn parse_hex(text: &str) -> Result<u16, String> {
+    u16::from_str_radix(text.trim(), 16).map_err(|_| format!("Bad address {}", text.trim()))
+}
+
+// A tracer for the trace panel, an empty filter traces everything
+fn build_tracer(
+    range: &str,
+    classes: &str,
+    writes: bool,
+    symbols: &SymbolMap,
+) -> Result<Tracer, String> {
+    let mut tracer = Tracer::new(TRACE_RECORDS);
+    if !range.trim().is_empty() {
+        let (start, end) = range
+            .split_once('-')
+            .ok_or("Range must look like 200-2FF")?;
+        tracer.set_range(Some((parse_hex(start)?, parse_hex(end)?)));
+    }
+    let classes = classes
+        .split_whitespace()
+        .map(|class| match u8::from_str_radix(class, 16) {
+            Ok(class) if class < 16 => Ok(class),
+            _ => Err(format!("Bad opcode class {}", class)),
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+    tracer.set_classes(&classes);
+    tracer.set_writes(writes);
+    tracer.set_symbols(symbols);
+    Ok(tracer)
+}
+
+// Restart the loaded ROM from power-on and record everything from there
+fn start_recording(
+    chip8: &mut Chip8,
+    rom: &[u8],
+    rate: InstructionRate,
+) -> Result<MovieRecorder, String> {
+    let cycles = rate
+        .cycles_per_frame()
+        .ok_or("Movies need a fixed instruction rate")?;
+    let mut movie = Movie::new(rom, cycles);
+    movie.seed = session_seed();
+    movie.quirks = chip8.quirks();
+    movie.xo_chip = chip8.is_xo_chip();
+    movie.key_wait_mode = chip8.key_wait_mode();
+    let tracer = chip8.take_tracer();
+    *chip8 = movie.power_on(rom).map_err(|err| err.to_string())?;
+    chip8.set_tracer(tracer);
+    Ok(MovieRecorder::new(movie))
+}
+
+fn start_playback(chip8: &mut Chip8, rom: &[u8], path: &str) -> Result<MoviePlayer, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let movie = Movie::parse(&text).map_err(|err| format!("{}: {}", path, err))?;
+    let tracer = chip8.take_tracer();
+    *chip8 = movie.power_on(rom).map_err(|err| err.to_string())?;
+    chip8.set_tracer(tracer);
+    Ok(MoviePlayer::new(movie))
+}
+
 impl eframe::App for Chip8App {
     // Called by the frame work to save state before shutdown
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         eframe::set_value(storage, eframe::APP_KEY, self)
     }
 
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let Self {
             label,
-            value,
+            value: _,
             chip8,
+            rate,
+            running,
+            clock,
+            screen,
+            slots,
+            status,
+            history,
+            rewind_position,
+            rom_path,
+            rom,
+            symbols_path,
+            symbols,
+            movie_path,
+            movie,
+            syntax,
+            follow_pc,
+            listing,
+            debugger,
+            debug_input,
+            breakpoint_condition,
+            watch_input,
+            trace_range,
+            trace_classes,
+            trace_writes,
         } = self;
 
+        // Keys typed into a text field, like the breakpoint or watch expressions, are not keypad input
+        let typing = ctx.wants_keyboard_input();
+        for event in &ctx.input().events {
+            if let egui::Event::Key {
+                key,
+                pressed,
+                modifiers,
+            } = event
+            {
+                if let (Some(slot), true) = (quick_save_slot(*key), *pressed) {
+                    *status = Some(if modifiers.shift {
+                        quick_save(chip8, slots, slot)
+                    } else {
+                        *rewind_position = 0;
+                        *movie = MovieState::Idle;
+                        quick_load(chip8, slots, slot)
+                    });
+                } else if let Some(key) = keypad_key(*key) {
+                    // Only let a key held from before the text field took focus come back up
+                    if typing && (*pressed || !chip8.keypad().is_pressed(key)) {
+                        continue;
+                    }
+                    // Ignore auto-repeat so a held key only presses once, a playing movie owns the keypad
+                    match movie {
+                        MovieState::Playing(_) => {}
+                        MovieState::Recording(recorder) => {
+                            if !*pressed {
+                                recorder.release(chip8, key);
+                            } else if !chip8.keypad().is_pressed(key) {
+                                recorder.press(chip8, key);
+                            }
+                        }
+                        MovieState::Idle => {
+                            if !*pressed {
+                                chip8.release(key);
+                            } else if !chip8.keypad().is_pressed(key) {
+                                chip8.press(key);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Hold Backspace to step back one frame per repaint, movies cannot be rewound and
+        // Backspace in a text field only edits the text
+        let movie_active = !matches!(movie, MovieState::Idle);
+        if ctx.input().key_down(egui::Key::Backspace)
+            && !movie_active
+            && !ctx.wants_keyboard_input()
+        {
+            if let Err(err) = history.rewind(chip8, *rewind_position + 1) {
+                *status = Some(err.to_string());
+            }
+            *rewind_position = 0;
+            clock.reset();
+            ctx.request_repaint();
+        } else if *running {
+            // Resuming from a point on the timeline drops the frames after it
+            if *rewind_position > 0 {
+                if let Err(err) = history.rewind(chip8, *rewind_position) {
+                    *status = Some(err.to_string());
+                }
+                *rewind_position = 0;
+            }
+            let elapsed = Duration::from_secs_f32(ctx.input().unstable_dt);
+            let frames = clock.frames_due(elapsed).min(MAX_CATCH_UP_FRAMES);
+            for _ in 0..frames {
+                // Breakpoints and watchpoints only apply at a fixed rate without a movie
+                let result = match (&mut *movie, rate.cycles_per_frame()) {
+                    (MovieState::Recording(recorder), _) => {
+                        recorder.run_frame(chip8).map(|()| None)
+                    }
+                    (MovieState::Playing(player), _) => player.run_frame(chip8).map(|()| None),
+                    (MovieState::Idle, Some(cycles)) => debugger.run_frame(chip8, cycles),
+                    (MovieState::Idle, None) => run_unlimited_frame(chip8).map(|()| None),
+                };
+                if let MovieState::Playing(player) = movie {
+                    if player.finished() {
+                        *status = Some(format!("Movie finished after {} frames", player.frame()));
+                        *movie = MovieState::Idle;
+                    }
+                }
+                // The fault stays on the machine and is shown in the CPU panel
+                if result.is_err() || chip8.exited() {
+                    *running = false;
+                    break;
+                }
+                history.push(chip8);
+                if let Ok(Some(stop)) = result {
+                    *status = Some(stop_message(stop, chip8, symbols));
+                    *running = false;
+                    break;
+                }
+            }
+            ctx.request_repaint();
+        }
+
         //  Examples of how to create different panels and windows
         //  Pick whichever suits you
         // Tip: a good default choice is just to keep the `CentralPanel`
         // For inspiration and more examples, go to https://emilk.github.io/egui
 
+        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("ROM:");
+                ui.text_edit_singleline(rom_path);
+                if ui.button("Load").clicked() {
+                    let mut message = load_rom(chip8, rom, rom_path).unwrap_or_else(|err| err);
+                    // Pick up `game.sym` next to `game.ch8`, as written by the assembler
+                    let sibling = std::path::Path::new(rom_path.as_str()).with_extension("sym");
+                    *symbols = SymbolMap::new();
+                    if sibling.is_file() {
+                        *symbols_path = sibling.to_string_lossy().into_owned();
+                        match load_symbols(symbols_path) {
+                            Ok(loaded) => *symbols = loaded,
+                            Err(err) => message = format!("{}, {}", message, err),
+                        }
+                    }
+                    *status = Some(message);
+                    *listing = None;
+                    *movie = MovieState::Idle;
+                    history.clear();
+                    *rewind_position = 0;
+                }
+
+                ui.separator();
+                ui.label("Symbols:");
+                ui.text_edit_singleline(symbols_path);
+                if ui.button("Load").clicked() {
+                    match load_symbols(symbols_path) {
+                        Ok(loaded) => {
+                            *symbols = loaded;
+                            *status = Some(format!("Loaded {}", symbols_path));
+                        }
+                        Err(err) => *status = Some(err),
+                    }
+                    *listing = None;
+                }
+
+                ui.separator();
+                ui.label("Movie:");
+                ui.text_edit_singleline(movie_path);
+                match movie {
+                    MovieState::Idle => {
+                        let has_rom = !rom.is_empty();
+                        if ui
+                            .add_enabled(has_rom, egui::Button::new("Record"))
+                            .clicked()
+                        {
+                            match start_recording(chip8, rom, *rate) {
+                                Ok(recorder) => {
+                                    *movie = MovieState::Recording(recorder);
+                                    *running = true;
+                                    clock.reset();
+                                }
+                                Err(err) => *status = Some(err),
+                            }
+                        }
+                        if ui.add_enabled(has_rom, egui::Button::new("Play")).clicked() {
+                            match start_playback(chip8, rom, movie_path) {
+                                Ok(player) => {
+                                    *movie = MovieState::Playing(player);
+                                    *running = true;
+                                    clock.reset();
+                                }
+                                Err(err) => *status = Some(err),
+                            }
+                        }
+                    }
+                    MovieState::Recording(recorder) => {
+                        ui.label(format!("Recording frame {}", recorder.frame()));
+                        if ui.button("Stop and save").clicked() {
+                            if let MovieState::Recording(recorder) = std::mem::take(movie) {
+                                let text = recorder.finish().to_text();
+                                *status = Some(match std::fs::write(&*movie_path, text) {
+                                    Ok(()) => format!("Saved movie to {}", movie_path),
+                                    Err(err) => format!("{}: {}", movie_path, err),
+                                });
+                            }
+                        }
+                    }
+                    MovieState::Playing(player) => {
+                        ui.label(format!(
+                            "Playing frame {} of {}",
+                            player.frame(),
+                            player.movie().frames
+                        ));
+                        if ui.button("Stop").clicked() {
+                            *movie = MovieState::Idle;
+                        }
+                    }
+                }
+            });
+        });
+
         egui::SidePanel::left("side_panel").show(ctx, |ui| {
-            ui.heading("( Dissassembly ) Side Panel");
-
-            let text_style = egui::TextStyle::Body;
-            let row_height = ui.text_style_height(&text_style);
-            let total_rows = 10_000;
-            egui::ScrollArea::vertical()
-                .auto_shrink([false, false])
-                .show_rows(ui, row_height, total_rows, |ui, row_range| {
-                    for row in row_range {
-                        let text = format!("Row {}/{}", row + 1, total_rows);
-                        ui.label(text);
+            ui.heading("Disassembly");
+            ui.horizontal(|ui| {
+                let cowgod = ui.selectable_value(syntax, Syntax::Cowgod, "Cowgod");
+                let octo = ui.selectable_value(syntax, Syntax::Octo, "Octo");
+                if cowgod.changed() || octo.changed() {
+                    *listing = None;
+                }
+                ui.checkbox(follow_pc, "Follow PC");
+            });
+
+            let listing = listing
+                .get_or_insert_with(|| analyze_with_symbols(rom, 0x200, symbols).listing(*syntax));
+            let pc = chip8.program_counter();
+            let text_style = egui::TextStyle::Monospace;
+            let row_height = ui.text_style_height(&text_style) + ui.spacing().item_spacing.y;
+            let mut scroll_area = egui::ScrollArea::vertical().auto_shrink([false, false]);
+            if let (true, true, Some(row)) = (*follow_pc, *running, listing.line_at(pc)) {
+                scroll_area = scroll_area.vertical_scroll_offset(row as f32 * row_height);
+            }
+            scroll_area.show_rows(ui, row_height, listing.lines.len(), |ui, row_range| {
+                for line in &listing.lines[row_range] {
+                    let text = format!(
+                        "{:04X} {:<10} {}",
+                        line.address,
+                        line.label.as_deref().unwrap_or(""),
+                        line.text
+                    );
+                    let breakpoint = if debugger.has_breakpoint(line.address) {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    let text = egui::RichText::new(format!("{}{}", breakpoint, text))
+                        .text_style(text_style.clone());
+                    // Click toggles a breakpoint, right click runs to the line
+                    let response = ui.selectable_label(line.address == pc, text);
+                    if response.clicked() {
+                        debugger.toggle_breakpoint(line.address);
                     }
-                });
+                    response.context_menu(|ui| {
+                        if ui.button("Run to here").clicked() {
+                            debugger.run_to(line.address);
+                            *running = true;
+                            clock.reset();
+                            ui.close_menu();
+                        }
+                    });
+                }
+            });
             ui.horizontal(|ui| {
                 ui.label("Write something: ");
                 ui.text_edit_singleline(label);
@@ -96,6 +614,32 @@ impl eframe::App for Chip8App {
             // The central panel the region left after adding TopPanel's and SidePanel's
 
             ui.heading("Main Screen");
+
+            // Only re-upload the frame when the emulator has drawn something
+            if chip8.draw_flag() || screen.is_none() {
+                let display = chip8.display();
+                let image = egui::ColorImage::from_rgba_unmultiplied(
+                    [display.width(), display.height()],
+                    &display.to_rgba8(&DEFAULT_PALETTE),
+                );
+                match screen {
+                    Some(texture) => texture.set(image, egui::TextureFilter::Nearest),
+                    None => {
+                        *screen =
+                            Some(ctx.load_texture("screen", image, egui::TextureFilter::Nearest))
+                    }
+                }
+                chip8.clear_draw_flag();
+            }
+            if let Some(texture) = screen {
+                // Scale the screen up to the available space, keeping the aspect ratio
+                let available = ui.available_size();
+                let texture_size = texture.size_vec2();
+                let scale = (available.x / texture_size.x)
+                    .min(available.y / texture_size.y)
+                    .max(1.0);
+                ui.image(texture.id(), texture_size * scale);
+            }
             // ui.hyperlink("https://github.com/emilk/eframe_template");
             // ui.add(egui::github_link_file!(
             //     "https://github.com/emilk/eframe_template/blob/master/",
@@ -104,8 +648,316 @@ impl eframe::App for Chip8App {
             egui::warn_if_debug_build(ui);
         });
 
-        egui::SidePanel::right("side_panel_right")
-            .show(ctx, |ui| ui.heading("Right Side Panel (CPU Information)"));
+        egui::SidePanel::right("side_panel_right").show(ctx, |ui| {
+            ui.heading("Right Side Panel (CPU Information)");
+
+            if ui.checkbox(running, "Running").changed() {
+                clock.reset();
+            }
+            ui.collapsing("Debugger", |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Step").clicked() {
+                        debugger.cancel_step();
+                        *running = false;
+                        *status = Some(match debugger.step_into(chip8) {
+                            Ok(stop) => stop_message(stop, chip8, symbols),
+                            Err(err) => err.to_string(),
+                        });
+                    }
+                    if ui.button("Step over").clicked() {
+                        debugger.step_over(chip8);
+                        *running = true;
+                        clock.reset();
+                    }
+                    if ui.button("Step out").clicked() {
+                        debugger.step_out(chip8);
+                        *running = true;
+                        clock.reset();
+                    }
+                });
+                // Addresses by number or symbol, registers by name
+                ui.text_edit_singleline(debug_input);
+                ui.horizontal(|ui| {
+                    ui.label("if");
+                    ui.text_edit_singleline(breakpoint_condition);
+                });
+                let address = symbols.resolve(debug_input);
+                let register = Register::parse(debug_input);
+                ui.horizontal(|ui| {
+                    if let Some(address) = address {
+                        if ui.button("Break").clicked() {
+                            // A bad condition is reported now rather than when it is reached
+                            if breakpoint_condition.trim().is_empty() {
+                                debugger.add_breakpoint(address);
+                            } else {
+                                match Expr::parse_with_symbols(breakpoint_condition, symbols) {
+                                    Ok(condition) => {
+                                        debugger.add_conditional_breakpoint(address, condition)
+                                    }
+                                    Err(err) => *status = Some(format!("Condition: {}", err)),
+                                }
+                            }
+                        }
+                        if ui.button("Run to").clicked() {
+                            debugger.run_to(address);
+                            *running = true;
+                            clock.reset();
+                        }
+                        if ui.button("Watch reads").clicked() {
+                            debugger.watch_memory(address, WatchKind::Read);
+                        }
+                        if ui.button("Watch writes").clicked() {
+                            debugger.watch_memory(address, WatchKind::Write);
+                        }
+                    }
+                    if let Some(register) = register {
+                        if ui.button("Watch register").clicked() {
+                            debugger.watch_register(register, chip8);
+                        }
+                    }
+                });
+                // Each entry can be removed with a click
+                let breakpoints: Vec<u16> = debugger.breakpoints().collect();
+                for address in breakpoints {
+                    let mut text = format!("break {:04X} {}", address, symbols.describe(address));
+                    if let Some(condition) = debugger.condition(address) {
+                        text = format!(
+                            "{} if {} ({} hits)",
+                            text,
+                            condition,
+                            debugger.hit_count(address)
+                        );
+                    }
+                    if ui.small_button(text).clicked() {
+                        debugger.remove_breakpoint(address);
+                    }
+                }
+                let watchpoints: Vec<(u16, WatchKind)> = debugger.memory_watchpoints().collect();
+                for (address, kind) in watchpoints {
+                    let kind = match kind {
+                        WatchKind::Read => "read",
+                        WatchKind::Write => "write",
+                        WatchKind::ReadWrite => "access",
+                    };
+                    let text = format!("{} {:04X} {}", kind, address, symbols.describe(address));
+                    if ui.small_button(text).clicked() {
+                        debugger.unwatch_memory(address);
+                    }
+                }
+                let registers: Vec<Register> = debugger.register_watchpoints().collect();
+                for register in registers {
+                    if ui.small_button(format!("change {}", register)).clicked() {
+                        debugger.unwatch_register(register);
+                    }
+                }
+            });
+            ui.collapsing("Watch", |ui| {
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(watch_input);
+                    if ui.button("Add").clicked() {
+                        match Expr::parse_with_symbols(watch_input, symbols) {
+                            Ok(expr) => {
+                                debugger.add_watch(expr);
+                                watch_input.clear();
+                            }
+                            Err(err) => *status = Some(format!("Watch: {}", err)),
+                        }
+                    }
+                });
+                let mut removed = None;
+                for (index, expr) in debugger.watches().iter().enumerate() {
+                    let value = expr.evaluate(chip8, 0);
+                    ui.horizontal(|ui| {
+                        if ui.small_button("x").clicked() {
+                            removed = Some(index);
+                        }
+                        ui.monospace(format!("{} = {} ({:#X})", expr, value, value));
+                    });
+                }
+                if let Some(index) = removed {
+                    debugger.remove_watch(index);
+                }
+            });
+            ui.collapsing("Trace", |ui| {
+                let mut enabled = chip8.tracer().is_some();
+                let toggled = ui.checkbox(&mut enabled, "Trace instructions").changed();
+                ui.checkbox(trace_writes, "Memory writes");
+                ui.horizontal(|ui| {
+                    ui.label("Addresses");
+                    ui.text_edit_singleline(trace_range);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Classes");
+                    ui.text_edit_singleline(trace_classes);
+                });
+                let apply = enabled && ui.button("Apply filters").clicked();
+                if toggled && !enabled {
+                    chip8.set_tracer(None);
+                } else if toggled || apply {
+                    match build_tracer(trace_range, trace_classes, *trace_writes, symbols) {
+                        Ok(tracer) => chip8.set_tracer(Some(tracer)),
+                        Err(err) => *status = Some(format!("Trace: {}", err)),
+                    }
+                }
+                if let Some(tracer) = chip8.tracer() {
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            for record in tracer.records() {
+                                ui.monospace(record.to_text());
+                            }
+                        });
+                }
+            });
+            ui.collapsing("Call stack", |ui| {
+                let pc = chip8.program_counter();
+                ui.monospace(format!("{:04X} {}", pc, symbols.describe(pc)));
+                // Innermost call first, each return address is just after its CALL
+                for ret in chip8.call_stack().rev() {
+                    let call = ret.wrapping_sub(2);
+                    ui.monospace(format!("{:04X} {}", call, symbols.describe(call)));
+                }
+            });
+            ui.collapsing("Rewind", |ui| {
+                let frames = history.len();
+                let timeline = egui::Slider::new(rewind_position, 0..=frames).text("frames back");
+                if ui.add(timeline).changed() {
+                    *running = false;
+                    if let Err(err) = history.peek(chip8, *rewind_position) {
+                        *status = Some(err.to_string());
+                    }
+                }
+                ui.label(format!(
+                    "{:.1} s of history, {} KiB",
+                    frames as f32 / TIMER_HZ as f32,
+                    history.memory_usage() / 1024
+                ));
+                ui.label("Hold Backspace to rewind");
+            });
+            let mut unlimited = *rate == InstructionRate::Unlimited;
+            if ui.checkbox(&mut unlimited, "Unlimited speed").changed() {
+                *rate = if unlimited {
+                    InstructionRate::Unlimited
+                } else {
+                    InstructionRate::default()
+                };
+            }
+            if let InstructionRate::PerSecond(ips) = rate {
+                ui.add(egui::Slider::new(ips, 60..=2000).text("instructions / s"));
+            }
+
+            // A movie records the settings it started with, they stay fixed until it ends
+            let movie_active = !matches!(movie, MovieState::Idle);
+            ui.add_enabled_ui(!movie_active, |ui| {
+                ui.collapsing("Quirks", |ui| {
+                    // The XO-CHIP preset is the quirks plus XO-CHIP mode, as with `--quirks xochip`
+                    let mut quirks = chip8.quirks();
+                    let is_current = |preset: &Quirks| {
+                        *preset == quirks && (*preset == Quirks::xo_chip()) == chip8.is_xo_chip()
+                    };
+                    let current = Quirks::presets()
+                        .into_iter()
+                        .find(|(_, preset)| is_current(preset))
+                        .map_or("Custom", |(name, _)| name);
+                    let mut picked = None;
+                    egui::ComboBox::from_label("Preset")
+                        .selected_text(current)
+                        .show_ui(ui, |ui| {
+                            for (name, preset) in Quirks::presets() {
+                                if ui.selectable_label(is_current(&preset), name).clicked() {
+                                    picked = Some(preset);
+                                }
+                            }
+                        });
+                    if let Some(preset) = picked {
+                        quirks = preset;
+                        chip8.set_xo_chip(preset == Quirks::xo_chip());
+                    }
+                    ui.checkbox(&mut quirks.shift, "Shift Vx in place");
+                    ui.checkbox(&mut quirks.jump, "BXNN jumps to XNN + Vx");
+                    ui.checkbox(&mut quirks.memory_increment, "FX55/FX65 increment I");
+                    ui.add_enabled(
+                        quirks.memory_increment,
+                        egui::Checkbox::new(
+                            &mut quirks.memory_increment_by_x,
+                            "...by X only (CHIP-48)",
+                        ),
+                    );
+                    ui.checkbox(&mut quirks.vf_reset, "Logic ops reset VF");
+                    ui.checkbox(&mut quirks.display_wait, "Wait for vblank on draw");
+                    ui.checkbox(&mut quirks.clipping, "Clip sprites");
+                    ui.checkbox(&mut quirks.super_chip_instructions, "SUPER-CHIP instructions");
+                    if quirks != chip8.quirks() {
+                        chip8.set_quirks(quirks);
+                    }
+                    let mut xo_chip = chip8.is_xo_chip();
+                    if ui
+                        .checkbox(&mut xo_chip, "XO-CHIP (64 KiB RAM, XO-CHIP instructions)")
+                        .changed()
+                    {
+                        chip8.set_xo_chip(xo_chip);
+                    }
+                });
+            });
+
+            ui.separator();
+            ui.monospace(format!(
+                "DT: {:3}  ST: {:3}",
+                chip8.delay_timer(),
+                chip8.sound_timer()
+            ));
+            if chip8.beeper_active() {
+                ui.colored_label(egui::Color32::YELLOW, "BEEP");
+            }
+
+            ui.separator();
+            egui::Grid::new("keypad").show(ui, |ui| {
+                for row in KEYPAD_LAYOUT {
+                    for key in row {
+                        let text = egui::RichText::new(format!("{:X}", key)).monospace();
+                        if chip8.keypad().is_pressed(key) {
+                            ui.label(text.strong().color(egui::Color32::LIGHT_GREEN));
+                        } else {
+                            ui.label(text.weak());
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+            if chip8.waiting_for_key() {
+                ui.label("Waiting for key...");
+            }
+            if let Some(fault) = chip8.fault() {
+                ui.colored_label(egui::Color32::RED, format!("Halted: {}", fault));
+            }
+            if chip8.exited() {
+                ui.label("Exited");
+            }
+
+            ui.separator();
+            ui.collapsing("Save states", |ui| {
+                egui::Grid::new("slots").show(ui, |ui| {
+                    for slot in 0..QUICK_SAVE_SLOTS {
+                        ui.label(format!("Slot {}", slot + 1));
+                        if ui.button("Save").clicked() {
+                            *status = Some(quick_save(chip8, slots, slot));
+                        }
+                        let load = egui::Button::new("Load");
+                        if ui.add_enabled(slots[slot].is_some(), load).clicked() {
+                            *rewind_position = 0;
+                            *movie = MovieState::Idle;
+                            *status = Some(quick_load(chip8, slots, slot));
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.label("F1-F4 load, Shift+F1-F4 save");
+            });
+            if let Some(status) = status {
+                ui.label(status.as_str());
+            }
+        });
         if false {
             egui::Window::new("Window").show(ctx, |ui| {
                 ui.label("Windows can be moved by dragging them.");
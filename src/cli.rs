@@ -0,0 +1,448 @@
+// Command line subcommands, the emulator window opens when none is given
+
+use chip8::{
+    analyze_with_symbols, diff_traces, parse_trace, ByteKind, Chip8, Chip8Error, DapServer,
+    GdbStub, InstructionRate, Quirks, StepOutcome, SymbolMap, Syntax, TraceFormat, TraceRecord,
+    Tracer,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::BufWriter;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+const USAGE: &str = "\
+usage: chip8_emu                      open the emulator window
+       chip8_emu run <rom.ch8> [run options]       run headless and print the final display
+       chip8_emu dump <rom.ch8> [run options]      run headless and print the RAM
+       chip8_emu disasm <rom.ch8> [--syntax cowgod|octo] [--symbols <rom.sym>]
+       chip8_emu info <rom.ch8>                    size and reachable code of a ROM
+       chip8_emu bench <rom.ch8> [run options]     instructions per second over 1000000 cycles
+       chip8_emu assemble <source.8o> [-o <rom.ch8>] [--symbols <rom.sym>]
+       chip8_emu gdb <rom.ch8> [--port <port>] [--quirks <preset>] [--seed <n>]
+                                                   debug with a GDB remote protocol client
+       chip8_emu dap [--port <port>]               debug adapter on stdio, or TCP with --port
+       chip8_emu trace-diff <expected> <actual> [--context <records>]
+                                                   first difference between two traces
+
+run options:
+       --quirks vip|chip48|schip|xochip            quirk preset, vip by default
+       --seed <n>                                  random seed, 0 by default
+       --rate <n>                                  instructions per second, 700 by default
+       --frames <n> | --cycles <n>                 how long to run, 600 frames by default
+       --trace <file> [--trace-format text|json]   write an execution trace, not for bench";
+
+// Port the GDB stub listens on when none is given
+const DEFAULT_GDB_PORT: u16 = 1234;
+// Records shown around the first difference of `trace-diff`
+const DEFAULT_DIFF_CONTEXT: usize = 3;
+// Ten seconds of emulated time
+const DEFAULT_RUN_FRAMES: u64 = 600;
+const DEFAULT_BENCH_CYCLES: u64 = 1_000_000;
+// Options of every subcommand that runs a ROM
+const RUN_OPTIONS: [&str; 7] = [
+    "--quirks",
+    "--seed",
+    "--rate",
+    "--cycles",
+    "--frames",
+    "--trace",
+    "--trace-format",
+];
+
+/// Run the subcommand in `args` (without the program name), returns the exit status.
+pub fn run(args: &[String]) -> i32 {
+    match dispatch(args) {
+        Ok(()) => 0,
+        Err(message) => {
+            eprintln!("{}", message);
+            1
+        }
+    }
+}
+
+fn dispatch(args: &[String]) -> Result<(), String> {
+    match args.split_first() {
+        Some((command, rest)) if command == "run" => run_rom(rest),
+        Some((command, rest)) if command == "dump" => dump(rest),
+        Some((command, rest)) if command == "disasm" => disasm(rest),
+        Some((command, rest)) if command == "info" => info(rest),
+        Some((command, rest)) if command == "bench" => bench(rest),
+        Some((command, rest)) if command == "assemble" => assemble(rest),
+        Some((command, rest)) if command == "gdb" => gdb(rest),
+        Some((command, rest)) if command == "dap" => dap(rest),
+        Some((command, rest)) if command == "trace-diff" => trace_diff(rest),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+// Positional arguments and `-o value` style options
+struct Args {
+    positional: Vec<String>,
+    options: BTreeMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: &[String], options: &[&str]) -> Result<Self, String> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            options: BTreeMap::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') {
+                parsed.positional.push(arg.clone());
+            } else if options.contains(&arg.as_str()) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs a value\n{}", arg, USAGE))?;
+                parsed.options.insert(arg.clone(), value.clone());
+            } else {
+                return Err(format!("unknown option {}\n{}", arg, USAGE));
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    // The value of a numeric option, `default` when it is not given
+    fn number<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.option(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("bad {} {}\n{}", name, value, USAGE)),
+            None => Ok(default),
+        }
+    }
+
+    // The only positional argument, the ROM for most subcommands
+    fn input(&self) -> Result<&str, String> {
+        match self.positional.as_slice() {
+            [input] => Ok(input),
+            _ => Err(USAGE.to_string()),
+        }
+    }
+}
+
+// Power on a machine with the quirks and seed in `args` and the ROM at `path` loaded
+fn power_on(args: &Args, path: &str) -> Result<Chip8, String> {
+    let rom = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+    let preset = args.option("--quirks").unwrap_or("vip");
+    let quirks =
+        Quirks::by_name(preset).ok_or_else(|| format!("unknown quirks {}\n{}", preset, USAGE))?;
+    let mut chip8 = Chip8::with_seed(args.number("--seed", 0)?);
+    chip8.set_quirks(quirks);
+    chip8.set_xo_chip(preset == "xochip");
+    chip8.initialize_ram();
+    chip8
+        .load_program(&rom)
+        .map_err(|err| format!("{}: {}", path, err))?;
+    Ok(chip8)
+}
+
+fn cycles_per_frame(args: &Args) -> Result<usize, String> {
+    let rate = match args.option("--rate") {
+        Some(_) => match args.number("--rate", 0)? {
+            0 => return Err(format!("--rate must be at least 1\n{}", USAGE)),
+            ips => InstructionRate::PerSecond(ips),
+        },
+        None => InstructionRate::default(),
+    };
+    Ok(rate
+        .cycles_per_frame()
+        .expect("rates per second are always fixed"))
+}
+
+// Run `cycles` calls of `emulate_cycle`, ticking the timers once per frame
+// A frame ends early when DXYN waits for the vertical blank. Returns the instructions executed.
+fn run_cycles(chip8: &mut Chip8, cycles: u64, cycles_per_frame: usize) -> Result<u64, Chip8Error> {
+    let mut executed = 0;
+    let mut in_frame = 0;
+    for _ in 0..cycles {
+        match chip8.emulate_cycle()? {
+            StepOutcome::Exited => break,
+            StepOutcome::WaitingForVblank => in_frame = cycles_per_frame,
+            StepOutcome::Executed => {
+                executed += 1;
+                in_frame += 1;
+            }
+            _ => in_frame += 1,
+        }
+        if in_frame >= cycles_per_frame {
+            chip8.tick_timers();
+            in_frame = 0;
+        }
+    }
+    Ok(executed)
+}
+
+fn run_frames(chip8: &mut Chip8, frames: u64, cycles_per_frame: usize) -> Result<(), Chip8Error> {
+    for _ in 0..frames {
+        if chip8.exited() {
+            break;
+        }
+        chip8.run_frame(cycles_per_frame)?;
+    }
+    Ok(())
+}
+
+// Run for `--cycles` or `--frames`, writing the `--trace` if asked for
+fn execute(args: &Args, chip8: &mut Chip8) -> Result<(), String> {
+    let cycles_per_frame = cycles_per_frame(args)?;
+    if let Some(path) = args.option("--trace") {
+        let format = match args.option("--trace-format").unwrap_or("text") {
+            "text" => TraceFormat::Text,
+            "json" => TraceFormat::JsonLines,
+            format => return Err(format!("unknown trace format {}\n{}", format, USAGE)),
+        };
+        let file = fs::File::create(path).map_err(|err| format!("{}: {}", path, err))?;
+        let mut tracer = Tracer::new(0);
+        tracer.set_writes(true);
+        tracer.set_output(Box::new(BufWriter::new(file)), format);
+        chip8.set_tracer(Some(tracer));
+    }
+
+    let result = match args.option("--cycles") {
+        Some(_) => run_cycles(chip8, args.number("--cycles", 0)?, cycles_per_frame).map(|_| ()),
+        None => {
+            let frames = args.number("--frames", DEFAULT_RUN_FRAMES)?;
+            run_frames(chip8, frames, cycles_per_frame)
+        }
+    };
+
+    if let Some(mut tracer) = chip8.take_tracer() {
+        let path = args.option("--trace").unwrap_or_default();
+        tracer.flush().map_err(|err| format!("{}: {}", path, err))?;
+        if let Some(err) = tracer.output_error() {
+            return Err(format!("{}: {}", path, err));
+        }
+    }
+    result.map_err(|err| err.to_string())
+}
+
+// Prints the display even when the ROM fails, it usually shows how far it got
+fn run_rom(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &RUN_OPTIONS)?;
+    let mut chip8 = power_on(&args, args.input()?)?;
+    let result = execute(&args, &mut chip8);
+    print!("{}", chip8.display().to_text());
+    result
+}
+
+fn dump(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &RUN_OPTIONS)?;
+    let mut chip8 = power_on(&args, args.input()?)?;
+    let result = execute(&args, &mut chip8);
+    print!("{}", chip8.ram_to_text(16));
+    result
+}
+
+fn disasm(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["--syntax", "--symbols"])?;
+    let input = args.input()?;
+    let syntax = match args.option("--syntax").unwrap_or("cowgod") {
+        "cowgod" => Syntax::Cowgod,
+        "octo" => Syntax::Octo,
+        syntax => return Err(format!("unknown syntax {}\n{}", syntax, USAGE)),
+    };
+    let symbols = match args.option("--symbols") {
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+            SymbolMap::parse(&text).map_err(|err| format!("{}: {}", path, err))?
+        }
+        None => SymbolMap::new(),
+    };
+    let rom = fs::read(input).map_err(|err| format!("{}: {}", input, err))?;
+    let analysis = analyze_with_symbols(&rom, 0x200, &symbols);
+    print!("{}", analysis.listing(syntax).to_text());
+    Ok(())
+}
+
+fn info(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[])?;
+    let input = args.input()?;
+    let rom = fs::read(input).map_err(|err| format!("{}: {}", input, err))?;
+    let analysis = analyze_with_symbols(&rom, 0x200, &SymbolMap::new());
+    let counts = analysis.counts();
+    println!("{}: {} bytes", input, rom.len());
+    println!(
+        "code: {} instructions, {} bytes",
+        counts[ByteKind::Code as usize],
+        counts[ByteKind::Code as usize] + counts[ByteKind::Operand as usize]
+    );
+    println!("data: {} bytes", counts[ByteKind::Data as usize]);
+    println!("unreached: {} bytes", counts[ByteKind::Unreached as usize]);
+    println!(
+        "targets: {} jumped to or called, {} loaded into I",
+        analysis.code_targets().len(),
+        analysis.data_targets().len()
+    );
+    Ok(())
+}
+
+// Runs as fast as possible, the instruction rate only decides when the timers tick
+fn bench(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &RUN_OPTIONS[..4])?;
+    let mut chip8 = power_on(&args, args.input()?)?;
+    let cycles = args.number("--cycles", DEFAULT_BENCH_CYCLES)?;
+    let cycles_per_frame = cycles_per_frame(&args)?;
+    let start = Instant::now();
+    let executed =
+        run_cycles(&mut chip8, cycles, cycles_per_frame).map_err(|err| err.to_string())?;
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "{} instructions in {:.3} s, {:.0} per second",
+        executed,
+        elapsed,
+        executed as f64 / elapsed.max(f64::EPSILON)
+    );
+    Ok(())
+}
+
+fn assemble(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["-o", "--symbols"])?;
+    let input = match args.positional.as_slice() {
+        [input] => input,
+        _ => return Err(USAGE.to_string()),
+    };
+    let source = fs::read_to_string(input).map_err(|err| format!("{}: {}", input, err))?;
+    let program = chip8_asm::assemble(&source).map_err(|err| format!("{}:{}", input, err))?;
+
+    let output = match args.option("-o") {
+        Some(output) => output.to_string(),
+        None => Path::new(input)
+            .with_extension("ch8")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    fs::write(&output, &program.bytes).map_err(|err| format!("{}: {}", output, err))?;
+    if let Some(symbols) = args.option("--symbols") {
+        fs::write(symbols, program.symbol_map()).map_err(|err| format!("{}: {}", symbols, err))?;
+    }
+    println!("{}: {} bytes", output, program.bytes.len());
+    Ok(())
+}
+
+fn gdb(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["--port", "--quirks", "--seed"])?;
+    let input = match args.positional.as_slice() {
+        [input] => input,
+        _ => return Err(USAGE.to_string()),
+    };
+    let port = match args.option("--port") {
+        Some(port) => port
+            .parse()
+            .map_err(|_| format!("bad port {}\n{}", port, USAGE))?,
+        None => DEFAULT_GDB_PORT,
+    };
+    let mut chip8 = power_on(&args, input)?;
+
+    let cycles = InstructionRate::default()
+        .cycles_per_frame()
+        .expect("the default rate is fixed");
+    let listener = GdbStub::listen(port).map_err(|err| format!("port {}: {}", port, err))?;
+    println!("waiting for a debugger on 127.0.0.1:{}", port);
+    let (stream, peer) = listener.accept().map_err(|err| err.to_string())?;
+    println!("{} attached", peer);
+    GdbStub::new(cycles)
+        .serve(&mut chip8, stream)
+        .map_err(|err| err.to_string())
+}
+
+// The ROM comes with the editor's launch request
+fn dap(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["--port"])?;
+    if !args.positional.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let mut server = DapServer::new();
+    let result = match args.option("--port") {
+        Some(port) => {
+            let port: u16 = port
+                .parse()
+                .map_err(|_| format!("bad port {}\n{}", port, USAGE))?;
+            let listener =
+                DapServer::listen(port).map_err(|err| format!("port {}: {}", port, err))?;
+            // Standard output is left alone, editors may be reading it
+            eprintln!("waiting for an editor on 127.0.0.1:{}", port);
+            let (stream, _) = listener.accept().map_err(|err| err.to_string())?;
+            let input = stream.try_clone().map_err(|err| err.to_string())?;
+            server.serve(input, stream)
+        }
+        None => server.serve(std::io::stdin(), std::io::stdout()),
+    };
+    result.map_err(|err| err.to_string())
+}
+
+fn read_trace(path: &str) -> Result<Vec<TraceRecord>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    parse_trace(&text).map_err(|err| format!("{}: {}", path, err))
+}
+
+// Fails when the traces differ, after printing where
+fn trace_diff(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["--context"])?;
+    let (expected, actual) = match args.positional.as_slice() {
+        [expected, actual] => (expected, actual),
+        _ => return Err(USAGE.to_string()),
+    };
+    let context = args.number("--context", DEFAULT_DIFF_CONTEXT)?;
+    let left = read_trace(expected)?;
+    let right = read_trace(actual)?;
+    match diff_traces(&left, &right) {
+        None => {
+            println!("{} records match", left.len());
+            Ok(())
+        }
+        Some(diff) => {
+            println!("{}", diff.report(&left, &right, context));
+            Err(format!("{} and {} differ", expected, actual))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::dispatch;
+
+    fn error(args: &[&str]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let message = dispatch(&args).unwrap_err();
+        message.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[test]
+    fn test_bad_options() {
+        assert_eq!(
+            error(&["run", "rom.ch8", "--speed", "2"]),
+            "unknown option --speed"
+        );
+        assert_eq!(error(&["run", "rom.ch8", "--seed"]), "--seed needs a value");
+        assert_eq!(
+            error(&["bench", "rom.ch8", "--trace", "out"]),
+            "unknown option --trace"
+        );
+        assert!(error(&["frobnicate"]).starts_with("usage: "));
+    }
+
+    #[test]
+    fn test_run_errors() {
+        // RET with nothing to return to
+        let path = std::env::temp_dir().join("chip8_cli_fault.ch8");
+        std::fs::write(&path, [0x00, 0xEE]).unwrap();
+        let rom = path.to_string_lossy();
+        assert_eq!(
+            error(&["run", &rom, "--cycles", "10"]),
+            "stack underflow: RET with empty stack at 0x200"
+        );
+        assert_eq!(
+            error(&["run", &rom, "--rate", "0"]),
+            "--rate must be at least 1"
+        );
+        assert_eq!(error(&["run", &rom, "--rate", "fast"]), "bad --rate fast");
+    }
+}
@@ -1,2 +1,3 @@
 mod app;
+pub mod cli;
 pub use app::Chip8App;
@@ -1,24 +1,10 @@
-// Crate-Specific libraries
-use chip8::Chip8;
-use chip8_emu::Chip8App;
-
-// Standard Libaries
-use std::io;
-
-// External Libraries
-
-fn cpu_main() {
-    // Set up render system and register input callbacks
-    // Initialize the Chip8 system and load the game into the memory
-    // Emulation Loop
-
-    // Emulate one cycle
-    // If DrawFlag is set, Update the Screen
-    // Store key press state ( Press and Release )
-    
-}
-
 fn main() {
+    // Subcommands run headless, see `chip8_emu::cli`
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(chip8_emu::cli::run(&args));
+    }
+
     // Log to stdout (if you run with `RUST_LOG=debug`)
     tracing_subscriber::fmt::init();
 